@@ -1,3 +1,4 @@
+#[derive(Clone)]
 pub struct Animation {
     // states are sprite sheet positions
     pub(crate) states: Vec<[f32; 4]>,
@@ -0,0 +1,367 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+
+use crate::atlas::{Atlas, AtlasLayout};
+use crate::gpus::{checkerboard_image, TextureOptions, WGPU};
+
+// How a texture gets sampled. Each texture picks one; the samplers themselves are shared.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerConfig {
+    pub filter: wgpu::FilterMode,
+    // How to pick between mip levels, for textures loaded with TextureOptions::mipmaps
+    pub mipmap_filter: wgpu::FilterMode,
+    pub address_mode: wgpu::AddressMode,
+}
+
+#[allow(dead_code)]
+impl SamplerConfig {
+    // Crisp, blocky pixels when scaled up, and nothing wraps around from the other side of the texture
+    pub const PIXEL_ART: SamplerConfig = SamplerConfig {
+        filter: wgpu::FilterMode::Nearest,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        address_mode: wgpu::AddressMode::ClampToEdge,
+    };
+    // Smooth scaling, for art that isn't pixel art
+    pub const SMOOTH: SamplerConfig = SamplerConfig {
+        filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        address_mode: wgpu::AddressMode::ClampToEdge,
+    };
+
+    // The same filtering, but tiling the texture outside 0..1 instead of clamping
+    pub fn repeating(self) -> SamplerConfig {
+        SamplerConfig {
+            address_mode: wgpu::AddressMode::Repeat,
+            ..self
+        }
+    }
+
+    // The same filtering, clamping at the edges again
+    pub fn clamped(self) -> SamplerConfig {
+        SamplerConfig {
+            address_mode: wgpu::AddressMode::ClampToEdge,
+            ..self
+        }
+    }
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        SamplerConfig::PIXEL_ART
+    }
+}
+
+// The GPU side of a texture. Only the GPU copy is kept around; the decoded image is dropped after uploading.
+struct LoadedTexture {
+    // Never read, but owning it here keeps the texture alive as long as its view
+    #[allow(dead_code)]
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    width: u32,
+    height: u32,
+    // Bind groups hold on to the view, so they're cached here instead of in the manager:
+    // that way they go away together with the texture.
+    bind_groups: RefCell<HashMap<SamplerConfig, Rc<wgpu::BindGroup>>>,
+}
+
+pub struct TextureAsset {
+    name: PathBuf,
+    // Swapped out when the file is hot reloaded
+    loaded: RefCell<Rc<LoadedTexture>>,
+    // How it was uploaded; reloads use the same options
+    options: TextureOptions,
+    sampler: Cell<SamplerConfig>,
+    // Goes up by one on every reload or sampler change, so users of the texture can tell when to rebuild
+    // their bind groups
+    generation: Cell<u32>,
+}
+
+// A cheap, clonable reference to a texture. The texture stays on the GPU as long as any handle to it exists.
+#[derive(Clone)]
+pub struct TextureHandle(Rc<TextureAsset>);
+
+#[allow(dead_code)]
+impl TextureHandle {
+    pub fn name(&self) -> &Path {
+        &self.0.name
+    }
+    pub fn size(&self) -> (u32, u32) {
+        let loaded = self.0.loaded.borrow();
+        (loaded.width, loaded.height)
+    }
+    pub fn generation(&self) -> u32 {
+        self.0.generation.get()
+    }
+    pub fn options(&self) -> TextureOptions {
+        self.0.options
+    }
+    pub fn sampler(&self) -> SamplerConfig {
+        self.0.sampler.get()
+    }
+    // Change how this texture is sampled, everywhere it's used
+    pub fn set_sampler(&self, sampler: SamplerConfig) {
+        if self.0.sampler.replace(sampler) != sampler {
+            self.0.generation.set(self.0.generation.get() + 1);
+        }
+    }
+}
+
+// A folder of loose images packed into one texture, plus where each one ended up
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct AtlasHandle {
+    pub texture: TextureHandle,
+    pub layout: Rc<AtlasLayout>,
+}
+
+impl PartialEq for TextureHandle {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+struct CachedAtlas {
+    texture: Weak<TextureAsset>,
+    layout: Rc<AtlasLayout>,
+    padding: u32,
+}
+
+// Loads textures by path, handing out the same handle when a path is loaded twice.
+// It also owns what every sprite texture shares: the bind group layout and one sampler per SamplerConfig.
+pub struct AssetManager {
+    textures: HashMap<PathBuf, Weak<TextureAsset>>,
+    // Packed folders: the texture, where everything went in it, and the padding they were packed with so
+    // they can be repacked the same way
+    atlases: HashMap<PathBuf, CachedAtlas>,
+    // Made the first time some texture asks for them
+    samplers: RefCell<HashMap<SamplerConfig, wgpu::Sampler>>,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+}
+
+#[allow(dead_code)]
+impl AssetManager {
+    pub(crate) fn new(gpu: &WGPU) -> Self {
+        let texture_bind_group_layout =
+            gpu.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("texture"),
+                    // It needs the first entry for the texture and the second for the sampler.
+                    // This is like defining a type signature.
+                    entries: &[
+                        // The texture binding
+                        wgpu::BindGroupLayoutEntry {
+                            // This matches the binding in the shader
+                            binding: 0,
+                            // Only available in the fragment shader
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            // It's a texture binding
+                            ty: wgpu::BindingType::Texture {
+                                // We can use it with float samplers
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                // It's being used as a 2D texture
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                // This is not a multisampled texture
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        // The sampler binding
+                        wgpu::BindGroupLayoutEntry {
+                            // This matches the binding in the shader
+                            binding: 1,
+                            // Only available in the fragment shader
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            // It's a sampler
+                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                            // No count
+                            count: None,
+                        },
+                    ],
+                });
+        Self {
+            textures: HashMap::new(),
+            atlases: HashMap::new(),
+            samplers: RefCell::new(HashMap::new()),
+            texture_bind_group_layout,
+        }
+    }
+
+    pub fn texture_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.texture_bind_group_layout
+    }
+
+    // Load a texture, or get another handle to it if it's already loaded.
+    // A missing file gives a checkerboard texture (and an error in the log) rather than failing.
+    pub(crate) async fn load_texture(&mut self, gpu: &WGPU, path: impl AsRef<Path>) -> TextureHandle {
+        self.load_texture_with(gpu, path, TextureOptions::DEFAULT).await
+    }
+
+    // The same, choosing the format, mipmaps and so on. A texture that's already loaded keeps the
+    // options it was first loaded with.
+    pub(crate) async fn load_texture_with(&mut self, gpu: &WGPU, path: impl AsRef<Path>, options: TextureOptions) -> TextureHandle {
+        let path = path.as_ref();
+        // Different spellings of the same file should still share a texture
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if let Some(asset) = self.textures.get(&key).and_then(Weak::upgrade) {
+            if asset.options != options {
+                log::warn!("{} is already loaded with {:?}, not {:?}", path.display(), asset.options, options);
+            }
+            return TextureHandle(asset);
+        }
+        let label = path.to_string_lossy();
+        let (texture, img) = WGPU::load_texture_or_checkerboard(path, Some(&label), options, &gpu.device, &gpu.queue).await;
+        let handle = Self::make_handle(path.to_path_buf(), texture, img.dimensions(), options);
+        self.collect_garbage();
+        self.textures.insert(key, Rc::downgrade(&handle.0));
+        handle
+    }
+
+    // Load `path` again if it's in use, updating every handle to it. Returns whether it was in use.
+    // If the new file is broken the old texture stays, so a half-saved PNG doesn't turn into a checkerboard.
+    pub(crate) async fn reload_texture(&mut self, gpu: &WGPU, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let Some(asset) = self.textures.get(&key).and_then(Weak::upgrade) else {
+            return false;
+        };
+        let label = path.to_string_lossy();
+        match WGPU::load_texture(path, Some(&label), asset.options, &gpu.device, &gpu.queue).await {
+            Ok((texture, img)) => {
+                *asset.loaded.borrow_mut() = Rc::new(Self::make_loaded(texture, img.dimensions()));
+                asset.generation.set(asset.generation.get() + 1);
+                log::info!("Reloaded {}", path.display());
+            }
+            Err(e) => log::error!("{}, keeping the old texture", e),
+        }
+        true
+    }
+
+    // Pack every PNG in `dir` into one texture (see Atlas::pack_directory). Loading the same folder
+    // twice gives the same texture and layout without packing it again. A missing folder gives a
+    // checkerboard with no frames.
+    pub(crate) fn load_atlas(&mut self, gpu: &WGPU, dir: impl AsRef<Path>, padding: u32) -> AtlasHandle {
+        let dir = dir.as_ref();
+        let key = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        if let Some(cached) = self.atlases.get(&key) {
+            if let Some(asset) = cached.texture.upgrade() {
+                return AtlasHandle { texture: TextureHandle(asset), layout: cached.layout.clone() };
+            }
+        }
+        let (img, layout) = match Atlas::pack_directory(dir, padding) {
+            Ok(atlas) => (atlas.image, atlas.layout),
+            Err(e) => {
+                log::error!("Couldn't pack {}: {}, using a placeholder texture", dir.display(), e);
+                let img = checkerboard_image();
+                let layout = AtlasLayout { width: img.width(), height: img.height(), frames: Default::default() };
+                (img, layout)
+            }
+        };
+        let layout = Rc::new(layout);
+        let label = dir.to_string_lossy();
+        let texture = WGPU::texture_from_image(&img, Some(&label), TextureOptions::DEFAULT, &gpu.device, &gpu.queue);
+        let handle = Self::make_handle(dir.to_path_buf(), texture, img.dimensions(), TextureOptions::DEFAULT);
+        self.collect_garbage();
+        self.atlases.insert(key, CachedAtlas { texture: Rc::downgrade(&handle.0), layout: layout.clone(), padding });
+        AtlasHandle { texture: handle, layout }
+    }
+
+    // Repack the atlas `path` is part of, if there's one in use. Returns whether there was.
+    // Frames keep their place as long as no image changed size, so existing sheet regions stay valid;
+    // later loads of the folder get the new layout.
+    pub(crate) fn reload_atlas_containing(&mut self, gpu: &WGPU, path: impl AsRef<Path>) -> bool {
+        let Some(dir) = path.as_ref().parent() else { return false };
+        let key = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        let Some(cached) = self.atlases.get_mut(&key) else { return false };
+        let Some(asset) = cached.texture.upgrade() else { return false };
+        match Atlas::pack_directory(dir, cached.padding) {
+            Ok(atlas) => {
+                cached.layout = Rc::new(atlas.layout);
+                let label = dir.to_string_lossy();
+                let texture = WGPU::texture_from_image(&atlas.image, Some(&label), asset.options, &gpu.device, &gpu.queue);
+                *asset.loaded.borrow_mut() = Rc::new(Self::make_loaded(texture, atlas.image.dimensions()));
+                asset.generation.set(asset.generation.get() + 1);
+                log::info!("Repacked {}", dir.display());
+            }
+            Err(e) => log::error!("Couldn't repack {}: {}, keeping the old atlas", dir.display(), e),
+        }
+        true
+    }
+
+    // A texture made from an image in memory, e.g. a generated one. These aren't shared by name.
+    pub(crate) fn texture_from_image(&mut self, gpu: &WGPU, name: &str, img: &image::RgbaImage, options: TextureOptions) -> TextureHandle {
+        let texture = WGPU::texture_from_image(img, Some(name), options, &gpu.device, &gpu.queue);
+        Self::make_handle(PathBuf::from(name), texture, img.dimensions(), options)
+    }
+
+    fn make_handle(name: PathBuf, texture: wgpu::Texture, size: (u32, u32), options: TextureOptions) -> TextureHandle {
+        TextureHandle(Rc::new(TextureAsset {
+            name,
+            loaded: RefCell::new(Rc::new(Self::make_loaded(texture, size))),
+            options,
+            sampler: Cell::new(SamplerConfig::default()),
+            generation: Cell::new(0),
+        }))
+    }
+
+    fn make_loaded(texture: wgpu::Texture, (width, height): (u32, u32)) -> LoadedTexture {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        LoadedTexture {
+            texture,
+            view,
+            width,
+            height,
+            bind_groups: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // The texture and the shared sampler it asked for, ready to bind. Made once per texture and sampler.
+    pub(crate) fn bind_group(&self, gpu: &WGPU, handle: &TextureHandle) -> Rc<wgpu::BindGroup> {
+        let config = handle.sampler();
+        let loaded = handle.0.loaded.borrow().clone();
+        let mut bind_groups = loaded.bind_groups.borrow_mut();
+        bind_groups
+            .entry(config)
+            .or_insert_with(|| {
+                let mut samplers = self.samplers.borrow_mut();
+                let sampler = samplers.entry(config).or_insert_with(|| {
+                    gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+                        address_mode_u: config.address_mode,
+                        address_mode_v: config.address_mode,
+                        mag_filter: config.filter,
+                        min_filter: config.filter,
+                        mipmap_filter: config.mipmap_filter,
+                        ..Default::default()
+                    })
+                });
+                Rc::new(gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &self.texture_bind_group_layout,
+                    entries: &[
+                        // One for the texture, one for the sampler
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&loaded.view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(sampler),
+                        },
+                    ],
+                }))
+            })
+            .clone()
+    }
+
+    // Forget textures nobody holds a handle to anymore
+    pub fn collect_garbage(&mut self) {
+        self.textures.retain(|_, asset| asset.strong_count() > 0);
+        self.atlases.retain(|_, cached| cached.texture.strong_count() > 0);
+    }
+
+    // How many loaded-from-disk textures are still alive
+    pub fn loaded_count(&self) -> usize {
+        self.textures.values().filter(|asset| asset.strong_count() > 0).count()
+    }
+}
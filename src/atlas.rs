@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use crate::animation::Animation;
+
+// Where one packed image ended up, in pixels (not counting its padding)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AtlasFrame {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// Where everything is in a packed atlas. Frames are named after the file they came from,
+// without the extension ("sq1", "acorn", ...). This is also what gets saved next to the image.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AtlasLayout {
+    pub width: u32,
+    pub height: u32,
+    pub frames: BTreeMap<String, AtlasFrame>,
+}
+
+// Lots of small images packed into one texture, so they can all be drawn by one sprite group
+pub struct Atlas {
+    pub image: RgbaImage,
+    pub layout: AtlasLayout,
+}
+
+#[allow(dead_code)]
+impl Atlas {
+    // Pack every PNG in `dir` (not recursively). `padding` pixels go around each image, filled by
+    // repeating its edge pixels so filtering and rounding at frame edges never pick up a neighbour.
+    pub fn pack_directory(dir: impl AsRef<Path>, padding: u32) -> image::ImageResult<Atlas> {
+        let mut images = vec![];
+        for entry in std::fs::read_dir(dir.as_ref())? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("png") {
+                continue;
+            }
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            images.push((name, image::open(&path)?.to_rgba8()));
+        }
+        Ok(Self::pack(images, padding))
+    }
+
+    // Shelf packing: tallest images first, left to right in rows, starting a new row when one's full.
+    // Not optimal, but good for a folder of sprites and the same input always gives the same layout.
+    pub fn pack(mut images: Vec<(String, RgbaImage)>, padding: u32) -> Atlas {
+        images.retain(|(_, img)| img.width() > 0 && img.height() > 0);
+        // Sort by name first so the order in the folder doesn't matter, then (stably) by height
+        images.sort_by(|a, b| a.0.cmp(&b.0));
+        images.sort_by(|a, b| b.1.height().cmp(&a.1.height()));
+
+        let padded = |img: &RgbaImage| (img.width() + 2 * padding, img.height() + 2 * padding);
+        let area: u32 = images.iter().map(|(_, img)| padded(img).0 * padded(img).1).sum();
+        let widest = images.iter().map(|(_, img)| padded(img).0).max().unwrap_or(1);
+        // Aim for a roughly square texture, with power-of-two width for friendliness
+        let width = ((area as f32).sqrt().ceil() as u32).max(widest).next_power_of_two();
+
+        let mut positions = Vec::with_capacity(images.len());
+        let (mut x, mut y, mut row_height) = (0, 0, 0);
+        for (_, img) in &images {
+            let (w, h) = padded(img);
+            if x + w > width {
+                x = 0;
+                y += row_height;
+                row_height = 0;
+            }
+            positions.push((x, y));
+            x += w;
+            row_height = row_height.max(h);
+        }
+        let height = (y + row_height).max(1);
+
+        let mut atlas = RgbaImage::new(width, height);
+        let mut frames = BTreeMap::new();
+        for ((name, img), (x, y)) in images.iter().zip(positions) {
+            let frame = AtlasFrame {
+                x: x + padding,
+                y: y + padding,
+                width: img.width(),
+                height: img.height(),
+            };
+            // Fill the frame and its padding, clamping to the image's edge pixels in the padding
+            for py in 0..img.height() + 2 * padding {
+                for px in 0..img.width() + 2 * padding {
+                    let sx = px.saturating_sub(padding).min(img.width() - 1);
+                    let sy = py.saturating_sub(padding).min(img.height() - 1);
+                    atlas.put_pixel(x + px, y + py, *img.get_pixel(sx, sy));
+                }
+            }
+            frames.insert(name.clone(), frame);
+        }
+        Atlas {
+            layout: AtlasLayout { width, height, frames },
+            image: atlas,
+        }
+    }
+
+    // Write the packed image and a TOML file with the frame rectangles, for tools or for
+    // shipping pre-packed atlases
+    pub fn save(&self, image_path: impl AsRef<Path>, layout_path: impl AsRef<Path>) -> std::io::Result<()> {
+        self.image
+            .save(image_path)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        let text = toml::to_string_pretty(&self.layout)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(layout_path, text)
+    }
+}
+
+#[allow(dead_code)]
+impl AtlasLayout {
+    // A frame's sheet region in UVs, ready for GPUSprite::sheet_region or an Animation state
+    pub fn uv(&self, name: &str) -> Option<[f32; 4]> {
+        let frame = self.frames.get(name)?;
+        let (w, h) = (self.width as f32, self.height as f32);
+        Some([
+            frame.x as f32 / w,
+            frame.y as f32 / h,
+            frame.width as f32 / w,
+            frame.height as f32 / h,
+        ])
+    }
+
+    // The frames named `prefix` followed by a number, in number order: "sq" gives sq1, sq2, ... sq5.
+    // This is how loose frames become an animation.
+    pub fn numbered_frames(&self, prefix: &str) -> Vec<[f32; 4]> {
+        let mut numbered: Vec<(u32, &str)> = self
+            .frames
+            .keys()
+            .filter_map(|name| {
+                let number = name.strip_prefix(prefix)?.parse().ok()?;
+                Some((number, name.as_str()))
+            })
+            .collect();
+        numbered.sort();
+        numbered.into_iter().filter_map(|(_, name)| self.uv(name)).collect()
+    }
+
+    // A looping animation through `numbered_frames(prefix)`, advancing every `rate` frames
+    pub fn animation(&self, prefix: &str, rate: i32) -> Option<Animation> {
+        let states = self.numbered_frames(prefix);
+        let sprite_width = states.first()?[2];
+        Some(Animation {
+            states,
+            frame_counter: 0,
+            rate,
+            state_number: 0,
+            is_facing_left: false,
+            sprite_width,
+            is_looping: true,
+            is_done: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, value: u8) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, image::Rgba([value, value, value, 255]))
+    }
+
+    #[test]
+    fn frames_do_not_overlap_and_keep_their_pixels() {
+        let images = vec![
+            ("a".to_string(), solid(10, 4, 10)),
+            ("b".to_string(), solid(3, 12, 20)),
+            ("c".to_string(), solid(7, 7, 30)),
+            ("d".to_string(), solid(1, 1, 40)),
+        ];
+        let atlas = Atlas::pack(images, 2);
+        let frames: Vec<_> = atlas.layout.frames.values().collect();
+        for (i, a) in frames.iter().enumerate() {
+            assert!(a.x + a.width <= atlas.image.width() && a.y + a.height <= atlas.image.height());
+            for b in &frames[i + 1..] {
+                // Including the padding, which is 2 pixels on each side
+                let apart = a.x + a.width + 4 <= b.x
+                    || b.x + b.width + 4 <= a.x
+                    || a.y + a.height + 4 <= b.y
+                    || b.y + b.height + 4 <= a.y;
+                assert!(apart, "{:?} overlaps {:?}", a, b);
+            }
+        }
+        let c = atlas.layout.frames["c"];
+        assert_eq!(atlas.image.get_pixel(c.x, c.y).0[0], 30);
+        // The padding repeats the edge pixels
+        assert_eq!(atlas.image.get_pixel(c.x - 1, c.y - 1).0[0], 30);
+        assert_eq!(atlas.image.get_pixel(c.x + c.width + 1, c.y).0[0], 30);
+    }
+
+    #[test]
+    fn numbered_frames_are_in_number_order() {
+        let images = (1..=12).map(|i| (format!("sq{}", i), solid(4, 4, i as u8))).chain([("king".to_string(), solid(4, 4, 0))]).collect();
+        let atlas = Atlas::pack(images, 1);
+        let frames = atlas.layout.numbered_frames("sq");
+        assert_eq!(frames.len(), 12);
+        for (i, uv) in frames.iter().enumerate() {
+            assert_eq!(Some(*uv), atlas.layout.uv(&format!("sq{}", i + 1)));
+        }
+        assert_eq!(atlas.layout.animation("sq", 8).unwrap().states.len(), 12);
+        assert!(atlas.layout.animation("missing", 8).is_none());
+    }
+
+    #[test]
+    fn packs_nut_war_content() {
+        let atlas = Atlas::pack_directory("nut_war_content", 2).unwrap();
+        for name in ["sq1", "sq2", "sq3", "sq4", "sq5", "acorn", "king", "squirrel"] {
+            assert!(atlas.layout.frames.contains_key(name), "missing {}", name);
+        }
+        assert_eq!(atlas.layout.numbered_frames("sq").len(), 5);
+        // The loose sq frames animate straight out of the packed texture, each one a copy of its file
+        let animation = atlas.layout.animation("sq", 6).unwrap();
+        for i in 1..=5 {
+            let frame = atlas.layout.frames[&format!("sq{}", i)];
+            assert_eq!(Some(animation.states[i - 1]), atlas.layout.uv(&format!("sq{}", i)));
+            let loose = image::open(format!("nut_war_content/sq{}.png", i)).unwrap().to_rgba8();
+            assert_eq!((frame.width, frame.height), loose.dimensions());
+            for (x, y, pixel) in loose.enumerate_pixels() {
+                assert_eq!(atlas.image.get_pixel(frame.x + x, frame.y + y), pixel);
+            }
+        }
+    }
+}
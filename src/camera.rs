@@ -0,0 +1,265 @@
+use rand::Rng;
+
+// What the shader sees: the bottom left corner of the view in world space, and how much world fits on screen.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+pub struct GPUCamera {
+    pub screen_pos: [f32; 2],
+    pub screen_size: [f32; 2],
+}
+
+impl GPUCamera {
+    // Where a world position ends up on a `canvas_size` canvas, in pixels from the top left like the
+    // text renderer and the mouse use
+    pub fn world_to_canvas(&self, world: [f32; 2], canvas_size: [f32; 2]) -> [f32; 2] {
+        [
+            (world[0] - self.screen_pos[0]) / self.screen_size[0] * canvas_size[0],
+            (1.0 - (world[1] - self.screen_pos[1]) / self.screen_size[1]) * canvas_size[1],
+        ]
+    }
+
+    // The other way around, e.g. for finding what's under the mouse
+    pub fn canvas_to_world(&self, canvas: [f32; 2], canvas_size: [f32; 2]) -> [f32; 2] {
+        [
+            self.screen_pos[0] + canvas[0] / canvas_size[0] * self.screen_size[0],
+            self.screen_pos[1] + (1.0 - canvas[1] / canvas_size[1]) * self.screen_size[1],
+        ]
+    }
+}
+
+// The part of the world the camera is allowed to look at
+#[derive(Clone, Copy, Debug)]
+pub struct WorldBounds {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+// Moves the view around the world: follows a target, but only once it leaves the dead zone,
+// eases towards it instead of snapping, never shows anything outside the world bounds, and can shake.
+pub struct CameraController {
+    // bottom left corner of the view in world space, before shake
+    pub pos: [f32; 2],
+    pub view_size: [f32; 2],
+    // half-size of the box around the middle of the screen the target can move in without the camera following
+    pub dead_zone: [f32; 2],
+    // how quickly the camera catches up, roughly "fraction per second"; 0 means snap straight to the target
+    pub smoothing: f32,
+    pub bounds: Option<WorldBounds>,
+    target: [f32; 2],
+    shake_intensity: f32,
+    shake_time_left: f32,
+    shake_duration: f32,
+    shake_offset: [f32; 2],
+}
+
+impl CameraController {
+    pub fn new(view_size: [f32; 2]) -> Self {
+        Self {
+            pos: [0.0, 0.0],
+            view_size,
+            dead_zone: [0.0, 0.0],
+            smoothing: 0.0,
+            bounds: None,
+            target: [view_size[0] / 2.0, view_size[1] / 2.0],
+            shake_intensity: 0.0,
+            shake_time_left: 0.0,
+            shake_duration: 0.0,
+            shake_offset: [0.0, 0.0],
+        }
+    }
+
+    // Set the world-space point the camera should keep on screen
+    pub fn follow(&mut self, target: [f32; 2]) {
+        self.target = target;
+    }
+
+    // Jump straight to the target, e.g. when a round starts
+    pub fn snap_to_target(&mut self) {
+        self.pos = self.desired_pos();
+        self.clamp_to_bounds();
+    }
+
+    // Shake the view by up to `intensity` world units, fading out over `duration` seconds.
+    // A weaker shake never cuts a stronger one short.
+    pub fn shake(&mut self, intensity: f32, duration: f32) {
+        if intensity >= self.current_shake_strength() {
+            self.shake_intensity = intensity;
+            self.shake_duration = duration.max(f32::EPSILON);
+            self.shake_time_left = duration;
+        }
+    }
+
+    fn current_shake_strength(&self) -> f32 {
+        if self.shake_time_left <= 0.0 {
+            0.0
+        } else {
+            self.shake_intensity * (self.shake_time_left / self.shake_duration)
+        }
+    }
+
+    // Where the camera would like to be so the target sits inside the dead zone
+    fn desired_pos(&self) -> [f32; 2] {
+        let mut desired = self.pos;
+        for axis in 0..2 {
+            let center = self.pos[axis] + self.view_size[axis] / 2.0;
+            let offset = self.target[axis] - center;
+            if offset > self.dead_zone[axis] {
+                desired[axis] += offset - self.dead_zone[axis];
+            } else if offset < -self.dead_zone[axis] {
+                desired[axis] += offset + self.dead_zone[axis];
+            }
+        }
+        desired
+    }
+
+    fn clamp_to_bounds(&mut self) {
+        self.pos = self.clamped(self.pos);
+    }
+
+    // `pos` moved just far enough that the view stays inside the world bounds
+    fn clamped(&self, mut pos: [f32; 2]) -> [f32; 2] {
+        if let Some(bounds) = self.bounds {
+            for axis in 0..2 {
+                let max = (bounds.max[axis] - self.view_size[axis]).max(bounds.min[axis]);
+                pos[axis] = pos[axis].clamp(bounds.min[axis], max);
+            }
+        }
+        pos
+    }
+
+    // Call once per frame with the time since the last frame, in seconds
+    pub fn update(&mut self, dt: f32) {
+        let desired = self.desired_pos();
+        // Exponential smoothing, so the feel doesn't depend on the frame rate
+        let t = if self.smoothing <= 0.0 {
+            1.0
+        } else {
+            1.0 - (-self.smoothing * dt).exp()
+        };
+        for axis in 0..2 {
+            self.pos[axis] += (desired[axis] - self.pos[axis]) * t;
+        }
+        self.clamp_to_bounds();
+
+        let strength = self.current_shake_strength();
+        if strength > 0.0 {
+            let mut rng = rand::thread_rng();
+            self.shake_offset = [
+                rng.gen_range(-1.0..=1.0) * strength,
+                rng.gen_range(-1.0..=1.0) * strength,
+            ];
+            self.shake_time_left -= dt;
+        } else {
+            self.shake_offset = [0.0, 0.0];
+        }
+    }
+
+    pub fn gpu_camera(&self) -> GPUCamera {
+        GPUCamera {
+            // Shaking against the edge of the world only moves the view inwards
+            screen_pos: self.clamped([self.pos[0] + self.shake_offset[0], self.pos[1] + self.shake_offset[1]]),
+            screen_size: self.view_size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> WorldBounds {
+        WorldBounds { min: [0.0, 0.0], max: [3072.0, 768.0] }
+    }
+
+    #[test]
+    fn canvas_and_world_positions_round_trip() {
+        let camera = GPUCamera { screen_pos: [100.0, 50.0], screen_size: [1024.0, 768.0] };
+        // The bottom left of the view is the bottom left of the canvas
+        assert_eq!(camera.world_to_canvas([100.0, 50.0], [1024.0, 768.0]), [0.0, 768.0]);
+        // On a canvas with a different aspect than the view, too
+        let canvas_size = [333.0, 500.0];
+        let canvas = camera.world_to_canvas([612.0, 434.0], canvas_size);
+        let world = camera.canvas_to_world(canvas, canvas_size);
+        assert!((world[0] - 612.0).abs() < 1e-3 && (world[1] - 434.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn target_inside_the_dead_zone_doesnt_move_the_camera() {
+        let mut camera = CameraController::new([1024.0, 768.0]);
+        camera.dead_zone = [100.0, 100.0];
+        camera.follow([512.0 + 90.0, 384.0 - 90.0]);
+        camera.update(1.0 / 60.0);
+        assert_eq!(camera.pos, [0.0, 0.0]);
+        // Leaving it drags the camera just far enough to keep the target on its edge
+        camera.follow([512.0 + 150.0, 384.0]);
+        camera.update(1.0 / 60.0);
+        assert_eq!(camera.pos, [50.0, 0.0]);
+    }
+
+    #[test]
+    fn smoothing_eases_towards_the_target() {
+        let mut camera = CameraController::new([1024.0, 768.0]);
+        camera.smoothing = 5.0;
+        camera.follow([1512.0, 384.0]);
+        camera.update(0.1);
+        assert!(camera.pos[0] > 0.0 && camera.pos[0] < 1000.0);
+        for _ in 0..600 {
+            camera.update(1.0 / 60.0);
+        }
+        assert!((camera.pos[0] - 1000.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn clamped_at_the_edges_of_the_world() {
+        let mut camera = CameraController::new([1024.0, 768.0]);
+        camera.bounds = Some(world());
+        camera.follow([-500.0, 384.0]);
+        camera.snap_to_target();
+        assert_eq!(camera.pos, [0.0, 0.0]);
+        camera.follow([10_000.0, 2000.0]);
+        camera.snap_to_target();
+        assert_eq!(camera.pos, [3072.0 - 1024.0, 0.0]);
+        // Updating clamps too
+        camera.follow([-500.0, -500.0]);
+        camera.update(1.0 / 60.0);
+        assert_eq!(camera.pos, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn worlds_smaller_than_the_view_stick_to_their_corner() {
+        let mut camera = CameraController::new([1024.0, 768.0]);
+        camera.bounds = Some(WorldBounds { min: [0.0, 0.0], max: [600.0, 400.0] });
+        camera.follow([2000.0, 2000.0]);
+        camera.snap_to_target();
+        assert_eq!(camera.pos, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn shake_stays_within_its_strength_and_wears_off() {
+        let mut camera = CameraController::new([1024.0, 768.0]);
+        camera.shake(8.0, 0.5);
+        // A weaker one doesn't replace it
+        camera.shake(2.0, 2.0);
+        for _ in 0..10 {
+            camera.update(1.0 / 60.0);
+            let shaken = camera.gpu_camera().screen_pos;
+            assert!(shaken[0].abs() <= 8.0 && shaken[1].abs() <= 8.0);
+        }
+        for _ in 0..30 {
+            camera.update(1.0 / 60.0);
+        }
+        assert_eq!(camera.gpu_camera().screen_pos, camera.pos);
+        assert_eq!(camera.gpu_camera().screen_size, [1024.0, 768.0]);
+
+        // In the corner of the world, shaking never shows past its edges
+        camera.bounds = Some(world());
+        camera.pos = [0.0, 0.0];
+        camera.shake(8.0, 0.5);
+        for _ in 0..20 {
+            camera.update(1.0 / 60.0);
+            let shaken = camera.gpu_camera().screen_pos;
+            assert!(shaken[0] >= 0.0 && shaken[1] >= 0.0);
+            assert!(shaken[0] <= 8.0 && shaken[1] <= 8.0);
+        }
+    }
+}
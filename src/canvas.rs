@@ -0,0 +1,306 @@
+use std::borrow::Cow;
+
+use crate::gpus::WGPU;
+use crate::input::MousePos;
+use crate::mipmaps::{self, MipChain};
+
+// The size the game is designed for. Everything draws into a texture this big,
+// which is then scaled up or down to fit the window.
+pub const LOGICAL_WIDTH: u32 = 1024;
+pub const LOGICAL_HEIGHT: u32 = 768;
+
+// Where the canvas lands inside the window, in physical pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    // Fit a logical-size canvas into the window, keeping its aspect ratio and centering it.
+    // With `integer` set the scale is rounded down to a whole number (at least 1) so pixel art stays sharp.
+    pub fn letterbox(window_width: u32, window_height: u32, logical_width: u32, logical_height: u32, integer: bool) -> Viewport {
+        let (ww, wh) = (window_width.max(1) as f32, window_height.max(1) as f32);
+        let (lw, lh) = (logical_width as f32, logical_height as f32);
+        let mut scale = (ww / lw).min(wh / lh);
+        if integer && scale >= 1.0 {
+            scale = scale.floor();
+        }
+        let width = lw * scale;
+        let height = lh * scale;
+        Viewport {
+            x: ((ww - width) / 2.0).floor(),
+            y: ((wh - height) / 2.0).floor(),
+            width,
+            height,
+        }
+    }
+
+    // Turn a position in the window into a position on the logical canvas (origin still at the top left).
+    // Positions in the black bars end up outside 0..logical size.
+    pub fn to_logical(&self, pos: MousePos<f64>, logical_width: u32, logical_height: u32) -> MousePos<f64> {
+        MousePos {
+            x: (pos.x - self.x as f64) * logical_width as f64 / self.width as f64,
+            y: (pos.y - self.y as f64) * logical_height as f64 / self.height as f64,
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub struct VirtualCanvas {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    // COPY_SRC too, so screenshots can be read back from it
+    pub(crate) texture: wgpu::Texture,
+    // Draw the game into this view instead of the swapchain
+    pub(crate) view: wgpu::TextureView,
+    pipeline: wgpu::RenderPipeline,
+    // Kept so the pipeline can be rebuilt when the shader changes
+    pipeline_layout: wgpu::PipelineLayout,
+    // One bind group per scaling mode: nearest for integer scaling, linear otherwise
+    bind_group_nearest: wgpu::BindGroup,
+    bind_group_linear: wgpu::BindGroup,
+    pub(crate) integer_scaling: bool,
+    pub(crate) viewport: Viewport,
+    // The canvas has a full mip chain, refilled whenever it's drawn smaller than it is so the blit
+    // doesn't skip over pixels and alias
+    mips: MipChain,
+}
+
+impl VirtualCanvas {
+    pub(crate) fn new(gpu: &WGPU, width: u32, height: u32, integer_scaling: bool) -> Self {
+        let texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("virtual canvas"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: mipmaps::mip_level_count(width, height),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            // Same format as the swapchain so every pipeline can draw to either one
+            format: gpu.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        // Only the top level gets drawn into; the blit reads the whole chain
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            mip_level_count: Some(1),
+            ..Default::default()
+        });
+        let sampled_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let layout = gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = Self::create_pipeline(gpu, &pipeline_layout, include_str!("shader.wgsl"));
+
+        let make_bind_group = |filter: wgpu::FilterMode| {
+            let sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+                mag_filter: filter,
+                min_filter: filter,
+                mipmap_filter: filter,
+                ..Default::default()
+            });
+            gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&sampled_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&sampler),
+                    },
+                ],
+            })
+        };
+        let bind_group_nearest = make_bind_group(wgpu::FilterMode::Nearest);
+        let bind_group_linear = make_bind_group(wgpu::FilterMode::Linear);
+
+        let viewport = Viewport::letterbox(gpu.config.width, gpu.config.height, width, height, integer_scaling);
+        // The game draws everything opaque, so a plain average is right
+        let mips = MipChain::new(&gpu.device, &texture, true);
+        Self {
+            width,
+            height,
+            texture,
+            view,
+            pipeline,
+            pipeline_layout,
+            bind_group_nearest,
+            bind_group_linear,
+            integer_scaling,
+            viewport,
+            mips,
+        }
+    }
+
+    fn create_pipeline(gpu: &WGPU, pipeline_layout: &wgpu::PipelineLayout, source: &str) -> wgpu::RenderPipeline {
+        // The blit reuses the fullscreen background quad from shader.wgsl
+        let shader = gpu.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
+        });
+        gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("canvas blit"),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main_bg",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main_bg",
+                targets: &[Some(gpu.config.format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    // Like SpriteRenderer::reload_shader: keeps the old pipeline if the new source doesn't compile
+    pub(crate) fn reload_shader(&mut self, gpu: &WGPU, source: &str) -> Result<(), String> {
+        self.pipeline = gpu.validated(|| Self::create_pipeline(gpu, &self.pipeline_layout, source))?;
+        Ok(())
+    }
+
+    // Call this whenever the window changes size (or the scaling mode changes)
+    pub(crate) fn resize(&mut self, window_width: u32, window_height: u32) {
+        self.viewport = Viewport::letterbox(window_width, window_height, self.width, self.height, self.integer_scaling);
+    }
+
+    pub(crate) fn set_integer_scaling(&mut self, integer: bool, window_width: u32, window_height: u32) {
+        self.integer_scaling = integer;
+        self.resize(window_width, window_height);
+    }
+
+    pub(crate) fn to_logical(&self, pos: MousePos<f64>) -> MousePos<f64> {
+        self.viewport.to_logical(pos, self.width, self.height)
+    }
+
+    // Scale the canvas onto the window, clearing the leftover bars to black.
+    pub(crate) fn present(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView) {
+        // Scaled up or to size, the sampler never looks past the top level
+        if self.viewport.width < self.width as f32 || self.viewport.height < self.height as f32 {
+            self.mips.encode(encoder);
+        }
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("canvas blit"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        let vp = self.viewport;
+        rpass.set_viewport(vp.x, vp.y, vp.width, vp.height, 0.0, 1.0);
+        rpass.set_pipeline(&self.pipeline);
+        if self.integer_scaling {
+            rpass.set_bind_group(0, &self.bind_group_nearest, &[]);
+        } else {
+            rpass.set_bind_group(0, &self.bind_group_linear, &[]);
+        }
+        rpass.draw(0..6, 0..2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_aspect_fills_the_window() {
+        let vp = Viewport::letterbox(2048, 1536, LOGICAL_WIDTH, LOGICAL_HEIGHT, false);
+        assert_eq!(vp, Viewport { x: 0.0, y: 0.0, width: 2048.0, height: 1536.0 });
+    }
+
+    #[test]
+    fn wide_windows_get_bars_on_the_sides() {
+        let vp = Viewport::letterbox(1920, 1080, LOGICAL_WIDTH, LOGICAL_HEIGHT, false);
+        assert_eq!((vp.width, vp.height), (1440.0, 1080.0));
+        assert_eq!((vp.x, vp.y), (240.0, 0.0));
+    }
+
+    #[test]
+    fn tall_windows_get_bars_top_and_bottom() {
+        let vp = Viewport::letterbox(800, 1000, LOGICAL_WIDTH, LOGICAL_HEIGHT, false);
+        assert_eq!((vp.width, vp.height), (800.0, 600.0));
+        assert_eq!((vp.x, vp.y), (0.0, 200.0));
+    }
+
+    #[test]
+    fn odd_sizes_keep_the_aspect_and_land_on_whole_pixels() {
+        let vp = Viewport::letterbox(1001, 333, LOGICAL_WIDTH, LOGICAL_HEIGHT, false);
+        assert!((vp.width / vp.height - 4.0 / 3.0).abs() < 1e-4);
+        assert_eq!(vp.height, 333.0);
+        assert_eq!(vp.x, vp.x.floor());
+        assert!(vp.x + vp.width <= 1001.0);
+    }
+
+    #[test]
+    fn integer_scaling_rounds_down_but_not_below_shrinking() {
+        // 2.5x fits, but only 2x is sharp
+        let vp = Viewport::letterbox(2560, 1920, LOGICAL_WIDTH, LOGICAL_HEIGHT, true);
+        assert_eq!((vp.width, vp.height), (2048.0, 1536.0));
+        assert_eq!((vp.x, vp.y), (256.0, 192.0));
+        // Too small for even 1x: shrinks like the smooth mode rather than spilling out of the window
+        let vp = Viewport::letterbox(512, 384, LOGICAL_WIDTH, LOGICAL_HEIGHT, true);
+        assert_eq!((vp.width, vp.height), (512.0, 384.0));
+    }
+
+    #[test]
+    fn minimized_windows_dont_divide_by_zero() {
+        let vp = Viewport::letterbox(0, 0, LOGICAL_WIDTH, LOGICAL_HEIGHT, false);
+        assert!(vp.width > 0.0 && vp.height > 0.0);
+    }
+
+    #[test]
+    fn window_positions_map_onto_the_canvas() {
+        let vp = Viewport::letterbox(1920, 1080, LOGICAL_WIDTH, LOGICAL_HEIGHT, false);
+        let logical = |x, y| {
+            let pos = vp.to_logical(MousePos { x, y }, LOGICAL_WIDTH, LOGICAL_HEIGHT);
+            (pos.x, pos.y)
+        };
+        assert_eq!(logical(240.0, 0.0), (0.0, 0.0));
+        assert_eq!(logical(960.0, 540.0), (512.0, 384.0));
+        assert_eq!(logical(1680.0, 1080.0), (1024.0, 768.0));
+        // In the left bar, so off the canvas
+        assert!(logical(100.0, 540.0).0 < 0.0);
+    }
+}
@@ -0,0 +1,346 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use image::RgbaImage;
+
+use crate::gpus::{image_from_padded_rows, padded_bytes_per_row, WGPU};
+
+// Where screenshots and clips are written, relative to the working directory
+pub const CAPTURE_DIR: &str = "captures";
+// How much the clip recorder keeps around, and how often it grabs a frame
+const CLIP_SECONDS: f32 = 5.0;
+const CLIP_FPS: f32 = 10.0;
+// Clip frames are shrunk by this much to keep GIF encoding time down
+const CLIP_DOWNSCALE: u32 = 2;
+
+// Where a readback's map_async has got to, shared with its callback
+const MAP_WAITING: u8 = 0;
+const MAP_DONE: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+// The size and format of the canvas, which is what the raw bytes in a readback buffer need to be
+// turned back into an image
+#[derive(Clone, Copy, Debug)]
+struct FrameLayout {
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+}
+
+impl FrameLayout {
+    fn of(canvas: &wgpu::Texture) -> Self {
+        Self {
+            width: canvas.width(),
+            height: canvas.height(),
+            format: canvas.format(),
+        }
+    }
+
+    fn buffer_size(&self) -> u64 {
+        (padded_bytes_per_row(self.width) * self.height) as u64
+    }
+
+    fn to_image(&self, data: &[u8]) -> RgbaImage {
+        image_from_padded_rows(data, self.width, self.height, self.format)
+    }
+}
+
+// What a copy of the canvas is for
+enum ReadbackTarget {
+    // Screenshots are rare, so each gets a buffer of its own
+    Screenshot(wgpu::Buffer),
+    // An index into the clip ring
+    ClipFrame(usize),
+}
+
+// A copy of the canvas that's on its way back from the GPU
+struct Readback {
+    target: ReadbackTarget,
+    grabbed: Instant,
+    // Set by map_async's callback, see MAP_WAITING
+    status: Arc<AtomicU8>,
+    mapping: bool,
+}
+
+// One of the buffers clip frames go into. Once its frame has arrived it stays mapped, ready to be
+// read if a clip gets saved, until the ring comes back round to it.
+struct ClipSlot {
+    buffer: wgpu::Buffer,
+    // When the frame in it was grabbed, once it's arrived and mapped
+    frame: Option<Instant>,
+}
+
+// Grabs frames from the virtual canvas for screenshots and a rolling "last few seconds" clip.
+// Frames come back from the GPU without stalling the game, clip frames go round a fixed ring of
+// buffers, and turning frames into files happens on its own thread.
+pub struct Capture {
+    screenshot_requested: bool,
+    clip_requested: bool,
+    last_clip_frame: Option<Instant>,
+    pending: Vec<Readback>,
+    // Made on the first frame, once the canvas size is known: enough buffers for CLIP_SECONDS
+    clip_ring: Vec<ClipSlot>,
+    next_slot: usize,
+    layout: Option<FrameLayout>,
+}
+
+impl Capture {
+    pub fn new() -> Self {
+        Self {
+            screenshot_requested: false,
+            clip_requested: false,
+            last_clip_frame: None,
+            pending: vec![],
+            clip_ring: vec![],
+            next_slot: 0,
+            layout: None,
+        }
+    }
+
+    // Save the next frame as a PNG
+    pub fn request_screenshot(&mut self) {
+        self.screenshot_requested = true;
+    }
+
+    // Save the last CLIP_SECONDS as a GIF, once any frames still on the GPU have arrived
+    pub fn request_clip(&mut self) {
+        self.clip_requested = true;
+    }
+
+    // Call in RedrawRequested after drawing into the canvas, before submitting the encoder.
+    // `canvas` has to have COPY_SRC usage.
+    pub(crate) fn record(&mut self, gpu: &WGPU, encoder: &mut wgpu::CommandEncoder, canvas: &wgpu::Texture) {
+        let layout = FrameLayout::of(canvas);
+        self.layout = Some(layout);
+        if std::mem::take(&mut self.screenshot_requested) {
+            let buffer = create_readback_buffer(gpu, &layout);
+            copy_canvas(encoder, canvas, &buffer);
+            self.pending.push(Readback::new(ReadbackTarget::Screenshot(buffer)));
+        }
+        let now = Instant::now();
+        let due = self
+            .last_clip_frame
+            .map_or(true, |last| now.duration_since(last).as_secs_f32() >= 1.0 / CLIP_FPS);
+        if !due {
+            return;
+        }
+        if self.clip_ring.first().map_or(true, |slot| slot.buffer.size() != layout.buffer_size()) {
+            let slots = (CLIP_SECONDS * CLIP_FPS).ceil() as usize;
+            self.clip_ring = (0..slots)
+                .map(|_| ClipSlot {
+                    buffer: create_readback_buffer(gpu, &layout),
+                    frame: None,
+                })
+                .collect();
+            self.next_slot = 0;
+            self.pending.retain(|r| matches!(r.target, ReadbackTarget::Screenshot(_)));
+        }
+        let index = self.next_slot;
+        // If the GPU is a whole clip behind, skip a frame rather than reuse a buffer that's still busy
+        if self.pending.iter().any(|r| matches!(r.target, ReadbackTarget::ClipFrame(i) if i == index)) {
+            return;
+        }
+        self.last_clip_frame = Some(now);
+        let slot = &mut self.clip_ring[index];
+        if slot.frame.take().is_some() {
+            slot.buffer.unmap();
+        }
+        copy_canvas(encoder, canvas, &slot.buffer);
+        self.pending.push(Readback::new(ReadbackTarget::ClipFrame(index)));
+        self.next_slot = (index + 1) % self.clip_ring.len();
+    }
+
+    // Call once per frame after the encoder has been submitted: starts mapping new copies,
+    // picks up the ones that have arrived, and hands finished captures off to be saved.
+    pub(crate) fn update(&mut self, gpu: &WGPU) {
+        for readback in self.pending.iter_mut().filter(|r| !r.mapping) {
+            let status = readback.status.clone();
+            let buffer = match &readback.target {
+                ReadbackTarget::Screenshot(buffer) => buffer,
+                ReadbackTarget::ClipFrame(index) => &self.clip_ring[*index].buffer,
+            };
+            buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                status.store(if result.is_ok() { MAP_DONE } else { MAP_FAILED }, Ordering::Release);
+            });
+            readback.mapping = true;
+        }
+        gpu.device.poll(wgpu::Maintain::Poll);
+
+        let Some(layout) = self.layout else { return };
+        let mut still_pending = Vec::with_capacity(self.pending.len());
+        for readback in self.pending.drain(..) {
+            match (readback.status.load(Ordering::Acquire), readback.target) {
+                (MAP_WAITING, target) => still_pending.push(Readback { target, ..readback }),
+                (MAP_DONE, ReadbackTarget::Screenshot(buffer)) => {
+                    let data = buffer.slice(..).get_mapped_range().to_vec();
+                    save_screenshot(data, layout);
+                }
+                (MAP_DONE, ReadbackTarget::ClipFrame(index)) => self.clip_ring[index].frame = Some(readback.grabbed),
+                // Nothing's going to arrive, so stop waiting for it (and let clips get saved without it)
+                (_, ReadbackTarget::Screenshot(_)) => log::error!("Couldn't read a screenshot back from the GPU"),
+                (_, ReadbackTarget::ClipFrame(_)) => log::error!("Couldn't read a clip frame back from the GPU"),
+            }
+        }
+        self.pending = still_pending;
+
+        let clip_frames_pending = self.pending.iter().any(|r| matches!(r.target, ReadbackTarget::ClipFrame(_)));
+        if self.clip_requested && !clip_frames_pending {
+            self.clip_requested = false;
+            // Only what's recent enough, oldest first. The bytes are copied out so the buffers can be
+            // reused straight away; everything else happens on the saving thread.
+            let keep = Duration::from_secs_f32(CLIP_SECONDS);
+            let mut slots: Vec<&ClipSlot> = self
+                .clip_ring
+                .iter()
+                .filter(|slot| slot.frame.map_or(false, |grabbed| grabbed.elapsed() <= keep))
+                .collect();
+            slots.sort_by_key(|slot| slot.frame);
+            let frames = slots.iter().map(|slot| slot.buffer.slice(..).get_mapped_range().to_vec()).collect();
+            save_clip(frames, layout);
+        }
+    }
+}
+
+impl Readback {
+    fn new(target: ReadbackTarget) -> Self {
+        Self {
+            target,
+            grabbed: Instant::now(),
+            status: Arc::new(AtomicU8::new(MAP_WAITING)),
+            mapping: false,
+        }
+    }
+}
+
+fn create_readback_buffer(gpu: &WGPU, layout: &FrameLayout) -> wgpu::Buffer {
+    gpu.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("capture readback"),
+        size: layout.buffer_size(),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    })
+}
+
+fn copy_canvas(encoder: &mut wgpu::CommandEncoder, canvas: &wgpu::Texture, buffer: &wgpu::Buffer) {
+    let (width, height) = (canvas.width(), canvas.height());
+    encoder.copy_texture_to_buffer(
+        canvas.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row(width)),
+                rows_per_image: Some(height),
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+fn capture_path(prefix: &str, extension: &str) -> PathBuf {
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    PathBuf::from(CAPTURE_DIR).join(format!("{}-{}.{}", prefix, stamp, extension))
+}
+
+// Converting and encoding can take a while (especially GIFs), so it happens on its own thread
+fn save_screenshot(data: Vec<u8>, layout: FrameLayout) {
+    let path = capture_path("screenshot", "png");
+    std::thread::spawn(move || {
+        let result = std::fs::create_dir_all(CAPTURE_DIR)
+            .map_err(image::ImageError::IoError)
+            .and_then(|_| layout.to_image(&data).save(&path));
+        match result {
+            Ok(()) => log::info!("Saved screenshot to {}", path.display()),
+            Err(e) => log::error!("Couldn't save screenshot {}: {}", path.display(), e),
+        }
+    });
+}
+
+fn save_clip(frames: Vec<Vec<u8>>, layout: FrameLayout) {
+    if frames.is_empty() {
+        return;
+    }
+    let path = capture_path("clip", "gif");
+    std::thread::spawn(move || {
+        let result = (|| -> image::ImageResult<()> {
+            std::fs::create_dir_all(CAPTURE_DIR)?;
+            let file = std::fs::File::create(&path)?;
+            let mut encoder = image::codecs::gif::GifEncoder::new_with_speed(std::io::BufWriter::new(file), 10);
+            encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+            let delay = image::Delay::from_numer_denom_ms(1000, CLIP_FPS as u32);
+            let (width, height) = (layout.width / CLIP_DOWNSCALE, layout.height / CLIP_DOWNSCALE);
+            encoder.encode_frames(frames.into_iter().map(|data| {
+                let small = image::imageops::resize(&layout.to_image(&data), width, height, image::imageops::FilterType::Nearest);
+                image::Frame::from_parts(small, 0, 0, delay)
+            }))
+        })();
+        match result {
+            Ok(()) => log::info!("Saved clip to {}", path.display()),
+            Err(e) => log::error!("Couldn't save clip {}: {}", path.display(), e),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clip_frames_go_round_the_ring_oldest_first_and_failed_copies_are_dropped() {
+        let Ok(gpu) = pollster::block_on(WGPU::headless(16, 8)) else { return };
+        let canvas = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d { width: 16, height: 8, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: gpu.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let mut capture = Capture::new();
+        let slots = (CLIP_SECONDS * CLIP_FPS) as usize;
+        // More frames than the ring holds, so it wraps around
+        for i in 0..80 {
+            let before: Vec<Option<Instant>> = capture.clip_ring.iter().map(|slot| slot.frame).collect();
+            // Once the ring is full, the slot about to be reused is the one with the oldest frame
+            if i >= slots {
+                assert_eq!(before[i % slots], before.iter().flatten().min().copied());
+            }
+            capture.last_clip_frame = None;
+            let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            capture.record(&gpu, &mut encoder, &canvas);
+            gpu.queue.submit(Some(encoder.finish()));
+            gpu.device.poll(wgpu::Maintain::Wait);
+            capture.update(&gpu);
+            assert_eq!(capture.clip_ring.len(), slots);
+            // The frame just written is the newest in the ring, and newer than the one it replaced
+            let written = capture.clip_ring[i % slots].frame.expect("the frame should have arrived");
+            for (index, slot) in capture.clip_ring.iter().enumerate() {
+                if let (true, Some(frame)) = (index != i % slots, slot.frame) {
+                    assert!(frame < written, "slot {} is newer than slot {}", index, i % slots);
+                }
+            }
+            if let Some(replaced) = before.get(i % slots).copied().flatten() {
+                assert!(replaced < written);
+            }
+        }
+        assert!(capture.clip_ring.iter().all(|slot| slot.frame.is_some()));
+
+        // A copy that never arrives stops being waited for, so clips aren't held up forever
+        let failed = Readback::new(ReadbackTarget::ClipFrame(0));
+        failed.status.store(MAP_FAILED, Ordering::Release);
+        capture.pending.push(Readback { mapping: true, ..failed });
+        capture.update(&gpu);
+        assert!(capture.pending.is_empty());
+    }
+}
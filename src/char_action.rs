@@ -1,5 +1,6 @@
 use rand::Rng;
 use crate::Animation;
+use crate::canvas::{LOGICAL_WIDTH, LOGICAL_HEIGHT};
 pub struct Char_action {
     pub screen_region: [f32; 4],
     pub sheet_region: [f32; 4],
@@ -11,6 +12,12 @@ pub struct Char_action {
     pub caught: bool,
     pub vibrate_state: bool,
     pub vibrate_counter: usize,
+    // the stretch of world (min x, max x) this character walks or swims in
+    pub x_range: [f32; 2],
+    // how faded in the character is, from 0.0 (invisible) to 1.0; fish fade in after they respawn
+    pub fade: f32,
+    // frames left to flash bright for, e.g. right after getting hooked
+    pub flash_frames: usize,
 
 }
 
@@ -32,7 +39,10 @@ impl Char_action {
                 sprites_index: (sprites_ind),
                 caught: false,
                 vibrate_state: false,
-                vibrate_counter: 0 }
+                vibrate_counter: 0,
+                x_range: [0.0, LOGICAL_WIDTH as f32],
+                fade: 1.0,
+                flash_frames: 0 }
     }
 
     pub fn walk(&mut self){
@@ -43,6 +53,12 @@ impl Char_action {
         else {
             self.screen_region[0] += self.speed;
         }
+        // don't walk off either end of the world
+        self.screen_region[0] = self.screen_region[0].clamp(self.x_range[0], self.x_range[1]);
+    }
+
+    pub fn set_x_range(&mut self, min: f32, max: f32) {
+        self.x_range = [min, max];
     }
     pub fn face_left(&mut self) {
         self.facing_left = true;
@@ -58,8 +74,8 @@ impl Char_action {
         self.screen_region[1] -= self.speed;
 
         if self.screen_region[1] <= 0.0 {
-            self.screen_region[1] = 768.0;
-            self.screen_region[0] = rand::thread_rng().gen_range(0..1025) as f32;
+            self.screen_region[1] = LOGICAL_HEIGHT as f32;
+            self.screen_region[0] = rand::thread_rng().gen_range(0..=LOGICAL_WIDTH) as f32;
         }
     }
 
@@ -88,7 +104,7 @@ impl Char_action {
     pub fn move_right(&mut self) {
         self.screen_region[0] += self.speed;
 
-        if self.screen_region[0] >= 1024.0 {
+        if self.screen_region[0] >= self.x_range[1] {
             self.reset_x();
         }
     }
@@ -96,7 +112,7 @@ impl Char_action {
     pub fn move_left(&mut self) {
         self.screen_region[0] -= self.speed;
 
-        if self.screen_region[0] <= -70.0 {
+        if self.screen_region[0] <= self.x_range[0] - 70.0 {
             self.reset_x();
         }
     }
@@ -104,8 +120,8 @@ impl Char_action {
     pub fn deep_move_right(&mut self) {
         self.screen_region[0] += self.speed;
 
-        if self.screen_region[0] >= 1024.0 {
-            self.screen_region[0] = 0.0;
+        if self.screen_region[0] >= self.x_range[1] {
+            self.screen_region[0] = self.x_range[0];
             self.screen_region[1] = rand::thread_rng().gen_range(0..100) as f32;
         }
     }
@@ -113,35 +129,47 @@ impl Char_action {
     pub fn deep_move_left(&mut self) {
         self.screen_region[0] -= self.speed;
 
-        if self.screen_region[0] <= -70.0 {
-            self.screen_region[0] = 1024.0;
+        if self.screen_region[0] <= self.x_range[0] - 70.0 {
+            self.screen_region[0] = self.x_range[1];
             self.screen_region[1] = rand::thread_rng().gen_range(0..100) as f32;
         }
     }
 
     pub fn reset_x(&mut self){
+        self.fade = 0.0;
         let chance = rand::thread_rng().gen_range(0..2) as usize;
         if chance == 0 {
-            self.screen_region[0] = 1024.0;
+            self.screen_region[0] = self.x_range[1];
             self.screen_region[1] = rand::thread_rng().gen_range(0..500) as f32;
             self.facing_left = true;
         }
         else {
-            self.screen_region[0] = 0.0;
+            self.screen_region[0] = self.x_range[0];
             self.screen_region[1] = rand::thread_rng().gen_range(0..500) as f32;
             self.facing_left = false;
         }
     }
 
     pub fn reset_y(&mut self){
-        self.screen_region[1] = 768.0;
-        self.screen_region[0] = rand::thread_rng().gen_range(0..1025) as f32;
+        self.screen_region[1] = LOGICAL_HEIGHT as f32;
+        self.screen_region[0] = rand::thread_rng().gen_range(0..=LOGICAL_WIDTH) as f32;
     }
 
-    pub fn scale_elongate(&mut self, desired_height: f32, offset: f32) {
-        self.screen_region[3] = -(768.0 - desired_height) + offset;
-        //self.screen_region[2] = self.screen_region[3] * 0.5;
-        
+    // Fade in over `fade_time` seconds and count down any flash
+    pub fn tick_fade(&mut self, dt: f32, fade_time: f32) {
+        self.fade = (self.fade + dt / fade_time).min(1.0);
+        self.flash_frames = self.flash_frames.saturating_sub(1);
+    }
+
+    // The color to tint the sprite with: deeper water makes fish darker, bluer and a bit see-through,
+    // and a flash turns the sprite nearly white for a few frames.
+    pub fn tint(&self, surface_y: f32) -> [f32; 4] {
+        if self.flash_frames > 0 {
+            return [4.0, 4.0, 4.0, 1.0];
+        }
+        let depth = 1.0 - (self.screen_region[1] / surface_y).clamp(0.0, 1.0);
+        let shade = 1.0 - 0.45 * depth;
+        [shade, shade, 1.0 - 0.2 * depth, self.fade * (1.0 - 0.3 * depth)]
     }
 
     pub fn set_animation_index(&mut self, index: usize) {
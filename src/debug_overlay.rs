@@ -0,0 +1,284 @@
+use std::collections::VecDeque;
+
+use glyphon::{Attrs, Buffer, Color, Family, FontSystem, Metrics, Shaping, TextArea, TextBounds};
+
+use crate::camera::GPUCamera;
+use crate::shapes::{Brush, ShapeRenderer};
+use crate::sprite_game::Layer;
+
+// How many frames the frame-time graph remembers
+const GRAPH_FRAMES: usize = 120;
+// Where the graph goes on the canvas, and how tall a frame has to be to reach its top
+const GRAPH_RECT: [f32; 4] = [764.0, 668.0, 240.0, 80.0];
+const GRAPH_MAX_MS: f32 = 50.0;
+
+const HITBOX_COLOR: [f32; 4] = [0.2, 1.0, 0.3, 0.9];
+const HOOK_COLOR: [f32; 4] = [1.0, 0.3, 0.2, 1.0];
+const VELOCITY_COLOR: [f32; 4] = [1.0, 1.0, 0.2, 1.0];
+const BAND_COLOR: [f32; 4] = [0.3, 0.7, 1.0, 0.7];
+const HOVER_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+// Over everything, including the HUD
+const WORLD: Brush = Brush::world(Layer::Ui);
+const CANVAS: Brush = Brush::canvas(Layer::Ui);
+const THICKNESS: f32 = 2.0;
+
+// Something in the world the overlay outlines and can inspect
+pub struct DebugEntity {
+    pub name: String,
+    // What the game checks collisions with, as [x, y, width, height] from the bottom left in world pixels.
+    // Not always where the sprite is!
+    pub hitbox: [f32; 4],
+    // World pixels per frame
+    pub velocity: [f32; 2],
+    // Extra lines for the inspector: animation, state and so on
+    pub details: String,
+}
+
+// Everything the overlay draws this frame, gathered up by main.rs
+pub struct DebugScene<'a> {
+    pub entities: &'a [DebugEntity],
+    // The box fish get caught by, if the line is out
+    pub hook_box: Option<[f32; 4]>,
+    // Heights in the world worth seeing: the surface, where the deep fish swim...
+    pub depth_bands: &'a [f32],
+    pub world_width: f32,
+    // Where the mouse is, in canvas pixels
+    pub mouse: [f32; 2],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameStats {
+    pub best: f32,
+    pub average: f32,
+    pub worst: f32,
+}
+
+// The last GRAPH_FRAMES frame times in seconds, oldest first
+#[derive(Default)]
+struct FrameTimes(VecDeque<f32>);
+
+impl FrameTimes {
+    fn record(&mut self, dt: f32) {
+        if self.0.len() == GRAPH_FRAMES {
+            self.0.pop_front();
+        }
+        self.0.push_back(dt);
+    }
+
+    fn stats(&self) -> FrameStats {
+        if self.0.is_empty() {
+            return FrameStats { best: 0.0, average: 0.0, worst: 0.0 };
+        }
+        let total: f32 = self.0.iter().sum();
+        FrameStats {
+            best: self.0.iter().copied().fold(f32::INFINITY, f32::min),
+            average: total / self.0.len() as f32,
+            worst: self.0.iter().copied().fold(0.0, f32::max),
+        }
+    }
+
+    // One bar per frame for the graph, as a rect on the canvas and a color. The newest is at the right
+    // edge of GRAPH_RECT, and anything over GRAPH_MAX_MS is cut off at the top.
+    fn bars(&self) -> impl Iterator<Item = ([f32; 4], [f32; 4])> + '_ {
+        let [left, top, width, height] = GRAPH_RECT;
+        let bottom = top + height;
+        let step = width / GRAPH_FRAMES as f32;
+        let first = GRAPH_FRAMES - self.0.len();
+        self.0.iter().enumerate().map(move |(i, &dt)| {
+            let ms = dt * 1000.0;
+            let bar_height = (ms / GRAPH_MAX_MS).min(1.0) * height;
+            // A little slack over 60 and 30 fps, so vsync jitter doesn't flicker the colors
+            let color = if ms <= 17.5 {
+                [0.3, 1.0, 0.3, 0.9]
+            } else if ms <= 34.5 {
+                [1.0, 0.8, 0.2, 0.9]
+            } else {
+                [1.0, 0.3, 0.2, 0.9]
+            };
+            ([left + (first + i) as f32 * step, bottom - bar_height, step, bar_height], color)
+        })
+    }
+}
+
+// Hitboxes, velocities and depth bands drawn over the world, an inspector for whatever's under the
+// mouse, and a rolling graph of frame times. Costs nothing while it's off.
+pub struct DebugOverlay {
+    pub enabled: bool,
+    frame_times: FrameTimes,
+    inspector: Buffer,
+    inspector_pos: Option<[f32; 2]>,
+    stats: Buffer,
+}
+
+#[allow(dead_code)]
+impl DebugOverlay {
+    pub fn new(font_system: &mut FontSystem) -> Self {
+        let buffer = |font_system: &mut FontSystem| {
+            let mut buffer = Buffer::new(font_system, Metrics::new(16.0, 19.0));
+            buffer.set_size(font_system, 320.0, 240.0);
+            buffer
+        };
+        Self {
+            enabled: false,
+            frame_times: FrameTimes::default(),
+            inspector: buffer(font_system),
+            inspector_pos: None,
+            stats: buffer(font_system),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    // Call once a frame with how long the frame took, in seconds. Recorded even while the overlay is
+    // off, so the graph is already full when it's turned on.
+    pub fn record_frame(&mut self, dt: f32) {
+        self.frame_times.record(dt);
+    }
+
+    // Queue up this frame's lines and lay out its text
+    pub fn draw(&mut self, font_system: &mut FontSystem, shapes: &mut ShapeRenderer, camera: &GPUCamera, canvas_size: [f32; 2], scene: &DebugScene) {
+        if !self.enabled {
+            return;
+        }
+        for &y in scene.depth_bands {
+            shapes.line(WORLD, [0.0, y], [scene.world_width, y], THICKNESS, BAND_COLOR);
+        }
+        let mouse = camera.canvas_to_world(scene.mouse, canvas_size);
+        let mut hovered = None;
+        for entity in scene.entities {
+            let [x, y, w, h] = entity.hitbox;
+            let under_mouse = (x..=x + w).contains(&mouse[0]) && (y..=y + h).contains(&mouse[1]);
+            if under_mouse && hovered.is_none() {
+                hovered = Some(entity);
+            }
+            shapes.rect_outline(WORLD, entity.hitbox, THICKNESS, if under_mouse { HOVER_COLOR } else { HITBOX_COLOR });
+            // Stretched out to where it'll be in a quarter of a second at 60fps
+            if entity.velocity != [0.0, 0.0] {
+                let middle = [x + w / 2.0, y + h / 2.0];
+                let tip = [middle[0] + entity.velocity[0] * 15.0, middle[1] + entity.velocity[1] * 15.0];
+                shapes.line(WORLD, middle, tip, THICKNESS, VELOCITY_COLOR);
+            }
+        }
+        if let Some(hook_box) = scene.hook_box {
+            shapes.rect_outline(WORLD, hook_box, THICKNESS, HOOK_COLOR);
+        }
+
+        let attrs = Attrs::new().family(Family::Monospace);
+        self.inspector_pos = hovered.map(|entity| {
+            let [x, y, w, h] = entity.hitbox;
+            let text = format!(
+                "{}\nhitbox: ({:.0}, {:.0}) {:.0}x{:.0}\nvelocity: ({:.1}, {:.1}) px/frame\n{}",
+                entity.name, x, y, w, h, entity.velocity[0], entity.velocity[1], entity.details
+            );
+            self.inspector.set_text(font_system, &text, attrs, Shaping::Advanced);
+            // Next to the pointer, but kept on the canvas
+            [(scene.mouse[0] + 16.0).min(canvas_size[0] - 320.0), (scene.mouse[1] + 16.0).min(canvas_size[1] - 120.0)]
+        });
+
+        self.draw_graph(shapes);
+        let FrameStats { best, average, worst } = self.frame_stats();
+        let stats = format!("{:.1} ms avg, {:.1} best, {:.1} worst", average * 1000.0, best * 1000.0, worst * 1000.0);
+        self.stats.set_text(font_system, &stats, attrs, Shaping::Advanced);
+    }
+
+    // One bar per frame, newest on the right, with lines at 60 and 30 fps
+    fn draw_graph(&self, shapes: &mut ShapeRenderer) {
+        let [left, top, width, height] = GRAPH_RECT;
+        let bottom = top + height;
+        shapes.rect(CANVAS, GRAPH_RECT, [0.0, 0.0, 0.0, 0.5]);
+        shapes.rect_outline(CANVAS, GRAPH_RECT, 1.0, [1.0, 1.0, 1.0, 0.6]);
+        let height_of = |ms: f32| (ms / GRAPH_MAX_MS).min(1.0) * height;
+        for (ms, color) in [(1000.0 / 60.0, [0.3, 1.0, 0.3, 0.5]), (1000.0 / 30.0, [1.0, 0.8, 0.2, 0.5])] {
+            shapes.line(CANVAS, [left, bottom - height_of(ms)], [left + width, bottom - height_of(ms)], 1.0, color);
+        }
+        for (bar, color) in self.frame_times.bars() {
+            shapes.rect(CANVAS, bar, color);
+        }
+    }
+
+    // Shortest, average and longest frame time in the graph, in seconds
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_times.stats()
+    }
+
+    // Hand these to the text renderer along with the HUD's
+    pub fn text_areas(&self, canvas_size: [f32; 2]) -> impl Iterator<Item = TextArea<'_>> {
+        let bounds = TextBounds {
+            left: 0,
+            top: 0,
+            right: canvas_size[0] as i32,
+            bottom: canvas_size[1] as i32,
+        };
+        let area = move |buffer, [left, top]: [f32; 2]| TextArea {
+            buffer,
+            left,
+            top,
+            scale: 1.0,
+            bounds,
+            default_color: Color::rgb(255, 255, 255),
+        };
+        let stats = self.enabled.then(|| area(&self.stats, [GRAPH_RECT[0], GRAPH_RECT[1] - 22.0]));
+        let inspector = self.inspector_pos.filter(|_| self.enabled).map(|pos| area(&self.inspector, pos));
+        stats.into_iter().chain(inspector)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_latest_frames_are_kept() {
+        let mut times = FrameTimes::default();
+        for i in 0..GRAPH_FRAMES + 30 {
+            times.record(i as f32);
+        }
+        assert_eq!(times.0.len(), GRAPH_FRAMES);
+        // The first 30 fell off the front
+        assert_eq!(times.0.front(), Some(&30.0));
+        assert_eq!(times.0.back(), Some(&(GRAPH_FRAMES as f32 + 29.0)));
+    }
+
+    #[test]
+    fn stats_cover_the_window() {
+        let mut times = FrameTimes::default();
+        assert_eq!(times.stats(), FrameStats { best: 0.0, average: 0.0, worst: 0.0 });
+        for dt in [0.016, 0.020, 0.050, 0.010] {
+            times.record(dt);
+        }
+        let stats = times.stats();
+        assert_eq!(stats.best, 0.010);
+        assert_eq!(stats.worst, 0.050);
+        assert!((stats.average - 0.024).abs() < 1e-6);
+        // Once the slow frame scrolls out of the window it stops counting
+        for _ in 0..GRAPH_FRAMES {
+            times.record(0.016);
+        }
+        assert_eq!(times.stats().worst, 0.016);
+    }
+
+    #[test]
+    fn graph_bars_grow_with_frame_time_and_end_at_the_right() {
+        let mut times = FrameTimes::default();
+        for dt in [0.010, 0.030, 1.0] {
+            times.record(dt);
+        }
+        let bars: Vec<_> = times.bars().collect();
+        let [left, top, width, height] = GRAPH_RECT;
+        // The newest bar touches the right edge, and every bar stands on the bottom
+        let [x, y, w, h] = bars[2].0;
+        assert!((x + w - (left + width)).abs() < 1e-3);
+        for (bar, _) in &bars {
+            assert!((bar[1] + bar[3] - (top + height)).abs() < 1e-3);
+        }
+        assert!(bars[0].0[3] < bars[1].0[3]);
+        // A very slow frame is cut off at the top of the graph, and shown in red
+        assert_eq!(h, height);
+        assert_eq!(y, top);
+        assert_eq!(bars[0].1, [0.3, 1.0, 0.3, 0.9]);
+        assert_eq!(bars[2].1, [1.0, 0.3, 0.2, 0.9]);
+    }
+}
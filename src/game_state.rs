@@ -1,3 +1,6 @@
+// The world is three screens wide; the camera scrolls along it following the fisherman.
+pub const WORLD_WIDTH: f32 = 3072.0;
+
 pub struct GameState{
     pub chars_typed: u32,
     pub score: usize,
@@ -6,6 +9,8 @@ pub struct GameState{
     // 0 = title, 1 = instructions, 2 = play, 3 = end,
     pub game_screen: usize,
     pub secs_left: usize,
+    // How long this round is in total, for the HUD's timer bar
+    pub round_length: usize,
     pub time_since_last_update: f32,
 }
 
@@ -19,7 +24,8 @@ impl GameState {
             is_currently_casted: false,
             game_screen: 0,
             secs_left: 30,
+            round_length: 30,
             time_since_last_update: 0.0
         }
     }
-}
\ No newline at end of file
+}
@@ -1,15 +1,114 @@
 // use crate::USE_STORAGE;
 
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::mipmaps;
+
+// Everything that can go wrong setting up the GPU or loading things onto it
+#[derive(Debug)]
+pub enum GpuError {
+    CreateSurface(wgpu::CreateSurfaceError),
+    // No adapter at all, not even a software one
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+    // The surface can't give us frames anymore and reconfiguring won't help
+    Surface(wgpu::SurfaceError),
+    LoadImage { path: PathBuf, source: image::ImageError },
+}
+
+impl fmt::Display for GpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GpuError::CreateSurface(e) => write!(f, "couldn't create a surface for the window: {}", e),
+            GpuError::NoAdapter => write!(f, "couldn't find a graphics adapter"),
+            GpuError::RequestDevice(e) => write!(f, "couldn't create a GPU device: {}", e),
+            GpuError::Surface(e) => write!(f, "couldn't get the next frame from the surface: {}", e),
+            GpuError::LoadImage { path, source } => write!(f, "couldn't load {}: {}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for GpuError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GpuError::CreateSurface(e) => Some(e),
+            GpuError::NoAdapter => None,
+            GpuError::RequestDevice(e) => Some(e),
+            GpuError::Surface(e) => Some(e),
+            GpuError::LoadImage { source, .. } => Some(source),
+        }
+    }
+}
+
+// Whether a texture's colors are sRGB-encoded (art painted by hand, the usual) or plain linear values
+// (data like noise or distortion maps, where the shader wants exactly the numbers in the file)
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+// How an image becomes a texture. The default is what every texture used to get:
+// sRGB, straight alpha, no mipmaps.
+// There's no block compression option: BC/ETC formats aren't available on every adapter the game
+// runs on (it only asks for downlevel limits), and the textures here are small.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureOptions {
+    pub color_space: ColorSpace,
+    // Generate the whole mip chain, down to 1x1
+    pub mipmaps: bool,
+    // Multiply colors by alpha before uploading. Sprite groups drawing such a texture skip doing it in
+    // the shader (see BlendMode), and its mipmaps and filtering are free of dark fringes.
+    pub premultiply_alpha: bool,
+}
+
+#[allow(dead_code)]
+impl TextureOptions {
+    pub const DEFAULT: TextureOptions = TextureOptions {
+        color_space: ColorSpace::Srgb,
+        mipmaps: false,
+        premultiply_alpha: false,
+    };
+    // For big backgrounds and screens that get drawn scaled down
+    pub const MIPMAPPED: TextureOptions = TextureOptions {
+        mipmaps: true,
+        ..TextureOptions::DEFAULT
+    };
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        match self.color_space {
+            ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+        }
+    }
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        TextureOptions::DEFAULT
+    }
+}
+
+// Anything that can draw itself into a render pass, so it can be rendered offscreen too
+#[allow(dead_code)]
+pub(crate) trait Scene {
+    fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>);
+}
+
 #[allow(dead_code)]
 pub struct WGPU {
     instance: wgpu::Instance,
-    pub(crate) surface: wgpu::Surface,
+    // None when running headless, see WGPU::headless
+    surface: Option<wgpu::Surface>,
     adapter: wgpu::Adapter,
     pub(crate) device: wgpu::Device,
     pub(crate) queue: wgpu::Queue,
     pub(crate) config: wgpu::SurfaceConfiguration,
 }
 
+#[allow(dead_code)]
 impl WGPU {
 
     // AsRef means we can take as parameters anything that cheaply converts into a Path,
@@ -17,12 +116,53 @@ impl WGPU {
     pub async fn load_texture(
         path: impl AsRef<std::path::Path>,
         label: Option<&str>,
+        options: TextureOptions,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-    ) -> Result<(wgpu::Texture, image::RgbaImage), image::ImageError> {
+    ) -> Result<(wgpu::Texture, image::RgbaImage), GpuError> {
         // This ? operator will return the error if there is one, unwrapping the result otherwise.
-        let img = image::open(path.as_ref())?.to_rgba8();
+        let img = image::open(path.as_ref())
+            .map_err(|source| GpuError::LoadImage { path: path.as_ref().to_path_buf(), source })?
+            .to_rgba8();
+        let texture = Self::texture_from_image(&img, label, options, device, queue);
+        Ok((texture,img))
+    }
+
+    // Like load_texture, but a missing or broken file logs an error and gives a checkerboard instead
+    // of stopping the game, so the problem is obvious on screen.
+    pub async fn load_texture_or_checkerboard(
+        path: impl AsRef<std::path::Path>,
+        label: Option<&str>,
+        options: TextureOptions,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> (wgpu::Texture, image::RgbaImage) {
+        match Self::load_texture(path, label, options, device, queue).await {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                log::error!("{}, using a placeholder texture", e);
+                let img = checkerboard_image();
+                let texture = Self::texture_from_image(&img, label, options, device, queue);
+                (texture, img)
+            }
+        }
+    }
 
+    // Upload an image that's already in memory, e.g. one generated by a test
+    pub fn texture_from_image(
+        img: &image::RgbaImage,
+        label: Option<&str>,
+        options: TextureOptions,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> wgpu::Texture {
+        let premultiplied;
+        let img = if options.premultiply_alpha {
+            premultiplied = premultiply_alpha(img, options.color_space);
+            &premultiplied
+        } else {
+            img
+        };
         let (width, height) = img.dimensions();
         let size = wgpu::Extent3d {
             width,
@@ -32,16 +172,20 @@ impl WGPU {
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
             size,
-            mip_level_count: 1,
+            mip_level_count: if options.mipmaps { mipmaps::mip_level_count(width, height) } else { 1 },
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            format: options.format(),
+            // The smaller mip levels are drawn into, and COPY_SRC lets tests and tools read any level back
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
+                | if options.mipmaps { wgpu::TextureUsages::RENDER_ATTACHMENT } else { wgpu::TextureUsages::empty() },
             view_formats: &[],
         });
         queue.write_texture(
             texture.as_image_copy(),
-            &img,
+            img,
             wgpu::ImageDataLayout {
                 offset: 0,
                 bytes_per_row: Some(4 * width),
@@ -49,7 +193,10 @@ impl WGPU {
             },
             size,
         );
-        Ok((texture,img))
+        if options.mipmaps {
+            mipmaps::generate_mipmaps(device, queue, &texture, options.premultiply_alpha);
+        }
+        texture
     }
 
     // pub async fn load_texture(
@@ -113,14 +260,14 @@ impl WGPU {
     //     Ok((texture, img))
     // }
 
-    pub(crate) async fn new(window: &winit::window::Window) -> Self {
+    pub(crate) async fn new(window: &winit::window::Window, present_mode: wgpu::PresentMode) -> Result<Self, GpuError> {
         let size = window.inner_size();
 
         // log::info!("Use storage? {:?}", USE_STORAGE);
 
         let instance = wgpu::Instance::default();
 
-        let surface = unsafe { instance.create_surface(&window) }.unwrap();
+        let surface = unsafe { instance.create_surface(&window) }.map_err(GpuError::CreateSurface)?;
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::default(),
@@ -129,7 +276,7 @@ impl WGPU {
                 compatible_surface: Some(&surface),
             })
             .await
-            .expect("Failed to find an appropriate adapter");
+            .ok_or(GpuError::NoAdapter)?;
 
         // Create the logical device and command queue
         // let (device, queue) = adapter
@@ -160,7 +307,7 @@ impl WGPU {
                 None,
             )
             .await
-            .expect("Failed to create device");
+            .map_err(GpuError::RequestDevice)?;
 
         // if USE_STORAGE {
         //     let supports_storage_resources = adapter
@@ -178,26 +325,285 @@ impl WGPU {
             format: swapchain_format,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::AutoVsync,
+            present_mode,
             alpha_mode: swapchain_capabilities.alpha_modes[0],
             view_formats: vec![],
         };
 
         surface.configure(&device, &config);
 
-        Self {
+        Ok(Self {
             instance,
-            surface,
+            surface: Some(surface),
             adapter,
             device,
             queue,
             config,
+        })
+    }
+
+    // A WGPU without a window, for rendering offscreen (screenshot tests, tools).
+    // It takes whatever adapter it can get, including a software one.
+    pub(crate) async fn headless(width: u32, height: u32) -> Result<Self, GpuError> {
+        let instance = wgpu::Instance::default();
+        let mut adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                force_fallback_adapter: false,
+                compatible_surface: None,
+            })
+            .await;
+        if adapter.is_none() {
+            // No real GPU, try the software rasterizer
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    force_fallback_adapter: true,
+                    compatible_surface: None,
+                })
+                .await;
         }
+        let adapter = adapter.ok_or(GpuError::NoAdapter)?;
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: wgpu::Features::empty(),
+                    limits: wgpu::Limits::downlevel_defaults()
+                        .using_resolution(adapter.limits()),
+                },
+                None,
+            )
+            .await
+            .map_err(GpuError::RequestDevice)?;
+
+        // Nothing is presented, but the pipelines still want to know what format they draw into
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::AutoVsync,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+        };
+
+        Ok(Self {
+            instance,
+            surface: None,
+            adapter,
+            device,
+            queue,
+            config,
+        })
+    }
+
+    pub(crate) fn surface(&self) -> &wgpu::Surface {
+        self.surface.as_ref().expect("Headless WGPU has no surface")
     }
 
     pub(crate) fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
         self.config.width = size.width;
         self.config.height = size.height;
-        self.surface.configure(&self.device, &self.config);
+        self.configure_surface();
+    }
+
+    // Switch between vsync and no vsync without recreating anything else
+    pub(crate) fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        self.config.present_mode = present_mode;
+        self.configure_surface();
+    }
+
+    fn configure_surface(&self) {
+        // A minimised window is 0x0, which the surface can't be configured to; wait for a real size
+        if self.config.width == 0 || self.config.height == 0 {
+            return;
+        }
+        if let Some(surface) = &self.surface {
+            surface.configure(&self.device, &self.config);
+        }
+    }
+
+    // Get the next swapchain image to draw into. Ok(None) means skip this frame: the window is
+    // minimised, or the surface was lost or outdated (it's been reconfigured for next time).
+    pub(crate) fn next_frame(&self) -> Result<Option<wgpu::SurfaceTexture>, GpuError> {
+        if self.config.width == 0 || self.config.height == 0 {
+            return Ok(None);
+        }
+        match self.surface().get_current_texture() {
+            Ok(frame) => Ok(Some(frame)),
+            Err(wgpu::SurfaceError::Lost) | Err(wgpu::SurfaceError::Outdated) => {
+                log::warn!("Surface lost or outdated, reconfiguring");
+                self.configure_surface();
+                Ok(None)
+            }
+            Err(wgpu::SurfaceError::Timeout) => {
+                log::warn!("Timed out waiting for the next frame");
+                Ok(None)
+            }
+            Err(e) => Err(GpuError::Surface(e)),
+        }
+    }
+
+    // Run some GPU object creation and catch validation errors (like a shader that doesn't compile)
+    // instead of letting wgpu panic. Used for hot reloading, where a typo shouldn't end the game.
+    pub(crate) fn validated<T>(&self, create: impl FnOnce() -> T) -> Result<T, String> {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let created = create();
+        match pollster::block_on(self.device.pop_error_scope()) {
+            Some(e) => Err(e.to_string()),
+            None => Ok(created),
+        }
+    }
+
+    // Draw a scene into a config-sized offscreen texture and read the pixels back
+    pub(crate) fn render_to_image(&self, scene: &impl Scene, clear: wgpu::Color) -> image::RgbaImage {
+        let (width, height) = (self.config.width, self.config.height);
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(clear),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            scene.draw(&mut rpass);
+        }
+        self.queue.submit(Some(encoder.finish()));
+        self.read_texture(&texture)
+    }
+
+    // Copy a texture (COPY_SRC, 4 bytes per pixel) back to the CPU.
+    // This waits for the GPU, so it's for tests and captures, not every frame.
+    pub(crate) fn read_texture(&self, texture: &wgpu::Texture) -> image::RgbaImage {
+        self.read_texture_level(texture, 0)
+    }
+
+    // The same for one of its mip levels
+    pub(crate) fn read_texture_level(&self, texture: &wgpu::Texture, mip_level: u32) -> image::RgbaImage {
+        let size = texture.size().mip_level_size(mip_level, texture.dimension());
+        let (width, height) = (size.width, size.height);
+        let padded_row = padded_bytes_per_row(width);
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: (padded_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            size,
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.expect("Couldn't map readback buffer"));
+        self.device.poll(wgpu::Maintain::Wait);
+        let img = image_from_padded_rows(&slice.get_mapped_range(), width, height, texture.format());
+        buffer.unmap();
+        img
+    }
+}
+
+// Magenta and black squares: hard to miss when an asset didn't load
+pub fn checkerboard_image() -> image::RgbaImage {
+    image::RgbaImage::from_fn(64, 64, |x, y| {
+        if (x / 8 + y / 8) % 2 == 0 {
+            image::Rgba([255, 0, 255, 255])
+        } else {
+            image::Rgba([0, 0, 0, 255])
+        }
+    })
+}
+
+// Multiply each pixel's color by its alpha. sRGB colors are decoded first so the multiply happens on
+// the same linear values the GPU will blend with.
+pub fn premultiply_alpha(img: &image::RgbaImage, color_space: ColorSpace) -> image::RgbaImage {
+    let mut out = img.clone();
+    for pixel in out.pixels_mut() {
+        let alpha = pixel.0[3] as f32 / 255.0;
+        for channel in &mut pixel.0[..3] {
+            let value = *channel as f32 / 255.0;
+            let premultiplied = match color_space {
+                ColorSpace::Srgb => linear_to_srgb(srgb_to_linear(value) * alpha),
+                ColorSpace::Linear => value * alpha,
+            };
+            *channel = (premultiplied * 255.0).round() as u8;
+        }
+    }
+    out
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Rows in a texture-to-buffer copy have to start on 256 byte boundaries, so there may be some padding
+pub(crate) fn padded_bytes_per_row(width: u32) -> u32 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    (4 * width + align - 1) / align * align
+}
+
+// Turn the bytes of a texture-to-buffer copy into an image, stripping the row padding
+pub(crate) fn image_from_padded_rows(data: &[u8], width: u32, height: u32, format: wgpu::TextureFormat) -> image::RgbaImage {
+    let unpadded_row = (4 * width) as usize;
+    let mut pixels = Vec::with_capacity(unpadded_row * height as usize);
+    for row in data.chunks(padded_bytes_per_row(width) as usize).take(height as usize) {
+        pixels.extend_from_slice(&row[..unpadded_row]);
+    }
+    let mut img = image::RgbaImage::from_raw(width, height, pixels).unwrap();
+    // The swapchain is often BGRA; put the channels back in the order image expects
+    if matches!(format, wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb) {
+        for pixel in img.pixels_mut() {
+            pixel.0.swap(0, 2);
+        }
     }
+    img
 }
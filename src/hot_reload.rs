@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+// Where dev mode looks for changes: the art, and the shader sources (read from disk instead of the copies
+// compiled into the game)
+pub const WATCHED_PATHS: [&str; 6] = [
+    "fishful_content",
+    "nut_war_content",
+    SHADER_PATH,
+    WATER_SHADER_PATH,
+    TRANSITION_SHADER_PATH,
+    SHAPES_SHADER_PATH,
+];
+pub const SHADER_PATH: &str = "src/shader.wgsl";
+pub const WATER_SHADER_PATH: &str = "src/water.wgsl";
+pub const TRANSITION_SHADER_PATH: &str = "src/transition.wgsl";
+pub const SHAPES_SHADER_PATH: &str = "src/shapes.wgsl";
+
+// Dev mode is opt-in: run with `--dev` or set FISHFUL_DEV
+pub fn dev_mode_enabled() -> bool {
+    std::env::args().any(|arg| arg == "--dev") || std::env::var_os("FISHFUL_DEV").is_some()
+}
+
+// Notices files that changed by checking modification times every so often.
+// Polling is plenty for a handful of content folders and doesn't need any platform-specific APIs.
+pub struct FileWatcher {
+    roots: Vec<PathBuf>,
+    modified: HashMap<PathBuf, SystemTime>,
+    interval: Duration,
+    last_scan: Instant,
+}
+
+impl FileWatcher {
+    // Roots can be files or folders; folders are watched all the way down, so atlas folders and the
+    // string tables under fishful_content count too
+    pub fn new(roots: &[&str]) -> Self {
+        let mut watcher = Self {
+            roots: roots.iter().map(PathBuf::from).collect(),
+            modified: HashMap::new(),
+            interval: Duration::from_millis(500),
+            last_scan: Instant::now(),
+        };
+        // Remember what's there now so the first poll doesn't report everything
+        watcher.scan();
+        watcher
+    }
+
+    // Files that are new or were modified since the last poll. Cheap to call every frame.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_scan.elapsed() < self.interval {
+            return vec![];
+        }
+        self.last_scan = Instant::now();
+        self.scan()
+    }
+
+    fn scan(&mut self) -> Vec<PathBuf> {
+        let mut files = vec![];
+        for root in &self.roots {
+            if root.is_dir() {
+                files_under(root, &mut files);
+            } else {
+                files.push(root.clone());
+            }
+        }
+        let mut changed = vec![];
+        for file in files {
+            let Some(modified) = modified_time(&file) else { continue };
+            if self.modified.insert(file.clone(), modified) != Some(modified) {
+                changed.push(file);
+            }
+        }
+        changed
+    }
+}
+
+// Every file in `dir` and the folders inside it
+fn files_under(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            files_under(&path, files);
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh, empty folder for one test
+    fn temp_folder(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fishful_hot_reload_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // A watcher on `dir` that rescans on every poll
+    fn watcher(dir: &Path) -> FileWatcher {
+        let mut watcher = FileWatcher::new(&[dir.to_str().unwrap()]);
+        watcher.interval = Duration::ZERO;
+        watcher
+    }
+
+    // Move a file's modification time forward, rather than waiting for the clock to tick over
+    fn touch(path: &Path, seconds_later: u64) {
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(seconds_later)).unwrap();
+    }
+
+    #[test]
+    fn first_poll_reports_nothing() {
+        let dir = temp_folder("first_poll");
+        std::fs::write(dir.join("fish.png"), b"fish").unwrap();
+        let mut watcher = watcher(&dir);
+        assert!(watcher.poll().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn modified_files_are_reported_once() {
+        let dir = temp_folder("modified");
+        let fish = dir.join("fish.png");
+        std::fs::write(&fish, b"fish").unwrap();
+        std::fs::write(dir.join("pier.png"), b"pier").unwrap();
+        let mut watcher = watcher(&dir);
+        touch(&fish, 10);
+        assert_eq!(watcher.poll(), vec![fish]);
+        assert!(watcher.poll().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn new_files_in_subfolders_are_reported() {
+        let dir = temp_folder("subfolders");
+        std::fs::create_dir_all(dir.join("squirrel")).unwrap();
+        let mut watcher = watcher(&dir);
+        let frame = dir.join("squirrel").join("sq1.png");
+        std::fs::write(&frame, b"sq1").unwrap();
+        assert_eq!(watcher.poll(), vec![frame]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn nothing_is_reported_between_scans() {
+        let dir = temp_folder("interval");
+        let fish = dir.join("fish.png");
+        std::fs::write(&fish, b"fish").unwrap();
+        let mut watcher = FileWatcher::new(&[dir.to_str().unwrap()]);
+        touch(&fish, 10);
+        // Still inside the 500ms since the watcher was made
+        assert!(watcher.poll().is_empty());
+        watcher.last_scan -= watcher.interval;
+        assert_eq!(watcher.poll(), vec![fish]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
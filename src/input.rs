@@ -9,6 +9,7 @@ pub struct Input {
     prev_mouse: Box<[bool]>,
     now_mouse_pos: MousePos<f64>,
     prev_mouse_pos: MousePos<f64>,
+    last_key_pressed: Option<Key>,
 }
 impl Default for Input {
     fn default() -> Self {
@@ -19,6 +20,7 @@ impl Default for Input {
             prev_mouse: vec![false; 16].into_boxed_slice(),
             now_mouse_pos: MousePos { x: 0.0, y: 0.0 },
             prev_mouse_pos: MousePos { x: 0.0, y: 0.0 },
+            last_key_pressed: None,
         }
     }
 }
@@ -71,7 +73,12 @@ impl Input {
         (if self.is_key_down(down) { -1.0 } else { 0.0 })
             + (if self.is_key_down(up) { 1.0 } else { 0.0 })
     }
+    // The most recent key to go down this frame, if any (handy for rebinding keys)
+    pub fn last_key_pressed(&self) -> Option<Key> {
+        self.last_key_pressed
+    }
     pub fn next_frame(&mut self) {
+        self.last_key_pressed = None;
         self.prev_keys.copy_from_slice(&self.now_keys);
         self.prev_mouse.copy_from_slice(&self.now_mouse);
         self.prev_mouse_pos = self.now_mouse_pos;
@@ -83,13 +90,20 @@ impl Input {
             ..
         } = ke
         {
-            match state {
-                winit::event::ElementState::Pressed => {
-                    self.now_keys[keycode as usize] = true;
-                }
-                winit::event::ElementState::Released => {
-                    self.now_keys[keycode as usize] = false;
+            self.handle_key(keycode, state);
+        }
+    }
+    pub fn handle_key(&mut self, keycode: Key, state: ElementState) {
+        match state {
+            ElementState::Pressed => {
+                // Ignore key repeat so holding a key doesn't keep reporting it
+                if !self.now_keys[keycode as usize] {
+                    self.last_key_pressed = Some(keycode);
                 }
+                self.now_keys[keycode as usize] = true;
+            }
+            ElementState::Released => {
+                self.now_keys[keycode as usize] = false;
             }
         }
     }
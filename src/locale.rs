@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Path;
+
+// Where the string tables live, one TOML file per locale named after it (en.toml, es.toml, pt-BR.toml...)
+pub const LOCALES_DIR: &str = "fishful_content/locales";
+// Every other locale falls back to this one, so it should have every string in it
+pub const DEFAULT_LOCALE: &str = "en";
+
+// One string in a table: either plain text, or one text per plural category
+#[derive(Clone, Debug, PartialEq)]
+enum Entry {
+    Text(String),
+    Plural(HashMap<String, String>),
+}
+
+// The plural categories a table can use, named like CLDR's
+const PLURAL_CATEGORIES: [&str; 6] = ["zero", "one", "two", "few", "many", "other"];
+
+// Every string the game shows, in one language. Tables are TOML where sections become the first part
+// of a key, so `score = "..."` under `[hud]` is "hud.score". Text can have `{name}` placeholders, and a
+// section holding `one`/`other`/etc. is a plural:
+//
+//   [hud.seconds]
+//   one = "{count} second"
+//   other = "{count} seconds"
+//
+// A locale like "pt-BR" looks things up in pt-BR.toml, then pt.toml, then en.toml, so a table only
+// needs the strings it changes. Anything missing everywhere shows up as its key.
+#[derive(Clone, Debug)]
+pub struct Strings {
+    // Most specific first
+    chain: Vec<(String, HashMap<String, Entry>)>,
+}
+
+// Two sets of strings are the same if they're for the same locales, so the UI can tell when to
+// re-shape its text without comparing every table
+impl PartialEq for Strings {
+    fn eq(&self, other: &Self) -> bool {
+        self.chain.len() == other.chain.len()
+            && self.chain.iter().zip(&other.chain).all(|((a, _), (b, _))| a == b)
+    }
+}
+
+impl Default for Strings {
+    // No tables at all: every string is its key
+    fn default() -> Self {
+        Self { chain: Vec::new() }
+    }
+}
+
+#[allow(dead_code)]
+impl Strings {
+    // Load `locale` and everything it falls back to from `dir`. Missing or broken files are skipped
+    // with a warning, like a broken settings file.
+    pub fn load(dir: impl AsRef<Path>, locale: &str) -> Strings {
+        let dir = dir.as_ref();
+        let mut chain = Vec::new();
+        for name in fallback_chain(locale) {
+            let path = dir.join(format!("{}.toml", name));
+            let table = match std::fs::read_to_string(&path) {
+                Ok(text) => parse_table(&text),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => Err(e.to_string()),
+            };
+            match table {
+                Ok(table) => chain.push((name, table)),
+                Err(e) => log::warn!("Couldn't load strings from {}: {}", path.display(), e),
+            }
+        }
+        if chain.is_empty() {
+            log::warn!("No strings found for locale {} in {}", locale, dir.display());
+        }
+        Strings { chain }
+    }
+
+    // Build from tables that are already in memory, most specific first
+    pub fn from_tables(tables: &[(&str, &str)]) -> Result<Strings, String> {
+        let chain = tables
+            .iter()
+            .map(|&(name, text)| Ok((name.to_string(), parse_table(text)?)))
+            .collect::<Result<_, String>>()?;
+        Ok(Strings { chain })
+    }
+
+    // The locale that was asked for (or the first one that was found)
+    pub fn locale(&self) -> &str {
+        self.chain.first().map_or(DEFAULT_LOCALE, |(name, _)| name)
+    }
+
+    pub fn has(&self, key: &str) -> bool {
+        self.lookup(key).is_some()
+    }
+
+    // The text for `key`, with no placeholders filled in
+    pub fn get(&self, key: &str) -> String {
+        self.format(key, &[])
+    }
+
+    // The text for `key` with each `{name}` replaced by its argument. Plurals use their "other" text.
+    pub fn format(&self, key: &str, args: &[(&str, &dyn Display)]) -> String {
+        match self.lookup(key) {
+            Some(Entry::Text(text)) => fill(text, args),
+            Some(Entry::Plural(forms)) => fill(forms.get("other").map_or(key, String::as_str), args),
+            None => key.to_string(),
+        }
+    }
+
+    // The text for `key` picked by `count`'s plural category in this language, with `{count}` filled
+    // in as well as `args`
+    pub fn plural(&self, key: &str, count: i64, args: &[(&str, &dyn Display)]) -> String {
+        let mut all_args: Vec<(&str, &dyn Display)> = vec![("count", &count)];
+        all_args.extend_from_slice(args);
+        let Some((locale, entry)) = self.lookup_with_locale(key) else {
+            return key.to_string();
+        };
+        match entry {
+            Entry::Text(text) => fill(text, &all_args),
+            Entry::Plural(forms) => {
+                let form = forms
+                    .get(plural_category(locale, count))
+                    .or_else(|| forms.get("other"))
+                    .map_or(key, String::as_str);
+                fill(form, &all_args)
+            }
+        }
+    }
+
+    fn lookup(&self, key: &str) -> Option<&Entry> {
+        self.lookup_with_locale(key).map(|(_, entry)| entry)
+    }
+
+    // The plural rules come from whichever table the string was found in, since that's the language
+    // it's written in
+    fn lookup_with_locale(&self, key: &str) -> Option<(&str, &Entry)> {
+        self.chain
+            .iter()
+            .find_map(|(name, table)| table.get(key).map(|entry| (name.as_str(), entry)))
+    }
+}
+
+// "pt-BR" -> ["pt-BR", "pt", "en"], without repeats
+pub fn fallback_chain(locale: &str) -> Vec<String> {
+    let mut chain: Vec<String> = Vec::new();
+    let mut push = |name: &str| {
+        if !name.is_empty() && !chain.iter().any(|existing| existing == name) {
+            chain.push(name.to_string());
+        }
+    };
+    push(locale);
+    if let Some((language, _)) = locale.split_once(['-', '_']) {
+        push(language);
+    }
+    push(DEFAULT_LOCALE);
+    chain
+}
+
+// Every locale with a table in `dir`, sorted, for the settings screen to cycle through
+pub fn available(dir: impl AsRef<Path>) -> Vec<String> {
+    let mut locales: Vec<String> = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().map_or(false, |ext| ext == "toml"))
+                .filter_map(|path| path.file_stem().and_then(|stem| stem.to_str()).map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+    if locales.is_empty() {
+        locales.push(DEFAULT_LOCALE.to_string());
+    }
+    locales.sort();
+    locales
+}
+
+// Which plural category `count` falls in. Covers the kinds of rules most languages use; anything
+// not listed here gets the English rule.
+fn plural_category(locale: &str, count: i64) -> &'static str {
+    let language = locale.split(['-', '_']).next().unwrap_or(locale);
+    let n = count.unsigned_abs();
+    match language {
+        // No plurals at all
+        "ja" | "ko" | "zh" | "th" | "vi" | "id" => "other",
+        // 0 and 1 are both singular
+        "fr" | "pt" => {
+            if n <= 1 {
+                "one"
+            } else {
+                "other"
+            }
+        }
+        // 1, 21, 31... / 2-4, 22-24... / everything else
+        "ru" | "uk" | "be" | "sr" | "hr" | "bs" => match (n % 10, n % 100) {
+            (1, r) if r != 11 => "one",
+            (2..=4, r) if !(12..=14).contains(&r) => "few",
+            _ => "many",
+        },
+        "pl" => match (n % 10, n % 100) {
+            _ if n == 1 => "one",
+            (2..=4, r) if !(12..=14).contains(&r) => "few",
+            _ => "many",
+        },
+        _ => {
+            if n == 1 {
+                "one"
+            } else {
+                "other"
+            }
+        }
+    }
+}
+
+// Replace every `{name}` in `text` with its argument. Unknown names are left as they are so a typo in
+// a table is easy to spot.
+fn fill(text: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        match after.find('}') {
+            Some(close) => {
+                let name = &after[..close];
+                match args.iter().find(|(arg, _)| *arg == name) {
+                    Some((_, value)) => out.push_str(&value.to_string()),
+                    None => {
+                        out.push('{');
+                        out.push_str(name);
+                        out.push('}');
+                    }
+                }
+                rest = &after[close + 1..];
+            }
+            None => {
+                out.push_str(&rest[open..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// Flatten a TOML table into dotted keys
+fn parse_table(text: &str) -> Result<HashMap<String, Entry>, String> {
+    let value: toml::Table = toml::from_str(text).map_err(|e| e.to_string())?;
+    let mut table = HashMap::new();
+    flatten("", &value, &mut table)?;
+    Ok(table)
+}
+
+fn flatten(prefix: &str, value: &toml::Table, out: &mut HashMap<String, Entry>) -> Result<(), String> {
+    for (name, value) in value {
+        let key = if prefix.is_empty() { name.clone() } else { format!("{}.{}", prefix, name) };
+        match value {
+            toml::Value::String(text) => {
+                out.insert(key, Entry::Text(text.clone()));
+            }
+            // A section with an "other" string in it is a plural, anything else is just more keys
+            toml::Value::Table(section) if section.get("other").map_or(false, toml::Value::is_str) => {
+                let mut forms = HashMap::new();
+                for (category, text) in section {
+                    match (PLURAL_CATEGORIES.contains(&category.as_str()), text.as_str()) {
+                        (true, Some(text)) => {
+                            forms.insert(category.clone(), text.to_string());
+                        }
+                        _ => return Err(format!("{}.{} isn't a plural form", key, category)),
+                    }
+                }
+                out.insert(key, Entry::Plural(forms));
+            }
+            toml::Value::Table(section) => flatten(&key, section, out)?,
+            _ => return Err(format!("{} should be text", key)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENGLISH: &str = r#"
+        greeting = "Hello"
+        [hud]
+        score = "Score: {score}"
+        [hud.seconds]
+        one = "{count} second"
+        other = "{count} seconds"
+    "#;
+
+    #[test]
+    fn placeholders_and_plurals() {
+        let strings = Strings::from_tables(&[("en", ENGLISH)]).unwrap();
+        assert_eq!(strings.get("greeting"), "Hello");
+        assert_eq!(strings.format("hud.score", &[("score", &12)]), "Score: 12");
+        assert_eq!(strings.plural("hud.seconds", 1, &[]), "1 second");
+        assert_eq!(strings.plural("hud.seconds", 0, &[]), "0 seconds");
+        // Unknown placeholders and keys stay visible
+        assert_eq!(strings.get("hud.score"), "Score: {score}");
+        assert_eq!(strings.get("nope"), "nope");
+    }
+
+    #[test]
+    fn missing_strings_fall_back_down_the_chain() {
+        let french = r#"
+            [hud.seconds]
+            one = "{count} seconde"
+            other = "{count} secondes"
+        "#;
+        let strings = Strings::from_tables(&[("fr", french), ("en", ENGLISH)]).unwrap();
+        assert_eq!(strings.locale(), "fr");
+        assert_eq!(strings.get("greeting"), "Hello");
+        // French counts 0 as singular
+        assert_eq!(strings.plural("hud.seconds", 0, &[]), "0 seconde");
+        assert_eq!(fallback_chain("pt-BR"), ["pt-BR", "pt", "en"]);
+        assert_eq!(fallback_chain("en"), ["en"]);
+    }
+
+    #[test]
+    fn slavic_plurals() {
+        assert_eq!(plural_category("ru", 21), "one");
+        assert_eq!(plural_category("ru", 11), "many");
+        assert_eq!(plural_category("ru", 23), "few");
+        assert_eq!(plural_category("pl", 21), "many");
+        assert_eq!(plural_category("ja", 1), "other");
+    }
+
+    #[test]
+    fn shipped_tables_only_use_english_keys() {
+        let english = Strings::load(LOCALES_DIR, DEFAULT_LOCALE);
+        assert_eq!(english.locale(), DEFAULT_LOCALE);
+        for locale in available(LOCALES_DIR) {
+            let path = Path::new(LOCALES_DIR).join(format!("{}.toml", locale));
+            let table = parse_table(&std::fs::read_to_string(path).unwrap()).unwrap();
+            for key in table.keys() {
+                assert!(english.has(key), "{} has {} but en doesn't", locale, key);
+            }
+        }
+        assert!(english.has("language.name"));
+    }
+}
@@ -16,7 +16,29 @@ mod game_state;
 mod char_action;
 mod gpus;
 mod input;
+mod locale;
 mod animation;
+mod settings;
+mod canvas;
+mod camera;
+mod sprite_game;
+mod assets;
+mod hot_reload;
+mod atlas;
+mod mipmaps;
+mod parallax;
+mod water;
+mod particles;
+mod popups;
+mod tween;
+mod transition;
+mod screens;
+mod ui;
+mod capture;
+mod debug_overlay;
+mod shapes;
+#[cfg(test)]
+mod render_tests;
 use rand::Rng;
 use bytemuck::{Pod, Zeroable};
 use glyphon::{
@@ -26,291 +48,96 @@ use glyphon::{
 use wgpu::{
     CompositeAlphaMode, MultisampleState, 
 };
+use std::rc::Rc;
 use std::time::{Duration, Instant};
 
-use crate::{char_action::Char_action, game_state::GameState};
-
-#[repr(C)]
-#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
-struct GPUSprite {
-    screen_region: [f32;4],
-    // Textures with a bunch of sprites are often called "sprite sheets"
-    sheet_region: [f32;4]
-}
-
-async fn run(event_loop: EventLoop<()>, window: Window) {
-    let mut gpu = gpus::WGPU::new(&window).await;
+use crate::{char_action::Char_action, settings::Settings};
+use crate::screens::MenuAction;
+use crate::sprite_game::{GPUSprite, Layer};
+use crate::transition::Transition;
+use crate::tween::{Ease, Tween, Tweens};
+
+async fn run(event_loop: EventLoop<()>, window: Window, mut settings: Settings) {
+    let mut gpu = match gpus::WGPU::new(&window, settings.present_mode()).await {
+        Ok(gpu) => gpu,
+        Err(e) => {
+            log::error!("Couldn't start the renderer: {}", e);
+            return;
+        }
+    };
     let mut gs = game_state::GameState::init_game_state();
-
-    let (fisherman_tex, mut fisherman_img) = gpus::WGPU::load_texture("fishful_content/fishful_spritesheet.png", Some("spritesheet"), &gpu.device, &gpu.queue).await.expect("Couldn't load squirrel sprite sheet");
-    let view: wgpu::TextureView = fisherman_tex.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
-
-    let (tex_bg, mut img_bg) = gpus::WGPU::load_texture("fishful_content/background.png", Some("background"), &gpu.device, &gpu.queue ).await.expect("Couldn't load background");
-    let view_bg = tex_bg.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler_bg = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
-
-    let (tex_title, mut img_title) = gpus::WGPU::load_texture("fishful_content/title.png", Some("title"), &gpu.device, &gpu.queue ).await.expect("Couldn't load title");
-    let view_title = tex_title.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler_title = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
-
-    let (tex_end_game, mut img_end_game) = gpus::WGPU::load_texture("fishful_content/end_game.png", Some("end screen"), &gpu.device, &gpu.queue ).await.expect("Couldn't load end screen");
-    let view_end_game = tex_end_game.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler_end_game = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
-
-    let (tex_instructions, mut img_instructions) = gpus::WGPU::load_texture("fishful_content/instructions.png", Some("instructions"), &gpu.device, &gpu.queue ).await.expect("Couldn't load instructions");
-    let view_instructions = tex_instructions.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler_instructions = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
+    gs.secs_left = settings.round_length;
+    gs.round_length = settings.round_length;
+    let mut settings_menu = settings::SettingsMenu::default();
+    // Every string on screen comes from here, in the language picked in the settings
+    let mut strings = Rc::new(locale::Strings::load(locale::LOCALES_DIR, &settings.locale));
+    // The game draws into a fixed-size canvas which is then letterboxed into the window
+    let mut canvas = canvas::VirtualCanvas::new(&gpu, canvas::LOGICAL_WIDTH, canvas::LOGICAL_HEIGHT, settings.integer_scaling);
+
+    // Textures are loaded through the asset manager, which shares samplers and bind groups between them
+    let mut assets = assets::AssetManager::new(&gpu);
+    let fisherman_tex = assets.load_texture(&gpu, "fishful_content/fishful_spritesheet.png").await;
 
     // Set up text renderer
     let mut font_system = FontSystem::new();
     let mut cache = SwashCache::new();
     let mut atlas = TextAtlas::new(&gpu.device, &gpu.queue, gpu.config.format);
     let mut text_renderer = TextRenderer::new(&mut atlas, &gpu.device, MultisampleState::default(), None);
-    let mut buffer = Buffer::new(&mut font_system, Metrics::new(60.0, 42.0));
-    
-    let physical_width = (gpu.config.width as f64 * window.scale_factor()) as f32;
-    let physical_height = (gpu.config.height as f64 * window.scale_factor()) as f32;
     
-    buffer.set_size(&mut font_system, physical_width, physical_height);
-
-    let score_text = format!("Score: {}     Timer: {}", gs.score, gs.secs_left);
-    buffer.set_text(&mut font_system, &score_text, Attrs::new().family(Family::SansSerif), Shaping::Advanced);
-    buffer.shape_until_scroll(&mut font_system);
-
-    // Load the shaders from disk.  Remember, shader programs are things we compile for
-    // our GPU so that it can compute vertices and colorize fragments.
-    let shader = gpu.device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: None,
-        // Cow is a "copy on write" wrapper that abstracts over owned or borrowed memory.
-        // Here we just need to use it since wgpu wants "some text" to compile a shader from.
-        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
-    });
-
-    let texture_bind_group_layout =
-    gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        label: None,
-        // This bind group's first entry is for the texture and the second is for the sampler.
-        entries: &[
-            // The texture binding
-            wgpu::BindGroupLayoutEntry {
-                // This matches the binding number in the shader
-                binding: 0,
-                // Only available in the fragment shader
-                visibility: wgpu::ShaderStages::FRAGMENT,
-                // It's a texture binding
-                ty: wgpu::BindingType::Texture {
-                    // We can use it with float samplers
-                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    // It's being used as a 2D texture
-                    view_dimension: wgpu::TextureViewDimension::D2,
-                    // This is not a multisampled texture
-                    multisampled: false,
-                },
-                // This is not an array texture, so it has None for count
-                count: None,
-            },
-            // The sampler binding
-            wgpu::BindGroupLayoutEntry {
-                // This matches the binding number in the shader
-                binding: 1,
-                // Only available in the fragment shader
-                visibility: wgpu::ShaderStages::FRAGMENT,
-                // It's a sampler
-                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                // No count
-                count: None,
-            },
-        ],
-    });
-    let sprite_bind_group_layout =
-    gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        label: None,
-        entries: &[
-            // The camera binding
-            wgpu::BindGroupLayoutEntry {
-                // This matches the binding in the shader
-                binding: 0,
-                // Available in vertex shader
-                visibility: wgpu::ShaderStages::VERTEX,
-                // It's a buffer
-                ty: wgpu::BindingType::Buffer {
-                    // Specifically, a uniform buffer
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None
-                },
-                // No count, not a buffer array binding
-                count: None,
-            },
-            // The sprite buffer binding
-            wgpu::BindGroupLayoutEntry {
-                // This matches the binding in the shader
-                binding: 1,
-                // Available in vertex shader
-                visibility: wgpu::ShaderStages::VERTEX,
-                // It's a buffer
-                ty: wgpu::BindingType::Buffer {
-                    // Specifically, a storage buffer
-                    ty: wgpu::BufferBindingType::Storage{read_only:true},
-                    has_dynamic_offset: false,
-                    min_binding_size: None
-                },
-                // No count, not a buffer array binding
-                count: None,
-            },
-        ],
-    });
-    let pipeline_layout = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: None,
-        bind_group_layouts: &[&sprite_bind_group_layout, &texture_bind_group_layout],
-        push_constant_ranges: &[],
-    });
-
-    let pipeline_layout_bg = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: None,
-        bind_group_layouts: &[&texture_bind_group_layout],
-        push_constant_ranges: &[],
-    });
-
-    let texture_bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &texture_bind_group_layout,
-        entries: &[
-            // One for the texture, one for the sampler
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&view),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&sampler),
-            },
-        ],
-    });
-
-    let tex_bg_bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &texture_bind_group_layout,
-        entries: &[
-            // One for the texture, one for the sampler
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&view_bg),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&sampler_bg),
-            },
-        ],
-    });
-
-    let tex_title_bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &texture_bind_group_layout,
-        entries: &[
-            // One for the texture, one for the sampler
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&view_title),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&sampler_title),
-            },
-        ],
-    });
-
-    let tex_end_bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &texture_bind_group_layout,
-        entries: &[
-            // One for the texture, one for the sampler
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&view_end_game),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&sampler_end_game),
-            },
-        ],
-    });
-
-    let tex_instructions_bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &texture_bind_group_layout,
-        entries: &[
-            // One for the texture, one for the sampler
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&view_instructions),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&sampler_instructions),
-            },
-        ],
-    });
-
-    let render_pipeline = gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: None,
-        layout: Some(&pipeline_layout),
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: "vs_main",
-            buffers: &[],
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: "fs_main",
-            targets: &[Some(gpu.config.format.into())],
-        }),
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState::default(),
-        multiview: None,
-    });
-
-    let render_pipeline_bg = gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: None,
-        layout: Some(&pipeline_layout_bg),
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: "vs_main_bg",
-            buffers: &[],
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: "fs_main_bg",
-            targets: &[Some(gpu.config.format.into())],
-        }),
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState::default(),
-        multiview: None,
-    });
+    // Text is laid out on the canvas, so it uses the logical size rather than the window size
+    let physical_width = canvas.width as f32;
+    let physical_height = canvas.height as f32;
+
+    // The settings screen is just one big block of text
+    let mut menu_buffer = Buffer::new(&mut font_system, Metrics::new(24.0, 28.0));
+    menu_buffer.set_size(&mut font_system, physical_width, physical_height);
+    menu_buffer.set_text(&mut font_system, &settings_menu.text(&settings, &strings), Attrs::new().family(Family::SansSerif), Shaping::Advanced);
+    menu_buffer.shape_until_scroll(&mut font_system);
+
+    // The "+2"s that float up where fish are landed. They're in the world, so they get their own text
+    // renderer, drawn over the world but under the HUD.
+    let mut world_popups = popups::WorldPopups::new(&mut font_system, 8, 40.0);
+    let mut world_text_renderer = TextRenderer::new(&mut atlas, &gpu.device, MultisampleState::default(), None);
+    // Hitboxes and a frame-time graph, drawn as plain lines over everything but the text
+    let mut shapes = shapes::ShapeRenderer::new(&gpu);
+    let mut debug_overlay = debug_overlay::DebugOverlay::new(&mut font_system);
+    // The HUD drops in from above when a round starts and the settings menu slides in from the left
+    let mut hud_top: f32 = 10.0;
+    let mut hud_tweens: Tweens<f32> = Tweens::new();
+    let mut menu_left: f32 = 40.0;
+    let mut menu_tweens: Tweens<f32> = Tweens::new();
+
+    // In dev mode, content and shader changes are picked up while the game runs,
+    // and a shader that doesn't compile shows its error here instead of crashing
+    let mut file_watcher = hot_reload::dev_mode_enabled().then(|| hot_reload::FileWatcher::new(&hot_reload::WATCHED_PATHS));
+    let mut dev_error_buffer = Buffer::new(&mut font_system, Metrics::new(18.0, 22.0));
+    dev_error_buffer.set_size(&mut font_system, physical_width - 20.0, physical_height);
+    let mut dev_error: Option<String> = None;
+
+    // The sprite renderer owns the shader and pipeline; every texture gets its own sprite group,
+    // and the groups are drawn together in layer order.
+    let mut sprite_renderer = sprite_game::SpriteRenderer::new(&gpu, &assets);
 
     let mut input = input::Input::default();
     let mut nut_count = 0;
     let mut color = image::Rgba([255,0,0,255]);
     let mut brush_size = 10_i32;
-    let (img_bg_w, img_bg_h) = img_bg.dimensions();
     let mut start = Instant::now();
     let mut last_second = Instant::now();
-    let time_limit = gs.secs_left;
+    let mut time_limit = gs.secs_left;
 
 
-    #[repr(C)]
-    #[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
-    struct GPUCamera {
-        screen_pos: [f32;2],
-        screen_size: [f32;2]
-    }
-    let camera = GPUCamera {
-        screen_pos: [0.0, 0.0],
-        screen_size: [1024.0, 768.0],
-    };
+    // The world is wider than the screen, so the camera follows the fisherman along the pier
+    let mut camera_controller = camera::CameraController::new([canvas::LOGICAL_WIDTH as f32, canvas::LOGICAL_HEIGHT as f32]);
+    camera_controller.dead_zone = [120.0, 0.0];
+    camera_controller.smoothing = 6.0;
+    camera_controller.bounds = Some(camera::WorldBounds {
+        min: [0.0, 0.0],
+        max: [game_state::WORLD_WIDTH, canvas::LOGICAL_HEIGHT as f32],
+    });
+    let mut camera = camera_controller.gpu_camera();
+    let mut last_update = Instant::now();
+    let mut capture = capture::Capture::new();
 
     let sprite_sheet_dimensions = (542.0, 356.0);
 
@@ -394,13 +221,6 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
         [291.0/sprite_sheet_dimensions.0, 255.0/sprite_sheet_dimensions.1, 100.0/sprite_sheet_dimensions.0, 100.0/sprite_sheet_dimensions.1],
     ];
 
-    // line is just one frame
-    let mut line_frames: Vec<[f32; 4]> = vec![
-
-        // frame 1 sheet position
-        [381.0/sprite_sheet_dimensions.0, 240.0/sprite_sheet_dimensions.1, 10.0/sprite_sheet_dimensions.0, 8.0/sprite_sheet_dimensions.1],
-    ];
-
     let mut fish_frames: Vec<[f32; 4]> = vec![
         //fish 1 positions
         [0.0/sprite_sheet_dimensions.0, 1.0/sprite_sheet_dimensions.1, 12.0/sprite_sheet_dimensions.0, 6.0/sprite_sheet_dimensions.1],
@@ -417,43 +237,22 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
     ];
     let mut sprites: Vec<GPUSprite> = vec![
         // FISHERMAN
-    GPUSprite {
-        screen_region: [100.0, 600.0, 100.0, 100.0],
-        sheet_region: fisherman_idle_frames[0],   
-    },
+    GPUSprite::new(
+        [100.0, 600.0, 100.0, 100.0],
+        fisherman_idle_frames[0],
+        Layer::Pier.z(),
+    ),
         // HOOK
         // start hook out by not being visible (taking up 0 width and height)
-    GPUSprite {
-        screen_region: [20.0, 200.0, 0.0, 0.0],
-        sheet_region: hook_frames[0],   
-    },
-    // FISHING LINE
-    // have line also not be visible at start
-    GPUSprite {
-        screen_region: [100.0, 540.0, 0.0, 0.0],
-        sheet_region: line_frames[0],
-    },
-        // FISH1A
-    GPUSprite {
-        screen_region: [20.0, 20.0, 50.0, 30.0],
-        sheet_region: fish_frames[0],
-    },
-    //     // FISH1B
-    // GPUSprite {
-    //     screen_region: [20.0, 40.0, 50.0, 30.0],
-    //     sheet_region: fish_frames[1],
-    // },
-        // FISH2A
-    GPUSprite {
-        screen_region: [20.0, 80.0, 50.0, 30.0],
-        sheet_region: large_fish_frames[0],
-    },
-    //     // FISH2B
-    // GPUSprite {
-    //     screen_region: [20.0, 80.0, 50.0, 30.0],
-    //     sheet_region: fish_frames[3],
-    // },
+    GPUSprite::new(
+        [20.0, 200.0, 0.0, 0.0],
+        hook_frames[0],
+        Layer::Fish.z_offset(0.6),
+    ),
+    // the fish get pushed on after this, once per school
     ];
+    let fish_sheet_region = fish_frames[0];
+    let large_fish_sheet_region = large_fish_frames[0];
 
     let fisherman_idle_animation: Animation = Animation {
         states: fisherman_idle_frames,
@@ -510,24 +309,13 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
         is_done: false,
     };
 
-    let line_animation: Animation = Animation {
-        states: line_frames,
-        frame_counter: 0,
-        rate: 50,
-        state_number: 0,
-        is_facing_left: false,
-        sprite_width: sprites[2].sheet_region[2],
-        is_looping: true,
-        is_done: false,
-    };
-
     let fish_animation: Animation = Animation {
         states: fish_frames,
         frame_counter: 0,
         rate: 12,
         state_number: 0,
         is_facing_left: false,
-        sprite_width: sprites[3].sheet_region[2],
+        sprite_width: fish_sheet_region[2],
         is_looping: true,
         is_done: false,
     };
@@ -538,7 +326,7 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
         rate: 12,
         state_number: 0,
         is_facing_left: false,
-        sprite_width: sprites[4].sheet_region[2],
+        sprite_width: large_fish_sheet_region[2],
         is_looping: true,
         is_done: false,
     };
@@ -552,7 +340,10 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
         false,
         0,
     );
+    fisherman.set_x_range(0.0, game_state::WORLD_WIDTH - fisherman.screen_region[2]);
 
+    // The hook drops in when it's cast instead of just appearing
+    let mut hook_tweens: Tweens<Char_action> = Tweens::new();
     let mut hook: Char_action = char_action::Char_action::new(
         sprites[1].screen_region,
         sprites[1].sheet_region,
@@ -563,66 +354,102 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
         1
     );
 
-    let mut line: Char_action = char_action::Char_action::new(
-        sprites[2].screen_region,
-        sprites[2].sheet_region,
-        vec![line_animation],
-        0,
-        3.0,
-        false,
-        2
-    );
-
-    let mut fish: Char_action = char_action::Char_action::new(
-        sprites[3].screen_region,
-        sprites[3].sheet_region,
-        vec![fish_animation],
-        0,
-        2.0,
-        false,
-        3
-    );
-
-    let mut large_fish: Char_action = char_action::Char_action::new(
-        sprites[4].screen_region,
-        sprites[4].sheet_region,
-        vec![large_fish_animation],
-        0,
-        3.5,
-        false,
-        4
-    );
+    // Spread the fish out along the world: one small fish and one large fish for every screen's worth of water,
+    // each swimming back and forth in its own stretch.
+    let mut small_fish: Vec<Char_action> = Vec::new();
+    let mut large_fish: Vec<Char_action> = Vec::new();
+    let screens = (game_state::WORLD_WIDTH / canvas::LOGICAL_WIDTH as f32).ceil() as usize;
+    for screen in 0..screens {
+        let min_x = screen as f32 * canvas::LOGICAL_WIDTH as f32;
+        let max_x = min_x + canvas::LOGICAL_WIDTH as f32;
+
+        // FISH1
+        sprites.push(GPUSprite::new(
+            [min_x + 20.0, 20.0, 50.0, 30.0],
+            fish_sheet_region,
+            Layer::Fish.z(),
+        ));
+        let mut fish = char_action::Char_action::new(
+            sprites[sprites.len() - 1].screen_region,
+            fish_sheet_region,
+            vec![fish_animation.clone()],
+            0,
+            2.0,
+            false,
+            sprites.len() - 1
+        );
+        fish.set_x_range(min_x, max_x);
+        small_fish.push(fish);
+
+        // FISH2
+        sprites.push(GPUSprite::new(
+            [min_x + 20.0, 80.0, 50.0, 30.0],
+            large_fish_sheet_region,
+            Layer::Fish.z(),
+        ));
+        let mut fish = char_action::Char_action::new(
+            sprites[sprites.len() - 1].screen_region,
+            large_fish_sheet_region,
+            vec![large_fish_animation.clone()],
+            0,
+            3.5,
+            false,
+            sprites.len() - 1
+        );
+        fish.set_x_range(min_x, max_x);
+        large_fish.push(fish);
+    }
     
-    let buffer_camera = gpu.device.create_buffer(&wgpu::BufferDescriptor{
-        label: None,
-        size: bytemuck::bytes_of(&camera).len() as u64,
-        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false
-    });
-    let buffer_sprite = gpu.device.create_buffer(&wgpu::BufferDescriptor{
-        label: None,
-        size: bytemuck::cast_slice::<_,u8>(&sprites).len() as u64,
-        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false
-    });
+    // Seaweed in front of the fish, so they can swim behind it
+    let seaweed_frame = [290.0/sprite_sheet_dimensions.0, 125.0/sprite_sheet_dimensions.1, 34.0/sprite_sheet_dimensions.0, 33.0/sprite_sheet_dimensions.1];
+    let mut seaweed_x = 180.0;
+    while seaweed_x < game_state::WORLD_WIDTH {
+        sprites.push(GPUSprite::new(
+            [seaweed_x, 0.0, 136.0, 132.0],
+            seaweed_frame,
+            Layer::ForegroundWater.z(),
+        ));
+        seaweed_x += 560.0;
+    }
 
-    gpu.queue.write_buffer(&buffer_camera, 0, bytemuck::bytes_of(&camera));
-    gpu.queue.write_buffer(&buffer_sprite, 0, bytemuck::cast_slice(&sprites));
+    let game_group = sprite_renderer.add_sprite_group(&gpu, &assets, &fisherman_tex, sprites, camera);
+    let mut swing_time: f32 = 0.0;
+    // Bubbles behind every fish, plus splashes and sparkles fired off by the game below
+    let mut particle_system = particles::ParticleSystem::new(512);
+    particle_system.add_to_renderer(&gpu, &mut assets, &mut sprite_renderer, camera);
+    let bubble_emitters: Vec<particles::EmitterId> = small_fish
+        .iter()
+        .chain(large_fish.iter())
+        .map(|fish| particle_system.add_emitter(particles::ParticleSettings::bubbles(), fish_tail(fish), 2.5))
+        .collect();
+
+    // The play screen is drawn through a wavy water filter, unless it's turned off in the settings
+    let mut water = water::WaterEffect::new(&gpu, canvas.width, canvas.height, settings.water);
+    // Changing screens blends the old one into the new one instead of snapping
+    let mut transitions = transition::ScreenTransitions::new(&gpu, canvas.width, canvas.height);
+
+    // The play screen's HUD: score and time left on a panel, and a button to end the round early
+    let (mut hud, hud_panel, end_round_button) = screens::hud(&mut font_system, fish_sheet_region, hud_top);
+    hud.add_to_renderer(&gpu, &mut assets, &mut sprite_renderer, &fisherman_tex);
+
+    // The other screens are menus drawn the same way, over the play screen's background
+    let mut title_screen = screens::MenuScreen::title(&mut font_system);
+    let mut instructions_screen = screens::MenuScreen::instructions(&mut font_system);
+    let mut end_screen = screens::MenuScreen::end(&mut font_system);
+    for screen in [&mut title_screen, &mut instructions_screen, &mut end_screen] {
+        screen.add_to_renderer(&gpu, &mut assets, &mut sprite_renderer, &fisherman_tex);
+    }
 
-    let sprite_bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &sprite_bind_group_layout,
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: buffer_camera.as_entire_binding()
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: buffer_sprite.as_entire_binding()
-            }
-        ],
-    });
+    // The play screen's background is layers of water and sky that scroll at their own speeds
+    let parallax_config = parallax::ParallaxConfig::load(parallax::PARALLAX_PATH);
+    let mut parallax = parallax::ParallaxBackground::new(
+        &gpu,
+        &mut assets,
+        &mut sprite_renderer,
+        &parallax_config,
+        [canvas::LOGICAL_WIDTH as f32, canvas::LOGICAL_HEIGHT as f32],
+    )
+    .await;
 
     // Now our setup is all done and we can kick off the windowing event loop.
     // This closure is a "move closure" that claims ownership over variables used within its scope.
@@ -646,63 +473,173 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
             } => {
                 // Reconfigure the surface with the new size
                 gpu.resize(size);
+                // and work out where the canvas goes in the resized window
+                canvas.resize(size.width, size.height);
                 // On MacOS the window needs to be redrawn manually after resizing
                 window.request_redraw();
             }
             Event::RedrawRequested(_) => {
 
                 // Then send the data to the GPU!
-                gpu.queue.write_buffer(&buffer_camera, 0, bytemuck::bytes_of(&camera));
-                gpu.queue.write_buffer(&buffer_sprite, 0, bytemuck::cast_slice(&sprites));
+                sprite_renderer.set_camera(&gpu, game_group, camera);
+                sprite_renderer.upload_sprites(&gpu, game_group);
+                parallax.sync(&gpu, &mut sprite_renderer, camera.screen_pos);
+                particle_system.sync(&gpu, &mut sprite_renderer, camera);
+                // Only show the sprite groups that belong on this screen
+                // Every screen has the water and sky behind it
+                parallax.set_visible(&mut sprite_renderer, true);
+                title_screen.set_visible(&mut sprite_renderer, gs.game_screen == 0);
+                instructions_screen.set_visible(&mut sprite_renderer, gs.game_screen == 1);
+                end_screen.set_visible(&mut sprite_renderer, gs.game_screen == 3);
+                sprite_renderer.set_visible(game_group, gs.game_screen == 2);
+                particle_system.set_visible(&mut sprite_renderer, gs.game_screen == 2);
+                hud.set_visible(&mut sprite_renderer, gs.game_screen == 2);
+                hud.sync(&gpu, &mut sprite_renderer);
+                title_screen.sync(&gpu, &mut sprite_renderer);
+                instructions_screen.sync(&gpu, &mut sprite_renderer);
+                end_screen.sync(&gpu, &mut sprite_renderer);
+                // The line runs from the rod down to the hook, over the far water but behind the hook, the
+                // fish on it, the seaweed and the pier
+                if gs.game_screen == 2 && gs.is_currently_casted {
+                    let [x, y, w, h] = hook.screen_region;
+                    let points = fishing_line(rod_tip(&fisherman), [x + w / 2.0, y + h], swing_time);
+                    shapes.polyline(shapes::Brush::world(Layer::FarWater), &points, 2.0, [0.9, 0.9, 0.85, 0.9]);
+                }
+                if debug_overlay.enabled {
+                    // Only the play screen has anything in the world to look at
+                    let mut entities = Vec::new();
+                    if gs.game_screen == 2 {
+                        entities.push(debug_entity("fisherman".to_string(), &fisherman, fisherman.screen_region, [0.0, 0.0]));
+                        let swimming = |fish: &Char_action| {
+                            if fish.caught { [0.0, 0.0] } else if fish.facing_left { [-fish.speed, 0.0] } else { [fish.speed, 0.0] }
+                        };
+                        for (i, fish) in small_fish.iter().enumerate() {
+                            entities.push(debug_entity(format!("small fish {}", i), fish, fish_hitbox(fish.screen_region), swimming(fish)));
+                        }
+                        for (i, fish) in large_fish.iter().enumerate() {
+                            entities.push(debug_entity(format!("large fish {}", i), fish, fish_hitbox(fish.screen_region), swimming(fish)));
+                        }
+                    }
+                    let mouse = input.mouse_pos();
+                    debug_overlay.draw(
+                        &mut font_system,
+                        &mut shapes,
+                        &camera,
+                        [canvas.width as f32, canvas.height as f32],
+                        &debug_overlay::DebugScene {
+                            entities: &entities,
+                            hook_box: (gs.game_screen == 2 && gs.is_currently_casted)
+                                .then(|| hook_hitbox(hook.screen_region)),
+                            // The surface the hook reels in at, and the top of where the large fish swim
+                            depth_bands: if gs.game_screen == 2 { &[500.0, 100.0] } else { &[] },
+                            world_width: game_state::WORLD_WIDTH,
+                            mouse: [mouse.x as f32, mouse.y as f32],
+                        },
+                    );
+                }
+                shapes.prepare(&gpu, &camera, [canvas.width as f32, canvas.height as f32]);
                 // ...all the drawing stuff goes here...
                 window.request_redraw();
 
                 // Leave now_keys alone, but copy over all changed keys
                 input.next_frame();
 
+                let menu_area = TextArea {
+                    buffer: &menu_buffer,
+                    left: menu_left,
+                    top: 20.0,
+                    scale: 1.0,
+                    bounds: TextBounds {
+                        left: 0,
+                        top: 0,
+                        right: canvas.width as i32,
+                        bottom: canvas.height as i32,
+                    },
+                    default_color: Color::rgb(255, 255, 255),
+                };
                 text_renderer.prepare(
                     &gpu.device,
                     &gpu.queue,
                     &mut font_system,
                     &mut atlas,
                     Resolution {
-                        width: gpu.config.width,
-                        height: gpu.config.height,
+                        width: canvas.width,
+                        height: canvas.height,
                     },
-                    [TextArea {
-                        buffer: &buffer,
-                        left: 10.0,
-                        top: 10.0,
-                        scale: 1.0,
-                        bounds: TextBounds {
-                            left: 0,
-                            top: 0,
-                            right: 600,
-                            bottom: 160,
-                        },
-                        default_color: Color::rgb(255, 255, 255),
-                    }],
+                    // The HUD and the menus only show on their own screens, but a dev mode error shows everywhere
+                    hud.text_areas()
+                        .chain(title_screen.text_areas())
+                        .chain(instructions_screen.text_areas())
+                        .chain(end_screen.text_areas())
+                        .chain(debug_overlay.text_areas([canvas.width as f32, canvas.height as f32]))
+                        .chain([
+                        (gs.game_screen == 4).then_some(menu_area),
+                        dev_error.is_some().then_some(TextArea {
+                            buffer: &dev_error_buffer,
+                            left: 10.0,
+                            top: 10.0,
+                            scale: 1.0,
+                            bounds: TextBounds {
+                                left: 0,
+                                top: 0,
+                                right: canvas.width as i32,
+                                bottom: canvas.height as i32,
+                            },
+                            default_color: Color::rgb(255, 80, 80),
+                        }),
+                    ].into_iter().flatten()),
+                    &mut cache,
+                ).unwrap();
+                // Popups go through the same camera as the sprites, so they stay where the fish were landed
+                world_text_renderer.prepare(
+                    &gpu.device,
+                    &gpu.queue,
+                    &mut font_system,
+                    &mut atlas,
+                    Resolution {
+                        width: canvas.width,
+                        height: canvas.height,
+                    },
+                    world_popups
+                        .text_areas(&camera, [canvas.width as f32, canvas.height as f32])
+                        .filter(|_| gs.game_screen == 2),
                     &mut cache,
                 ).unwrap();
 
                 // If the window system is telling us to redraw, let's get our next swapchain image
-                let frame = gpu.surface
-                    .get_current_texture()
-                    .expect("Failed to acquire next swap chain texture");
+                let frame = match gpu.next_frame() {
+                    Ok(Some(frame)) => frame,
+                    // Minimised, or the surface needed reconfiguring: try again next frame
+                    Ok(None) => {
+                        window.request_redraw();
+                        return;
+                    }
+                    Err(e) => {
+                        log::error!("{}", e);
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                };
                 // And set up a texture view onto it, since the GPU needs a way to interpret those
                 // image bytes for writing.
-                let view = frame
+                let frame_view = frame
                     .texture
                     .create_view(&wgpu::TextureViewDescriptor::default());
                 // From the queue we obtain a command encoder that lets us issue GPU commands
                 let mut encoder =
                 gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+                // Every screen is over the water now, so the effect goes everywhere; menus are drawn after it
+                let water_on = settings.water_effect;
+                // Mid-transition the new screen is drawn off to the side and blended in at the end
+                let screen_view = if transitions.is_running() { transitions.scene_view() } else { &canvas.view };
                 {
                     
+                    // Everything draws into the logical-size canvas first (or into the water effect's
+                    // texture, to be filtered into the canvas)...
                     let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                         label: None,
                         color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                            view: &view,
+                            view: if water_on { water.scene_view() } else { screen_view },
                             resolve_target: None,
                             ops: wgpu::Operations {
                                 // When loading this texture for writing, the GPU should clear
@@ -715,58 +652,47 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                         depth_stencil_attachment: None,
                     });
                     
-                    if gs.game_screen == 0 {
-                        rpass.set_pipeline(&render_pipeline_bg);
-                        // Attach the bind group for group 0
-                        rpass.set_bind_group(0, &tex_title_bind_group, &[]);
-                        // Now draw two triangles!
-                        rpass.draw(0..6, 0..2);
-
-                        // Now we begin a render pass.  The descriptor tells WGPU that
-                        // we want to draw onto our swapchain texture view (that's where the colors will go)
-                        // and that there's no depth buffer or stencil buffer.
-                    }
-
-                    else if gs.game_screen == 1 {
-                        rpass.set_pipeline(&render_pipeline_bg);
-                        // Attach the bind group for group 0
-                        rpass.set_bind_group(0, &tex_instructions_bind_group, &[]);
-                        // Now draw two triangles!
-                        rpass.draw(0..6, 0..2);
-                    }
-
-                    else if gs.game_screen == 2 {
-                        rpass.set_pipeline(&render_pipeline_bg);
-                        // Attach the bind group for group 0
-                        rpass.set_bind_group(0, &tex_bg_bind_group, &[]);
-                        // Now draw two triangles!
-                        rpass.draw(0..6, 0..2);
-
-                        // Now we begin a render pass.  The descriptor tells WGPU that
-                        // we want to draw onto our swapchain texture view (that's where the colors will go)
-                        // and that there's no depth buffer or stencil buffer.
-
-                        text_renderer.render(&atlas, &mut rpass).unwrap();
-
-                        rpass.set_pipeline(&render_pipeline);
-                        rpass.set_bind_group(0, &sprite_bind_group, &[]);
-                        rpass.set_bind_group(1, &texture_bind_group, &[]);
-                        rpass.draw(0..6, 0..(sprites.len() as u32));
-                    }
-
-                    else if gs.game_screen == 3 {
-                        rpass.set_pipeline(&render_pipeline_bg);
-                        
-                        // Attach the bind group for group 0
-                        rpass.set_bind_group(0, &tex_end_bind_group, &[]);
-                        // Now draw two triangles!
-                        rpass.draw(0..6, 0..2);
-                    }
-
+                    // Everything under the HUD, with the fishing line between the far water and the fish...
+                    sprite_renderer.render(&mut rpass, Layer::Sky..=Layer::FarWater);
+                    shapes.render(&mut rpass, Layer::Sky..=Layer::FarWater);
+                    sprite_renderer.render(&mut rpass, Layer::Fish..=Layer::Pier);
+                    shapes.render(&mut rpass, Layer::Fish..=Layer::Pier);
+                }
+                if water_on {
+                    water.apply(&gpu, &mut encoder, screen_view);
+                }
+                {
+                    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: None,
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: screen_view,
+                            resolve_target: None,
+                            // On top of what's already there
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Load,
+                                store: true,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                    });
+                    // ...then the popups over the world (after the water, so they don't wobble)...
+                    world_text_renderer.render(&atlas, &mut rpass).unwrap();
+                    // ...then the HUD's panels, bars and icons...
+                    sprite_renderer.render(&mut rpass, Layer::Ui..=Layer::Ui);
+                    // ...any debug lines...
+                    shapes.render(&mut rpass, Layer::Ui..=Layer::Ui);
+                    // ...and the text on top of them.
+                    text_renderer.render(&atlas, &mut rpass).unwrap();
             }
+                transitions.apply(&gpu, &mut encoder, &canvas.view);
+                // ...and then the canvas gets scaled onto the actual window.
+                canvas.present(&mut encoder, &frame_view);
+                // Grab the canvas for screenshots and the clip recorder
+                capture.record(&gpu, &mut encoder, &canvas.texture);
 
                 // Once the commands have been scheduled, we send them over to the GPU via the queue.
                 gpu.queue.submit(Some(encoder.finish()));
+                capture.update(&gpu);
                 // Then we wait for the commands to finish and tell the windowing system to
                 // present the swapchain image.
                 frame.present();
@@ -799,22 +725,92 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 event: WindowEvent::CursorMoved { position, .. },
                 ..
             } => {
-                input.handle_mouse_move(position);
+                // Mouse positions are reported in window pixels, but the game thinks in canvas pixels
+                input.handle_mouse_move(canvas.to_logical(position));
             }
             Event::MainEventsCleared => {
+                // Dev mode: reload whatever changed on disk
+                if let Some(watcher) = file_watcher.as_mut() {
+                    let changed = watcher.poll();
+                    for path in &changed {
+                        match path.extension().and_then(|ext| ext.to_str()) {
+                            Some("wgsl") => {
+                                let result = std::fs::read_to_string(path)
+                                    .map_err(|e| e.to_string())
+                                    .and_then(|source| {
+                                        if path.ends_with(hot_reload::WATER_SHADER_PATH) {
+                                            return water.reload_shader(&gpu, &source);
+                                        }
+                                        if path.ends_with(hot_reload::TRANSITION_SHADER_PATH) {
+                                            return transitions.reload_shader(&gpu, &source);
+                                        }
+                                        if path.ends_with(hot_reload::SHAPES_SHADER_PATH) {
+                                            return shapes.reload_shader(&gpu, &source);
+                                        }
+                                        sprite_renderer.reload_shader(&gpu, &source)?;
+                                        canvas.reload_shader(&gpu, &source)
+                                    });
+                                match result {
+                                    Ok(()) => {
+                                        log::info!("Reloaded {}", path.display());
+                                        dev_error = None;
+                                    }
+                                    Err(e) => {
+                                        log::error!("{}: {}", path.display(), e);
+                                        dev_error = Some(format!("{}:\n{}", path.display(), e));
+                                    }
+                                }
+                                dev_error_buffer.set_text(&mut font_system, dev_error.as_deref().unwrap_or(""), Attrs::new().family(Family::Monospace), Shaping::Advanced);
+                            }
+                            Some("png") => {
+                                // Either a texture of its own or part of a packed atlas
+                                if !pollster::block_on(assets.reload_texture(&gpu, path)) {
+                                    assets.reload_atlas_containing(&gpu, path);
+                                }
+                            }
+                            Some("toml") if path.ends_with(parallax::PARALLAX_PATH) => {
+                                let result = std::fs::read_to_string(path)
+                                    .map_err(|e| e.to_string())
+                                    .and_then(|text| toml::from_str::<parallax::ParallaxConfig>(&text).map_err(|e| e.to_string()))
+                                    .and_then(|config| parallax.reconfigure(&config));
+                                match result {
+                                    Ok(()) => {
+                                        log::info!("Reloaded {}", path.display());
+                                        dev_error = None;
+                                    }
+                                    Err(e) => {
+                                        log::error!("{}: {}", path.display(), e);
+                                        dev_error = Some(format!("{}:\n{}", path.display(), e));
+                                    }
+                                }
+                                dev_error_buffer.set_text(&mut font_system, dev_error.as_deref().unwrap_or(""), Attrs::new().family(Family::Monospace), Shaping::Advanced);
+                            }
+                            _ => log::debug!("No reloader for {}", path.display()),
+                        }
+                    }
+                    if !changed.is_empty() {
+                        sprite_renderer.refresh_textures(&gpu, &assets);
+                    }
+                }
+
 
                 let mut last_frame = Instant::now();
 
                 let mut last_frame = Instant::now();
 
                 if gs.game_screen == 2 {
+                    // The clock only starts once the transition into the round has finished, since
+                    // nothing can be played until then
+                    if transitions.is_running() {
+                        start = Instant::now();
+                        last_second = Instant::now();
+                    }
                     let mut new_now = Instant::now();
                     if new_now.duration_since(start) >= Duration::from_secs(time_limit as u64)
                     {
+                        transitions.start(&gpu, Transition::fade(1.0, [0.0, 0.0, 0.0, 1.0]), &canvas.texture);
+                        end_screen.reset_selection();
                         gs.game_screen = 3;
-                        let score_text = format!("Score: {}     Timer: {}", gs.score, gs.secs_left);
-                        // buffer.set_text(&mut font_system, &gs.score.to_string(), Attrs::new().family(Family::SansSerif), Shaping::Advanced);    
-                        buffer.set_text(&mut font_system, &score_text, Attrs::new().family(Family::SansSerif), Shaping::Advanced);
                         last_second = Instant::now();
 
                     }
@@ -825,9 +821,6 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
 
                     if new_now.duration_since(last_second).as_secs() >= 1 {
                         gs.secs_left -= 1;
-                        let score_text = format!("Score: {}     Timer: {}", gs.score, gs.secs_left);
-                        // buffer.set_text(&mut font_system, &gs.score.to_string(), Attrs::new().family(Family::SansSerif), Shaping::Advanced);    
-                        buffer.set_text(&mut font_system, &score_text, Attrs::new().family(Family::SansSerif), Shaping::Advanced);
                         last_second = Instant::now();
 
                     }
@@ -838,6 +831,7 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 
 
                 
+                for fish in small_fish.iter_mut() {
                 if fish.caught {
                     fish.vibrate_counter += 1;
                     if fish.vibrate_counter > 30{
@@ -860,7 +854,9 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                         fish.move_right();
                     }   
                 }
+                }
                 
+                for large_fish in large_fish.iter_mut() {
                 if large_fish.caught {
                     large_fish.vibrate_counter += 1;
                     if large_fish.vibrate_counter > 30{
@@ -882,115 +878,206 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                         large_fish.deep_move_right();
                     }
                 }
+                }
+
+                // Captures and the debug overlay work on every screen, except while the settings screen is
+                // waiting for a key to bind
+                if !settings_menu.rebinding {
+                    if input.is_key_pressed(settings.keys.screenshot) {
+                        capture.request_screenshot();
+                    }
+                    if input.is_key_pressed(settings.keys.record_clip) {
+                        capture.request_clip();
+                    }
+                    if input.is_key_pressed(settings.keys.debug_overlay) {
+                        debug_overlay.toggle();
+                    }
+                }
 
-                if input.is_key_down(winit::event::VirtualKeyCode::Return) && gs.game_screen==0 {
+                // The HUD's offsets come from its slide-in tween
+                hud.set_offset(hud_panel, [10.0, hud_top]);
+                hud.set_offset(end_round_button, [-10.0, hud_top]);
+                // Only the screen being shown takes input, and only once it has finished coming in
+                let screen_model = screens::ScreenModel {
+                    score: gs.score,
+                    secs_left: gs.secs_left,
+                    round_length: gs.round_length,
+                    keys: settings.keys.clone(),
+                    strings: strings.clone(),
+                };
+                hud.update(&mut font_system, &screen_model, &input);
+                let accepting = !transitions.is_running();
+                let title_choice = title_screen.update(&mut font_system, &screen_model, &input, accepting && gs.game_screen == 0);
+                let instructions_choice = instructions_screen.update(&mut font_system, &screen_model, &input, accepting && gs.game_screen == 1);
+                let end_choice = end_screen.update(&mut font_system, &screen_model, &input, accepting && gs.game_screen == 3);
+
+                if transitions.is_running() {
+                    // Keys do nothing while the screen is changing
+                }
+
+                else if gs.game_screen == 4 {
+                    let change = settings_menu.update(&input, &mut settings);
+                    match change {
+                        settings::SettingsChange::Resolution => {
+                            // The Resized event that follows takes care of reconfiguring the surface
+                            window.set_inner_size(settings.window_size());
+                        }
+                        settings::SettingsChange::Fullscreen => {
+                            window.set_fullscreen(settings.fullscreen_mode());
+                        }
+                        settings::SettingsChange::PresentMode => {
+                            gpu.set_present_mode(settings.present_mode());
+                        }
+                        settings::SettingsChange::Scaling => {
+                            canvas.set_integer_scaling(settings.integer_scaling, gpu.config.width, gpu.config.height);
+                        }
+                        settings::SettingsChange::Close => {
+                            if let Err(e) = settings.save(settings::SETTINGS_PATH) {
+                                log::error!("Couldn't save settings: {}", e);
+                            }
+                            settings_menu = settings::SettingsMenu::default();
+                            transitions.start(&gpu, Transition::wipe(0.4), &canvas.texture);
+                            title_screen.reset_selection();
+                            gs.game_screen = 0;
+                        }
+                        // Everything bound to the strings picks the new ones up on its next update
+                        settings::SettingsChange::Locale => {
+                            strings = Rc::new(locale::Strings::load(locale::LOCALES_DIR, &settings.locale));
+                        }
+                        settings::SettingsChange::Value | settings::SettingsChange::None => {}
+                    }
+                    if change != settings::SettingsChange::None {
+                        menu_buffer.set_text(&mut font_system, &settings_menu.text(&settings, &strings), Attrs::new().family(Family::SansSerif), Shaping::Advanced);
+                    }
+                }
+
+                else if title_choice == Some(MenuAction::Quit) || end_choice == Some(MenuAction::Quit) {
+                    *control_flow = ControlFlow::Exit;
+                }
+
+                else if (input.is_key_pressed(settings.keys.settings) || title_choice == Some(MenuAction::Settings)) && gs.game_screen==0 {
+                    transitions.start(&gpu, Transition::wipe(0.4), &canvas.texture);
+                    gs.game_screen = 4;
+                    menu_tweens.replace(Tween::new(-(canvas::LOGICAL_WIDTH as f32) / 2.0, 40.0, 0.35).ease(Ease::CubicOut));
+                }
+
+                else if title_choice == Some(MenuAction::Start) {
                     start = Instant::now();
                     last_second = Instant::now();
+                    transitions.start(&gpu, Transition::crossfade(0.5), &canvas.texture);
                     gs.game_screen = 1;
                 }
 
-                else if input.is_key_down(winit::event::VirtualKeyCode::P) && gs.game_screen==1 {
+                else if (input.is_key_down(settings.keys.play) || instructions_choice == Some(MenuAction::Play)) && gs.game_screen==1 {
                     start = Instant::now();
+                    gs.score = 0;
+                    time_limit = settings.round_length;
+                    gs.secs_left = settings.round_length;
+                    gs.round_length = settings.round_length;
+                    transitions.start(&gpu, Transition::iris(0.8), &canvas.texture);
                     gs.game_screen = 2;
+                    // (once the iris has opened up a bit)
+                    hud_tweens.replace(Tween::new(-60.0, 10.0, 0.5).ease(Ease::BackOut).delay(0.5));
                 }
 
-                else if input.is_key_down(winit::event::VirtualKeyCode::E) && gs.game_screen==2 {
+                else if (input.is_key_down(settings.keys.end_round) || hud.clicked(end_round_button)) && gs.game_screen==2 {
+                    transitions.start(&gpu, Transition::fade(1.0, [0.0, 0.0, 0.0, 1.0]), &canvas.texture);
+                    end_screen.reset_selection();
                     gs.game_screen = 3;
                 }
 
-                else if input.is_key_down(winit::event::VirtualKeyCode::A) && gs.game_screen==3 {
-                    gs.game_screen = 0;
+                // Playing again skips the title screen
+                else if (input.is_key_down(settings.keys.restart) || end_choice == Some(MenuAction::PlayAgain)) && gs.game_screen==3 {
+                    transitions.start(&gpu, Transition::iris(0.8), &canvas.texture);
+                    gs.game_screen = if end_choice == Some(MenuAction::PlayAgain) { 1 } else { 0 };
+                    title_screen.reset_selection();
                     gs.is_currently_casted = false;
                     hook.screen_region = [20.0, 200.0, 0.0, 0.0];
                     fisherman.screen_region = [100.0, 600.0, 100.0, 100.0];
-                    line.screen_region = [100.0, 540.0, 0.0, 0.0];
-                    fish.reset_x();
-                    large_fish.reset_x();
+                    for fish in small_fish.iter_mut().chain(large_fish.iter_mut()) {
+                        fish.caught = false;
+                        fish.reset_x();
+                    }
+                    particle_system.clear();
+                    hook_tweens.stop();
+                    world_popups.clear();
+                    camera_controller.follow([fisherman.screen_region[0] + fisherman.screen_region[2] / 2.0, fisherman.screen_region[1]]);
+                    camera_controller.snap_to_target();
                 }
 
-                else if input.is_key_down(winit::event::VirtualKeyCode::Left) {
+                else if input.is_key_down(settings.keys.left) {
                     if !gs.is_currently_casted{
                         fisherman.set_animation_index(1);
                         fisherman.face_left();
                         fisherman.walk();
                     }
                 }
-                else if input.is_key_down(winit::event::VirtualKeyCode::Right) {
+                else if input.is_key_down(settings.keys.right) {
                     if !gs.is_currently_casted{
                         fisherman.set_animation_index(1);
                         fisherman.face_right();
                         fisherman.walk();
                     }
                 }
-                else if input.is_key_down(winit::event::VirtualKeyCode::Down) {
-                    if gs.is_currently_casted{
+                else if input.is_key_down(settings.keys.drop_line) {
+                    // (not while the hook is still dropping in from the cast)
+                    if gs.is_currently_casted && !hook_tweens.is_playing() {
                         hook.travel_down();
-                        line.scale_elongate(hook.screen_region[1], 250.0);
-
-                        if fish.caught {
-                            fish.travel_down();
-                        }
 
-                        if large_fish.caught {
-                            large_fish.travel_down();
+                        for fish in small_fish.iter_mut().chain(large_fish.iter_mut()) {
+                            if fish.caught {
+                                fish.travel_down();
+                            }
                         }
                     }
                 }
-                else if input.is_key_down(winit::event::VirtualKeyCode::Up) {
-                    if gs.is_currently_casted{
-                        if hook.screen_region[1] == 500.0 {
+                else if input.is_key_down(settings.keys.reel_in) {
+                    if gs.is_currently_casted && !hook_tweens.is_playing() {
+                        if hook.screen_region[1] >= 500.0 {
                             hook.hide();
-                            line.hide();
                             gs.is_currently_casted = false;
                             fisherman.set_animation_index(3);
                             fisherman.reset_current_animation();
-                            if fish.caught && !large_fish.caught {
-                                if !gs.score_changing{
-                                    gs.score += 1;
-                                    let score_text = format!("Score: {}     Timer: {}", gs.score, gs.secs_left);
-                                    // buffer.set_text(&mut font_system, &gs.score.to_string(), Attrs::new().family(Family::SansSerif), Shaping::Advanced);    
-                                    buffer.set_text(&mut font_system, &score_text, Attrs::new().family(Family::SansSerif), Shaping::Advanced);
-                                    gs.score_changing = true;
-                                }
-                                fish.caught = false;
-                                fish.reset_x();
-                            }
-                            else if large_fish.caught && !fish.caught {
-                                if !gs.score_changing{
-                                    gs.score += 2;
-                                    let score_text = format!("Score: {}     Timer: {}", gs.score, gs.secs_left);
-                                    // buffer.set_text(&mut font_system, &gs.score.to_string(), Attrs::new().family(Family::SansSerif), Shaping::Advanced);    
-                                    buffer.set_text(&mut font_system, &score_text, Attrs::new().family(Family::SansSerif), Shaping::Advanced);
+                            let small_caught = small_fish.iter().filter(|fish| fish.caught).count();
+                            let large_caught = large_fish.iter().filter(|fish| fish.caught).count();
+                            if small_caught + large_caught > 0 {
+                                // Points only count once per catch; see score_changing
+                                let scoring = !gs.score_changing;
+                                if scoring {
+                                    // one point per small fish, two per large fish, and a bonus point for landing both kinds at once
+                                    gs.score += small_caught + 2 * large_caught;
+                                    if small_caught > 0 && large_caught > 0 {
+                                        gs.score += 1;
+                                        let [x, y, w, h] = hook.screen_region;
+                                        world_popups.spawn(&mut font_system, &strings.format("popup.combo", &[("points", &1)]), [x + w / 2.0, y + h + 40.0], [255, 140, 200]);
+                                    }
                                     gs.score_changing = true;
+                                    camera_controller.shake(8.0, 0.3);
                                 }
-                                large_fish.caught = false;
-                                large_fish.reset_x();
-                            }
-                            else if large_fish.caught && fish.caught {
-                                if !gs.score_changing{
-                                    gs.score += 4;
-                                    let score_text = format!("Score: {}     Timer: {}", gs.score, gs.secs_left);
-                                    // buffer.set_text(&mut font_system, &gs.score.to_string(), Attrs::new().family(Family::SansSerif), Shaping::Advanced);    
-                                    buffer.set_text(&mut font_system, &score_text, Attrs::new().family(Family::SansSerif), Shaping::Advanced);
-                                    gs.score_changing = true;
+                                let worth = small_fish.iter_mut().map(|fish| (fish, 1)).chain(large_fish.iter_mut().map(|fish| (fish, 2)));
+                                for (fish, points) in worth {
+                                    if fish.caught {
+                                        let [x, y, w, h] = fish.screen_region;
+                                        particle_system.burst(&particles::ParticleSettings::catch_sparkles(), [x + w / 2.0, y + h / 2.0], 24);
+                                        // Each fish shows what it was worth where it came out of the water
+                                        if scoring {
+                                            world_popups.spawn(&mut font_system, &strings.format("popup.points", &[("points", &points)]), [x + w / 2.0, y + h / 2.0], [255, 220, 90]);
+                                        }
+                                        fish.caught = false;
+                                        fish.reset_x();
+                                    }
                                 }
-                                large_fish.caught = false;
-                                large_fish.reset_x();
-                                fish.caught = false;
-                                fish.reset_x();
                             }
                         }
                         hook.travel_up();
-                        if fish.caught {
-                            fish.travel_up();
-                        }
-                        if large_fish.caught {
-                            large_fish.travel_up();
+                        for fish in small_fish.iter_mut().chain(large_fish.iter_mut()) {
+                            if fish.caught {
+                                fish.travel_up();
+                            }
                         }
-                        line.scale_elongate(hook.screen_region[1], 250.0);
                     }
                 }
-                else if input.is_key_down(winit::event::VirtualKeyCode::Space) {
+                else if input.is_key_down(settings.keys.cast) {
                     if !gs.is_currently_casted{
                         fisherman.set_animation_index(2);
                         gs.is_currently_casted = true;
@@ -1002,24 +1089,27 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                         // hardcoded the offset bc of time
                         if fisherman.facing_left {
                             hook.screen_region[0] = fisherman.screen_region[0] - 38.0;
-                            line.screen_region[0] = fisherman.screen_region[0] + 12.0;
                         }
                         else {
                             hook.screen_region[0] = fisherman.screen_region[0] + 38.0;
-                            line.screen_region[0] = fisherman.screen_region[0] + 90.0;
                         }
                         
-                        hook.screen_region[1] = fisherman.screen_region[1] - 100.0;
-
-                        // spawn the fishing line
-                        line.screen_region[2] = 10.0;
-                        line.screen_region[3] = 0.0;
-                        line.screen_region[1] = 600.0;
+                        // and drop it from the rod down to the water, bouncing a little when it lands
+                        let rod_y = fisherman.screen_region[1] - 30.0;
+                        hook.screen_region[1] = rod_y;
+                        hook_tweens.replace(
+                            Tween::field(|hook: &mut Char_action, y| hook.screen_region[1] = y, rod_y, fisherman.screen_region[1] - 100.0, 0.5)
+                                .ease(Ease::BounceOut),
+                        );
+
+                        // and a splash where it goes in
+                        let splash_x = hook.screen_region[0] + hook.screen_region[2] / 2.0;
+                        particle_system.burst(&particles::ParticleSettings::splash(), [splash_x, water.config.waterline], 16);
                     }
                     
 
                 }
-                else if input.is_key_up(winit::event::VirtualKeyCode::Left) || input.is_key_up(winit::event::VirtualKeyCode::Right){
+                else if input.is_key_up(settings.keys.left) || input.is_key_up(settings.keys.right){
                     if !gs.is_currently_casted && fisherman.animations[fisherman.current_animation_index].is_done {
                         fisherman.set_animation_index(0);
                     }
@@ -1031,12 +1121,44 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 // find a way to set every sprite in 'sprites' to their appropriate new sheet regions and screen regions
                 // ALSO ticks their animations!
                 fisherman.animations[fisherman.current_animation_index].tick();
+                let sprites = sprite_renderer.get_sprites_mut(game_group);
                 sprites[fisherman.sprites_index].sheet_region = fisherman.get_current_animation_state();
                 sprites[fisherman.sprites_index].screen_region = fisherman.screen_region;
                 sprites[hook.sprites_index].screen_region = hook.screen_region;
-                sprites[line.sprites_index].screen_region = line.screen_region;
-                sprites[fish.sprites_index].screen_region = fish.screen_region;
-                sprites[large_fish.sprites_index].screen_region = large_fish.screen_region;
+
+                let now = Instant::now();
+                let dt = now.duration_since(last_update).as_secs_f32();
+                last_update = now;
+                debug_overlay.record_frame(dt);
+                if hook_tweens.is_playing() {
+                    hook_tweens.update(&mut hook, dt);
+                    sprites[hook.sprites_index].screen_region = hook.screen_region;
+                }
+                world_popups.update(dt);
+                transitions.update(dt);
+                hud_tweens.update(&mut hud_top, dt);
+                menu_tweens.update(&mut menu_left, dt);
+                // Fish fade in after respawning and get darker the deeper they swim (the hook reels in at y == 500)
+                for fish in small_fish.iter_mut().chain(large_fish.iter_mut()) {
+                    fish.tick_fade(dt, 0.75);
+                    sprites[fish.sprites_index].screen_region = fish.screen_region;
+                    sprites[fish.sprites_index].tint = fish.tint(500.0);
+                }
+                for (fish, emitter) in small_fish.iter().chain(large_fish.iter()).zip(&bubble_emitters) {
+                    particle_system.move_emitter(*emitter, fish_tail(fish));
+                }
+                particle_system.update(dt);
+                // The hook swings gently from where the line ties onto it
+                swing_time += dt;
+                sprites[hook.sprites_index].pivot = [0.5, 1.0];
+                sprites[hook.sprites_index].rotation = (swing_time * 3.0).sin() * 0.2;
+
+                // Keep the fisherman on screen
+                camera_controller.follow([fisherman.screen_region[0] + fisherman.screen_region[2] / 2.0, fisherman.screen_region[1]]);
+                camera_controller.update(dt);
+                camera = camera_controller.gpu_camera();
+                parallax.update(dt);
+                water.update(dt);
 
                 let mut man_x: f32 = sprites[fisherman.sprites_index].screen_region[0];
                 let man_y: f32 = sprites[fisherman.sprites_index].screen_region[1];
@@ -1048,62 +1170,24 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 let mut hook_width: f32 = sprites[hook.sprites_index].screen_region[2];
                 let hook_height: f32 = sprites[hook.sprites_index].screen_region[3];
 
-                let mut fish_x: f32 = sprites[fish.sprites_index].screen_region[0];
-                let fish_y: f32 = sprites[fish.sprites_index].screen_region[1];
-                let mut fish_width: f32 = sprites[fish.sprites_index].screen_region[2];
-                let fish_height: f32 = sprites[fish.sprites_index].screen_region[3];
-
-                let mut large_fish_x: f32 = sprites[large_fish.sprites_index].screen_region[0];
-                let large_fish_y: f32 = sprites[large_fish.sprites_index].screen_region[1];
-                let mut large_fish_width: f32 = sprites[large_fish.sprites_index].screen_region[2];
-                let large_fish_height: f32 = sprites[large_fish.sprites_index].screen_region[3];
-                
                 // Check for collisions
-                if (hook_x + hook_width > fish_x) && (hook_x < fish_x + fish_width)
-                    && (hook_y - hook_height + 70.0 < fish_y) && (hook_y + 38.0 > fish_y - fish_height) {
-                    // Collision detected, handle it here
-                    //fish.reset_x();
-                    if !fish.caught{
-                        fish.screen_region[0] = hook.screen_region[0];
-                        fish.screen_region[1] = hook.screen_region[1];
-                    }
-                    fish.caught = true;
-                    fish.speed = hook.speed;
-
-
-                    /*
-                    if !gs.score_changing{
-                        gs.score += 1;
-                        let score_text = format!("Score: {}", gs.score);
-                        // buffer.set_text(&mut font_system, &gs.score.to_string(), Attrs::new().family(Family::SansSerif), Shaping::Advanced);    
-                        buffer.set_text(&mut font_system, &score_text, Attrs::new().family(Family::SansSerif), Shaping::Advanced);
-                        gs.score_changing = true;
-                    }
-                    */
-                }
-                else if (hook_x + hook_width > large_fish_x) && (hook_x < large_fish_x + large_fish_width)
-                    && (hook_y - hook_height + 70.0 < large_fish_y) && (hook_y + 38.0 > large_fish_y - large_fish_height) {
-                    // Collision detected, handle it here
-                    //large_fish.reset_x();
-                    if !large_fish.caught{
-                        large_fish.screen_region[0] = hook.screen_region[0];
-                        large_fish.screen_region[1] = hook.screen_region[1];
-                    }
-                    large_fish.caught = true;
-                    large_fish.speed = hook.speed;
-
-
-                    /*
-                    if !gs.score_changing{
-                        gs.score += 2;
-                        let score_text = format!("Score: {}", gs.score);
-                        // buffer.set_text(&mut font_system, &gs.score.to_string(), Attrs::new().family(Family::SansSerif), Shaping::Advanced);    
-                        buffer.set_text(&mut font_system, &score_text, Attrs::new().family(Family::SansSerif), Shaping::Advanced);
-                        gs.score_changing = true;
+                let mut hooked_any = false;
+                let hook_box = hook_hitbox([hook_x, hook_y, hook_width, hook_height]);
+                for fish in small_fish.iter_mut().chain(large_fish.iter_mut()) {
+                    if overlaps(hook_box, fish_hitbox(fish.screen_region)) {
+                        // Collision detected, handle it here
+                        if !fish.caught{
+                            fish.screen_region[0] = hook.screen_region[0];
+                            fish.screen_region[1] = hook.screen_region[1];
+                            camera_controller.shake(4.0, 0.2);
+                            fish.flash_frames = 8;
+                        }
+                        fish.caught = true;
+                        fish.speed = hook.speed;
+                        hooked_any = true;
                     }
-                    */
                 }
-                else{gs.score_changing = false;}
+                if !hooked_any {gs.score_changing = false;}
                 
                 
                 window.request_redraw();
@@ -1113,17 +1197,92 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
     });
 }
 
+// What the catch check compares, as [x, y, width, height] from the bottom left. A fish's box hangs
+// down from its y, and the hook's is trimmed to roughly where the barb is; the debug overlay draws both.
+fn fish_hitbox(screen_region: [f32; 4]) -> [f32; 4] {
+    let [x, y, w, h] = screen_region;
+    [x, y - h, w, h]
+}
+
+fn hook_hitbox(screen_region: [f32; 4]) -> [f32; 4] {
+    let [x, y, w, h] = screen_region;
+    [x, y - h + 70.0, w, h - 32.0]
+}
+
+// Whether two boxes overlap; only touching edges doesn't count
+fn overlaps(a: [f32; 4], b: [f32; 4]) -> bool {
+    a[0] + a[2] > b[0] && a[0] < b[0] + b[2] && a[1] < b[1] + b[3] && a[1] + a[3] > b[1]
+}
+
+// What the debug overlay's inspector shows about a character
+fn debug_entity(name: String, character: &Char_action, hitbox: [f32; 4], velocity: [f32; 2]) -> debug_overlay::DebugEntity {
+    let animation = &character.animations[character.current_animation_index];
+    debug_overlay::DebugEntity {
+        name,
+        hitbox,
+        velocity,
+        details: format!(
+            "animation: {} frame {}\nfacing {}, caught: {}, fade: {:.2}",
+            character.current_animation_index,
+            animation.state_number,
+            if character.facing_left { "left" } else { "right" },
+            character.caught,
+            character.fade,
+        ),
+    }
+}
+
+// Where a fish's bubbles come from: the back end, whichever way it's swimming
+fn fish_tail(fish: &Char_action) -> [f32; 2] {
+    let [x, y, w, h] = fish.screen_region;
+    if fish.facing_left {
+        [x + w, y + h / 2.0]
+    } else {
+        [x, y + h / 2.0]
+    }
+}
+
+// Where the fishing line leaves the rod, which depends on which way the fisherman is facing
+fn rod_tip(fisherman: &Char_action) -> [f32; 2] {
+    let [x, y, _, _] = fisherman.screen_region;
+    if fisherman.facing_left {
+        [x + 17.0, y]
+    } else {
+        [x + 95.0, y]
+    }
+}
+
+// Points along the fishing line from the rod to where it ties onto the hook, bowing out a little in
+// time with the hook's swing
+fn fishing_line(from: [f32; 2], to: [f32; 2], swing_time: f32) -> Vec<[f32; 2]> {
+    const SEGMENTS: usize = 12;
+    let bow = (swing_time * 3.0).sin() * 6.0;
+    (0..=SEGMENTS)
+        .map(|i| {
+            let t = i as f32 / SEGMENTS as f32;
+            let sway = bow * (t * std::f32::consts::PI).sin();
+            [from[0] + (to[0] - from[0]) * t + sway, from[1] + (to[1] - from[1]) * t]
+        })
+        .collect()
+}
+
 // Main is just going to configure an event loop, open a window, set up logging,
 // and kick off our `run` function.
 fn main() {
     let event_loop = EventLoop::new();
-    let window = winit::window::Window::new(&event_loop).unwrap();
+    let settings = Settings::load(settings::SETTINGS_PATH);
+    let window = winit::window::WindowBuilder::new()
+        .with_title("Fishful Thinking")
+        .with_inner_size(settings.window_size())
+        .with_fullscreen(settings.fullscreen_mode())
+        .build(&event_loop)
+        .unwrap();
 
     #[cfg(not(target_arch = "wasm32"))]
     {
         env_logger::init();
         // On native, we just want to wait for `run` to finish.
-        pollster::block_on(run(event_loop, window));
+        pollster::block_on(run(event_loop, window, settings));
     }
     #[cfg(target_arch = "wasm32")]
     {
@@ -1141,6 +1300,6 @@ fn main() {
             })
             .expect("couldn't append canvas to document body");
         // Now we use the browser's runtime to spawn our async run function.
-        wasm_bindgen_futures::spawn_local(run(event_loop, window));
+        wasm_bindgen_futures::spawn_local(run(event_loop, window, settings));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,132 @@
+use std::borrow::Cow;
+
+// How many levels a full mip chain has, down to 1x1
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+// Fill in every mip level after the first by drawing each one from the level above it (see mipmaps.wgsl).
+// The texture needs TEXTURE_BINDING and RENDER_ATTACHMENT usage, and a format that can be drawn into.
+// For textures that only need it once, while loading; see MipChain for ones redrawn every frame.
+pub(crate) fn generate_mipmaps(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture, premultiplied: bool) {
+    if texture.mip_level_count() < 2 {
+        return;
+    }
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("mipmaps") });
+    MipChain::new(device, texture, premultiplied).encode(&mut encoder);
+    queue.submit(Some(encoder.finish()));
+}
+
+// Everything needed to refill one texture's mip levels, made once up front so a texture that changes
+// every frame (like the virtual canvas) can have its mips redone without building pipelines each time
+pub(crate) struct MipChain {
+    pipeline: wgpu::RenderPipeline,
+    // One per level after the first: what to read from, and the level to draw into
+    levels: Vec<(wgpu::BindGroup, wgpu::TextureView)>,
+}
+
+impl MipChain {
+    pub(crate) fn new(device: &wgpu::Device, texture: &wgpu::Texture, premultiplied: bool) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("mipmaps"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("mipmaps.wgsl"))),
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mipmaps"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                // Read with textureLoad, so no sampler and no filtering needed
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("mipmaps"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("mipmaps"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: if premultiplied { "fs_premultiplied" } else { "fs_straight" },
+                targets: &[Some(texture.format().into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        // A view of just one level, to read from or draw into
+        let level_view = |level: u32| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+        };
+        let levels = (1..texture.mip_level_count())
+            .map(|level| {
+                let source = level_view(level - 1);
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&source),
+                    }],
+                });
+                (bind_group, level_view(level))
+            })
+            .collect();
+        Self { pipeline, levels }
+    }
+
+    // Redraw every level from level 0, biggest first
+    pub(crate) fn encode(&self, encoder: &mut wgpu::CommandEncoder) {
+        for (bind_group, target) in &self.levels {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("mipmaps"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        // Every pixel gets drawn, so there's nothing worth loading
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_bind_group(0, bind_group, &[]);
+            rpass.draw(0..3, 0..1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_chain_goes_down_to_one_pixel() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(2, 1), 2);
+        assert_eq!(mip_level_count(256, 256), 9);
+        // Odd and non-square sizes round down at each level: 300 -> 150 -> 75 -> ... -> 1
+        assert_eq!(mip_level_count(300, 20), 9);
+    }
+}
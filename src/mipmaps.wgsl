@@ -0,0 +1,45 @@
+// Fills one mip level from the level above it.
+// Each pixel of the smaller level is the average of the 2x2 block of pixels it covers.
+
+// The bigger level, as its own one-level view
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+// One triangle big enough to cover the whole target, so there's no vertex data at all
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // (0,0), (2,0), (0,2), scaled into NDC
+    let corner:vec2<f32> = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    return vec4(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// textureLoad doesn't filter or clamp, so stay inside the source (odd sizes have a leftover row or column)
+fn texel(pos: vec2<i32>) -> vec4<f32> {
+    let last:vec2<i32> = vec2<i32>(textureDimensions(source)) - vec2<i32>(1, 1);
+    return textureLoad(source, min(pos, last), 0);
+}
+
+// For premultiplied textures a plain average is already right
+@fragment
+fn fs_premultiplied(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pos:vec2<i32> = vec2<i32>(position.xy) * 2;
+    return (texel(pos) + texel(pos + vec2<i32>(1, 0)) + texel(pos + vec2<i32>(0, 1)) + texel(pos + vec2<i32>(1, 1))) * 0.25;
+}
+
+// With straight alpha, the color of a fully transparent pixel is meaningless (often black),
+// so weight each color by its alpha to keep it from bleeding into the edges of the sprite.
+@fragment
+fn fs_straight(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pos:vec2<i32> = vec2<i32>(position.xy) * 2;
+    let a:vec4<f32> = texel(pos);
+    let b:vec4<f32> = texel(pos + vec2<i32>(1, 0));
+    let c:vec4<f32> = texel(pos + vec2<i32>(0, 1));
+    let d:vec4<f32> = texel(pos + vec2<i32>(1, 1));
+    let alpha_sum:f32 = a.a + b.a + c.a + d.a;
+    if alpha_sum <= 0.0 {
+        // All see-through: the color doesn't matter, but keep the plain average anyway
+        return vec4((a.rgb + b.rgb + c.rgb + d.rgb) * 0.25, 0.0);
+    }
+    let rgb:vec3<f32> = a.rgb * a.a + b.rgb * b.a + c.rgb * c.a + d.rgb * d.a;
+    return vec4(rgb / alpha_sum, alpha_sum * 0.25);
+}
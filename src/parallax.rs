@@ -0,0 +1,275 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::assets::{AssetManager, TextureHandle};
+use crate::camera::GPUCamera;
+use crate::gpus::{TextureOptions, WGPU};
+use crate::sprite_game::{GPUSprite, Layer, SpriteRenderer};
+
+// Where the play screen's background layers are described
+pub const PARALLAX_PATH: &str = "fishful_content/parallax.toml";
+
+// One background layer. Everything's in canvas pixels, with y going up from the bottom of the screen.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParallaxLayerConfig {
+    pub texture: String,
+    // Which draw layer it goes on; layers later in the file are drawn in front of earlier ones
+    pub layer: Layer,
+    // How far the layer moves compared to the world: 0 stays put, 1 moves with the fish and the pier,
+    // in between looks further away
+    pub scroll_factor: f32,
+    // Extra drift to the right in pixels per second (negative for left), whatever the camera does:
+    // clouds, waves
+    pub auto_scroll: f32,
+    // Repeat the texture sideways forever instead of drawing it once
+    pub wrap: bool,
+    pub y: f32,
+    pub height: f32,
+    // How wide one copy of the texture is on screen. Leave it out to keep the texture's proportions.
+    pub tile_width: Option<f32>,
+    // Which horizontal band of the texture to use, as [top, height] fractions, so one image can be
+    // split into layers that move differently
+    pub band: [f32; 2],
+    pub tint: [f32; 4],
+}
+
+impl Default for ParallaxLayerConfig {
+    fn default() -> Self {
+        Self {
+            texture: String::new(),
+            layer: Layer::Sky,
+            scroll_factor: 0.0,
+            auto_scroll: 0.0,
+            wrap: true,
+            y: 0.0,
+            height: crate::canvas::LOGICAL_HEIGHT as f32,
+            tile_width: None,
+            band: [0.0, 1.0],
+            tint: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParallaxConfig {
+    pub layers: Vec<ParallaxLayerConfig>,
+}
+
+// Without a config the play screen still gets its old static background
+impl Default for ParallaxConfig {
+    fn default() -> Self {
+        Self {
+            layers: vec![ParallaxLayerConfig {
+                texture: "fishful_content/background.png".to_string(),
+                tile_width: Some(crate::canvas::LOGICAL_WIDTH as f32),
+                wrap: false,
+                ..Default::default()
+            }],
+        }
+    }
+}
+
+impl ParallaxConfig {
+    // Same deal as Settings::load: a missing or broken file logs a warning and gives the default
+    pub fn load(path: impl AsRef<Path>) -> ParallaxConfig {
+        let path = path.as_ref();
+        match std::fs::read_to_string(path) {
+            Ok(text) => match toml::from_str::<ParallaxConfig>(&text) {
+                Ok(config) => config,
+                Err(e) => {
+                    log::warn!("Couldn't parse {}, using the default background: {}", path.display(), e);
+                    ParallaxConfig::default()
+                }
+            },
+            Err(e) => {
+                log::warn!("Couldn't read {}, using the default background: {}", path.display(), e);
+                ParallaxConfig::default()
+            }
+        }
+    }
+}
+
+struct ParallaxLayer {
+    config: ParallaxLayerConfig,
+    tex: TextureHandle,
+    group: usize,
+}
+
+impl ParallaxLayer {
+    fn tile_width(&self) -> f32 {
+        let (tex_width, tex_height) = self.tex.size();
+        let band_height = (tex_height as f32 * self.config.band[1]).max(1.0);
+        self.config.tile_width.unwrap_or(tex_width as f32 * self.config.height / band_height)
+    }
+}
+
+// Background layers that scroll at different speeds as the camera moves, for a sense of depth.
+// Each layer is one sprite in its own group, drawn with a camera that never moves; wrapping comes
+// from a repeating sampler, so a layer is one quad however far the world scrolls.
+pub struct ParallaxBackground {
+    layers: Vec<ParallaxLayer>,
+    view_size: [f32; 2],
+    time: f32,
+}
+
+#[allow(dead_code)]
+impl ParallaxBackground {
+    pub(crate) async fn new(
+        gpu: &WGPU,
+        assets: &mut AssetManager,
+        renderer: &mut SpriteRenderer,
+        config: &ParallaxConfig,
+        view_size: [f32; 2],
+    ) -> Self {
+        let screen_camera = GPUCamera {
+            screen_pos: [0.0, 0.0],
+            screen_size: view_size,
+        };
+        let mut layers = Vec::with_capacity(config.layers.len());
+        for (index, layer_config) in config.layers.iter().enumerate() {
+            let tex = assets.load_texture_with(gpu, &layer_config.texture, TextureOptions::MIPMAPPED).await;
+            if layer_config.wrap {
+                tex.set_sampler(tex.sampler().repeating());
+            }
+            // File order is back to front within each draw layer
+            let z = layer_config.layer.z_offset(index as f32 * 0.01);
+            let sprite = GPUSprite::new([0.0, 0.0, 0.0, 0.0], [0.0, 0.0, 1.0, 1.0], z);
+            let group = renderer.add_sprite_group(gpu, assets, &tex, vec![sprite], screen_camera);
+            // The UVs run past the edge on purpose to wrap, so pulling them in would leave seams
+            renderer.set_half_texel_inset(group, false);
+            layers.push(ParallaxLayer {
+                config: layer_config.clone(),
+                tex,
+                group,
+            });
+        }
+        let background = Self {
+            layers,
+            view_size,
+            time: 0.0,
+        };
+        background.sync(gpu, renderer, [0.0, 0.0]);
+        background
+    }
+
+    // Pick up an edited config (dev mode). Positions and speeds change straight away, but layers
+    // can't be added, removed or given other textures without restarting.
+    pub fn reconfigure(&mut self, config: &ParallaxConfig) -> Result<(), String> {
+        let same_textures = config.layers.len() == self.layers.len()
+            && config.layers.iter().zip(&self.layers).all(|(new, old)| new.texture == old.config.texture);
+        if !same_textures {
+            return Err("the background layers or their textures changed; restart to see that".to_string());
+        }
+        for (layer, new_config) in self.layers.iter_mut().zip(&config.layers) {
+            if new_config.wrap != layer.config.wrap {
+                // Only the wrapping changes; the filtering stays whatever the texture was given
+                let sampler = if new_config.wrap { layer.tex.sampler().repeating() } else { layer.tex.sampler().clamped() };
+                layer.tex.set_sampler(sampler);
+            }
+            layer.config = new_config.clone();
+        }
+        Ok(())
+    }
+
+    // Move the auto-scrolling layers along
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+    }
+
+    // Put every layer where it belongs for a camera at `camera_pos` (GPUCamera::screen_pos) and upload it
+    pub(crate) fn sync(&self, gpu: &WGPU, renderer: &mut SpriteRenderer, camera_pos: [f32; 2]) {
+        for layer in &self.layers {
+            let sprite = layer_sprite(&layer.config, layer.tile_width(), camera_pos[0], self.time, self.view_size[0]);
+            let sprites = renderer.get_sprites_mut(layer.group);
+            sprites[0].screen_region = sprite.screen_region;
+            sprites[0].sheet_region = sprite.sheet_region;
+            sprites[0].tint = sprite.tint;
+            renderer.upload_sprites(gpu, layer.group);
+        }
+    }
+
+    pub fn set_visible(&self, renderer: &mut SpriteRenderer, visible: bool) {
+        for layer in &self.layers {
+            renderer.set_visible(layer.group, visible);
+        }
+    }
+}
+
+// Where a layer's one sprite goes, in screen space, when the camera's left edge is at `camera_x`
+fn layer_sprite(config: &ParallaxLayerConfig, tile_width: f32, camera_x: f32, time: f32, view_width: f32) -> GPUSprite {
+    let offset = camera_x * config.scroll_factor - time * config.auto_scroll;
+    let [band_top, band_height] = config.band;
+    let (screen_region, sheet_region) = if config.wrap {
+        // Cover the whole width and slide the texture along inside it; keeping the start inside the
+        // first tile stops the UVs growing (and losing precision) as time goes on
+        let start = offset.rem_euclid(tile_width) / tile_width;
+        (
+            [0.0, config.y, view_width, config.height],
+            [start, band_top, view_width / tile_width, band_height],
+        )
+    } else {
+        ([-offset, config.y, tile_width, config.height], [0.0, band_top, 1.0, band_height])
+    };
+    GPUSprite::new(screen_region, sheet_region, 0.0).with_tint(config.tint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn wrapped_layers_slide_at_their_scroll_factor() {
+        let config = ParallaxLayerConfig {
+            scroll_factor: 0.5,
+            ..Default::default()
+        };
+        let sprite = layer_sprite(&config, 200.0, 300.0, 0.0, 1000.0);
+        assert_eq!(sprite.screen_region, [0.0, 0.0, 1000.0, config.height]);
+        // 300 * 0.5 = 150 pixels into a 200 pixel tile
+        assert_close(sprite.sheet_region[0], 0.75);
+        assert_close(sprite.sheet_region[2], 5.0);
+        // One whole tile further along looks exactly the same
+        let further = layer_sprite(&config, 200.0, 700.0, 0.0, 1000.0);
+        assert_close(further.sheet_region[0], sprite.sheet_region[0]);
+    }
+
+    #[test]
+    fn auto_scroll_moves_without_the_camera() {
+        let config = ParallaxLayerConfig {
+            auto_scroll: 10.0,
+            ..Default::default()
+        };
+        let still = layer_sprite(&config, 100.0, 500.0, 0.0, 1000.0);
+        let later = layer_sprite(&config, 100.0, 500.0, 2.5, 1000.0);
+        assert_close(still.sheet_region[0], 0.0);
+        // 25 pixels the other way, wrapped into the tile
+        assert_close(later.sheet_region[0], 0.75);
+    }
+
+    #[test]
+    fn unwrapped_layers_move_as_one_picture() {
+        let config = ParallaxLayerConfig {
+            wrap: false,
+            scroll_factor: 0.25,
+            band: [0.5, 0.5],
+            ..Default::default()
+        };
+        let sprite = layer_sprite(&config, 800.0, 400.0, 0.0, 1000.0);
+        assert_eq!(sprite.screen_region, [-100.0, 0.0, 800.0, config.height]);
+        assert_eq!(sprite.sheet_region, [0.0, 0.5, 1.0, 0.5]);
+    }
+
+    #[test]
+    fn shipped_config_parses() {
+        let text = std::fs::read_to_string(PARALLAX_PATH).unwrap();
+        let config: ParallaxConfig = toml::from_str(&text).unwrap();
+        assert!(config.layers.iter().any(|layer| layer.texture.ends_with("Ocean.png")));
+    }
+}
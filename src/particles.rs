@@ -0,0 +1,358 @@
+use rand::Rng;
+
+use crate::assets::{AssetManager, SamplerConfig};
+use crate::camera::GPUCamera;
+use crate::gpus::{TextureOptions, WGPU};
+use crate::sprite_game::{GPUSprite, Layer, SpriteRenderer};
+
+// How particles from one emitter behave. Positions and speeds are in world pixels (per second),
+// with y going up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParticleSettings {
+    // Each particle lives somewhere between these many seconds
+    pub lifetime: [f32; 2],
+    // Starting speed range
+    pub speed: [f32; 2],
+    // Which way they start moving (radians, 0 is right, counterclockwise), give or take `spread`
+    pub direction: f32,
+    pub spread: f32,
+    // How far from the emitter they can start, in each direction
+    pub jitter: [f32; 2],
+    // Gravity (negative y) or buoyancy (positive y), in pixels per second per second
+    pub acceleration: [f32; 2],
+    // The fraction of speed lost per second, 0 for none
+    pub drag: f32,
+    // Width and height at birth and at death, blended in between
+    pub size: [f32; 2],
+    pub color: [[f32; 4]; 2],
+    pub z: f32,
+}
+
+#[allow(dead_code)]
+impl ParticleSettings {
+    // A slow stream of small bubbles that wobble up and fade out, for behind swimming fish
+    pub fn bubbles() -> Self {
+        Self {
+            lifetime: [1.0, 1.8],
+            speed: [10.0, 25.0],
+            direction: std::f32::consts::FRAC_PI_2,
+            spread: 0.6,
+            jitter: [3.0, 3.0],
+            acceleration: [0.0, 30.0],
+            drag: 0.5,
+            size: [5.0, 9.0],
+            color: [[0.85, 0.95, 1.0, 0.8], [0.85, 0.95, 1.0, 0.0]],
+            z: Layer::FarWater.z_offset(0.9),
+        }
+    }
+
+    // Droplets thrown up where the hook hits the water, falling back down
+    pub fn splash() -> Self {
+        Self {
+            lifetime: [0.4, 0.8],
+            speed: [120.0, 260.0],
+            direction: std::f32::consts::FRAC_PI_2,
+            spread: 0.7,
+            jitter: [12.0, 2.0],
+            acceleration: [0.0, -700.0],
+            drag: 0.0,
+            size: [9.0, 4.0],
+            color: [[0.9, 0.97, 1.0, 1.0], [0.6, 0.8, 1.0, 0.0]],
+            z: Layer::ForegroundWater.z_offset(0.5),
+        }
+    }
+
+    // A ring of golden sparkles when a fish is landed
+    pub fn catch_sparkles() -> Self {
+        Self {
+            lifetime: [0.5, 0.9],
+            speed: [80.0, 220.0],
+            direction: 0.0,
+            spread: std::f32::consts::PI,
+            jitter: [4.0, 4.0],
+            acceleration: [0.0, -120.0],
+            drag: 2.0,
+            size: [12.0, 2.0],
+            color: [[1.0, 0.9, 0.3, 1.0], [1.0, 1.0, 1.0, 0.0]],
+            z: Layer::Pier.z_offset(0.9),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Particle {
+    pos: [f32; 2],
+    vel: [f32; 2],
+    age: f32,
+    lifetime: f32,
+    settings: ParticleSettings,
+}
+
+// Keeps spawning particles at `rate` per second until it's removed or switched off
+struct Emitter {
+    settings: ParticleSettings,
+    pos: [f32; 2],
+    rate: f32,
+    active: bool,
+    // Fractions of a particle carried over between frames, so low rates still come out right
+    owed: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EmitterId(usize);
+
+// CPU-simulated particles drawn as sprites in one sprite group. There's a fixed number of slots,
+// allocated up front; dead particles' slots get reused, and spawning when every slot is busy does nothing.
+pub struct ParticleSystem {
+    // None is a free slot
+    particles: Vec<Option<Particle>>,
+    // Which slots are free, so spawning doesn't have to search
+    free: Vec<usize>,
+    emitters: Vec<Option<Emitter>>,
+    group: Option<usize>,
+}
+
+#[allow(dead_code)]
+impl ParticleSystem {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            particles: vec![None; capacity],
+            // Reversed so slot 0 gets used first
+            free: (0..capacity).rev().collect(),
+            emitters: Vec::new(),
+            group: None,
+        }
+    }
+
+    // Make the sprite group the particles are drawn with: one sprite per slot, all using a soft
+    // white dot that each particle tints
+    pub(crate) fn add_to_renderer(&mut self, gpu: &WGPU, assets: &mut AssetManager, renderer: &mut SpriteRenderer, camera: GPUCamera) {
+        let tex = assets.texture_from_image(gpu, "particle", &particle_image(), TextureOptions::DEFAULT);
+        tex.set_sampler(SamplerConfig::SMOOTH);
+        let hidden = vec![GPUSprite::new([0.0, 0.0, 0.0, 0.0], [0.0, 0.0, 1.0, 1.0], 0.0); self.particles.len()];
+        self.group = Some(renderer.add_sprite_group(gpu, assets, &tex, hidden, camera));
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn alive_count(&self) -> usize {
+        self.particles.len() - self.free.len()
+    }
+
+    // Spawn `count` particles at once
+    pub fn burst(&mut self, settings: &ParticleSettings, pos: [f32; 2], count: usize) {
+        let mut rng = rand::thread_rng();
+        for _ in 0..count {
+            let Some(slot) = self.free.pop() else { return };
+            self.particles[slot] = Some(spawn(settings, pos, &mut rng));
+        }
+    }
+
+    // Start spawning particles continuously at `pos`
+    pub fn add_emitter(&mut self, settings: ParticleSettings, pos: [f32; 2], rate: f32) -> EmitterId {
+        let emitter = Emitter {
+            settings,
+            pos,
+            rate,
+            active: true,
+            owed: 0.0,
+        };
+        match self.emitters.iter().position(Option::is_none) {
+            Some(index) => {
+                self.emitters[index] = Some(emitter);
+                EmitterId(index)
+            }
+            None => {
+                self.emitters.push(Some(emitter));
+                EmitterId(self.emitters.len() - 1)
+            }
+        }
+    }
+
+    pub fn move_emitter(&mut self, id: EmitterId, pos: [f32; 2]) {
+        if let Some(emitter) = &mut self.emitters[id.0] {
+            emitter.pos = pos;
+        }
+    }
+
+    // Pause or resume an emitter; its particles already in the air carry on either way
+    pub fn set_emitter_active(&mut self, id: EmitterId, active: bool) {
+        if let Some(emitter) = &mut self.emitters[id.0] {
+            emitter.active = active;
+            emitter.owed = 0.0;
+        }
+    }
+
+    pub fn remove_emitter(&mut self, id: EmitterId) {
+        self.emitters[id.0] = None;
+    }
+
+    // Remove every particle, e.g. when a round restarts. Emitters stay.
+    pub fn clear(&mut self) {
+        self.particles.iter_mut().for_each(|p| *p = None);
+        self.free = (0..self.particles.len()).rev().collect();
+    }
+
+    // Call once per frame with the time since the last frame, in seconds
+    pub fn update(&mut self, dt: f32) {
+        for (slot, particle) in self.particles.iter_mut().enumerate() {
+            let Some(p) = particle else { continue };
+            p.age += dt;
+            if p.age >= p.lifetime {
+                *particle = None;
+                self.free.push(slot);
+                continue;
+            }
+            let drag = (1.0 - p.settings.drag * dt).max(0.0);
+            for axis in 0..2 {
+                p.vel[axis] = (p.vel[axis] + p.settings.acceleration[axis] * dt) * drag;
+                p.pos[axis] += p.vel[axis] * dt;
+            }
+        }
+
+        let mut rng = rand::thread_rng();
+        for emitter in self.emitters.iter_mut().flatten() {
+            if !emitter.active {
+                continue;
+            }
+            emitter.owed += emitter.rate * dt;
+            while emitter.owed >= 1.0 {
+                emitter.owed -= 1.0;
+                let Some(slot) = self.free.pop() else { break };
+                self.particles[slot] = Some(spawn(&emitter.settings, emitter.pos, &mut rng));
+            }
+        }
+    }
+
+    // Every slot as a sprite, centered on its particle; free slots are zero-size
+    pub fn write_sprites(&self, sprites: &mut [GPUSprite]) {
+        for (sprite, particle) in sprites.iter_mut().zip(&self.particles) {
+            match particle {
+                Some(p) => {
+                    let t = p.age / p.lifetime;
+                    let size = lerp(p.settings.size[0], p.settings.size[1], t);
+                    let [from, to] = p.settings.color;
+                    sprite.screen_region = [p.pos[0] - size / 2.0, p.pos[1] - size / 2.0, size, size];
+                    sprite.tint = [0, 1, 2, 3].map(|i| lerp(from[i], to[i], t));
+                    sprite.z = p.settings.z;
+                }
+                None => sprite.screen_region = [0.0, 0.0, 0.0, 0.0],
+            }
+        }
+    }
+
+    // Send this frame's particles to the GPU, drawn through `camera` (the world camera)
+    pub(crate) fn sync(&self, gpu: &WGPU, renderer: &mut SpriteRenderer, camera: GPUCamera) {
+        let Some(group) = self.group else { return };
+        self.write_sprites(renderer.get_sprites_mut(group));
+        renderer.set_camera(gpu, group, camera);
+        renderer.upload_sprites(gpu, group);
+    }
+
+    pub fn set_visible(&self, renderer: &mut SpriteRenderer, visible: bool) {
+        if let Some(group) = self.group {
+            renderer.set_visible(group, visible);
+        }
+    }
+}
+
+fn spawn(settings: &ParticleSettings, pos: [f32; 2], rng: &mut impl Rng) -> Particle {
+    let mut range = |[min, max]: [f32; 2]| if max > min { rng.gen_range(min..max) } else { min };
+    let angle = settings.direction + range([-settings.spread, settings.spread]);
+    let speed = range(settings.speed);
+    Particle {
+        pos: [
+            pos[0] + range([-settings.jitter[0], settings.jitter[0]]),
+            pos[1] + range([-settings.jitter[1], settings.jitter[1]]),
+        ],
+        vel: [angle.cos() * speed, angle.sin() * speed],
+        age: 0.0,
+        lifetime: range(settings.lifetime).max(f32::EPSILON),
+        settings: *settings,
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// A soft white dot, see-through towards the edges
+pub fn particle_image() -> image::RgbaImage {
+    const SIZE: u32 = 16;
+    image::RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        let center = SIZE as f32 / 2.0;
+        let (dx, dy) = (x as f32 + 0.5 - center, y as f32 + 0.5 - center);
+        let distance = (dx * dx + dy * dy).sqrt() / center;
+        let alpha = (1.0 - distance).clamp(0.0, 1.0).powf(0.7);
+        image::Rgba([255, 255, 255, (alpha * 255.0) as u8])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn still() -> ParticleSettings {
+        ParticleSettings {
+            lifetime: [1.0, 1.0],
+            speed: [0.0, 0.0],
+            spread: 0.0,
+            jitter: [0.0, 0.0],
+            acceleration: [0.0, 0.0],
+            drag: 0.0,
+            ..ParticleSettings::bubbles()
+        }
+    }
+
+    #[test]
+    fn pool_never_grows_and_reuses_slots() {
+        let mut particles = ParticleSystem::new(8);
+        particles.burst(&still(), [0.0, 0.0], 20);
+        assert_eq!(particles.alive_count(), 8);
+        particles.update(1.5);
+        assert_eq!(particles.alive_count(), 0);
+        particles.burst(&still(), [0.0, 0.0], 3);
+        assert_eq!(particles.alive_count(), 3);
+        assert_eq!(particles.capacity(), 8);
+    }
+
+    #[test]
+    fn emitters_spawn_at_their_rate() {
+        let mut particles = ParticleSystem::new(100);
+        let emitter = particles.add_emitter(still(), [0.0, 0.0], 10.0);
+        // 0.25 of a particle each frame adds up to 2 after 8 frames
+        for _ in 0..8 {
+            particles.update(0.025);
+        }
+        assert_eq!(particles.alive_count(), 2);
+        particles.set_emitter_active(emitter, false);
+        particles.update(0.5);
+        assert_eq!(particles.alive_count(), 2);
+        particles.remove_emitter(emitter);
+        assert_eq!(particles.add_emitter(still(), [0.0, 0.0], 1.0), emitter);
+    }
+
+    #[test]
+    fn buoyancy_lifts_and_size_and_color_follow_age() {
+        let settings = ParticleSettings {
+            acceleration: [0.0, 100.0],
+            size: [10.0, 20.0],
+            color: [[1.0, 1.0, 1.0, 1.0], [1.0, 1.0, 1.0, 0.0]],
+            ..still()
+        };
+        let mut particles = ParticleSystem::new(2);
+        particles.burst(&settings, [50.0, 50.0], 1);
+        particles.update(0.5);
+        let mut sprites = vec![GPUSprite::new([0.0; 4], [0.0; 4], 0.0); 2];
+        particles.write_sprites(&mut sprites);
+        let [x, y, w, h] = sprites[0].screen_region;
+        assert_eq!((w, h), (15.0, 15.0));
+        assert_eq!(x + w / 2.0, 50.0);
+        assert!(y + h / 2.0 > 50.0);
+        assert_eq!(sprites[0].tint[3], 0.5);
+        // The free slot stays hidden
+        assert_eq!(sprites[1].screen_region, [0.0; 4]);
+    }
+}
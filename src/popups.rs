@@ -0,0 +1,191 @@
+use glyphon::{Attrs, Buffer, Color, Family, FontSystem, Metrics, Shaping, TextArea, TextBounds};
+
+use crate::camera::GPUCamera;
+use crate::tween::{Ease, Parallel, Sequence, Tween, Tweens, Wait};
+
+// What a popup's tweens move
+#[derive(Clone, Copy, Debug, Default)]
+struct PopupAnim {
+    // Off once it has faded away
+    shown: bool,
+    // How far it has floated up, in world pixels
+    rise: f32,
+    scale: f32,
+    alpha: f32,
+}
+
+// One bit of floating text. Its buffer is kept when it's recycled, so spawning doesn't allocate.
+struct Slot {
+    buffer: Buffer,
+    // Where in the world the middle of the text starts, y up
+    pos: [f32; 2],
+    // Laid out size of the text, for centering it
+    size: [f32; 2],
+    color: [u8; 3],
+    anim: PopupAnim,
+    tweens: Tweens<PopupAnim>,
+    // When it was spawned, so the oldest one gets reused when they're all busy
+    spawned: u64,
+}
+
+// Text like "+2" that pops up somewhere in the world, floats up and fades away. There's a fixed number
+// of them, like the particle pool.
+pub struct WorldPopups {
+    slots: Vec<Slot>,
+    spawn_count: u64,
+}
+
+#[allow(dead_code)]
+impl WorldPopups {
+    pub fn new(font_system: &mut FontSystem, capacity: usize, font_size: f32) -> Self {
+        let slots = (0..capacity)
+            .map(|_| {
+                let mut buffer = Buffer::new(font_system, Metrics::new(font_size, font_size * 1.1));
+                // Popups are short; this is just so the text never wraps
+                buffer.set_size(font_system, crate::canvas::LOGICAL_WIDTH as f32, font_size * 2.0);
+                Slot {
+                    buffer,
+                    pos: [0.0, 0.0],
+                    size: [0.0, 0.0],
+                    color: [255, 255, 255],
+                    anim: PopupAnim::default(),
+                    tweens: Tweens::new(),
+                    spawned: 0,
+                }
+            })
+            .collect();
+        Self { slots, spawn_count: 0 }
+    }
+
+    // Show `text` centered on `pos` in world space. If every popup is busy the oldest one is cut short.
+    pub fn spawn(&mut self, font_system: &mut FontSystem, text: &str, pos: [f32; 2], color: [u8; 3]) {
+        self.spawn_count += 1;
+        let spawned = self.spawn_count;
+        let Some(slot) = self
+            .slots
+            .iter_mut()
+            .min_by_key(|slot| if slot.tweens.is_playing() { slot.spawned } else { 0 })
+        else {
+            return;
+        };
+        slot.buffer.set_text(font_system, text, Attrs::new().family(Family::SansSerif), Shaping::Advanced);
+        slot.buffer.shape_until_scroll(font_system);
+        let width = slot.buffer.layout_runs().map(|run| run.line_w).fold(0.0, f32::max);
+        let lines = slot.buffer.layout_runs().count();
+        slot.size = [width, lines as f32 * slot.buffer.metrics().line_height];
+        slot.pos = pos;
+        slot.color = color;
+        slot.spawned = spawned;
+        slot.anim = PopupAnim { shown: true, ..Default::default() };
+        // Pop in with a little bounce, hold, then float up and fade out
+        slot.tweens.replace(
+            Sequence::new()
+                .then(Parallel::new()
+                    .with(Tween::field(|p: &mut PopupAnim, a| p.alpha = a, 0.0, 1.0, 0.1))
+                    .with(Tween::field(|p: &mut PopupAnim, s| p.scale = s, 1.0, 1.5, 0.15).ease(Ease::QuadOut).yoyo()))
+                .then(Wait::new(0.4))
+                .then(Parallel::new()
+                    .with(Tween::field(|p: &mut PopupAnim, r| p.rise = r, 0.0, 40.0, 0.7).ease(Ease::SineIn))
+                    .with(Tween::field(|p: &mut PopupAnim, a| p.alpha = a, 1.0, 0.0, 0.7).ease(Ease::QuadIn)))
+                .on_complete(|p| p.shown = false),
+        );
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for slot in self.slots.iter_mut() {
+            slot.tweens.update(&mut slot.anim, dt);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for slot in self.slots.iter_mut() {
+            slot.tweens.stop();
+            slot.anim = PopupAnim::default();
+        }
+    }
+
+    pub fn active_count(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.anim.shown).count()
+    }
+
+    // The live popups as text areas on a `canvas_size` canvas, seen through `camera`
+    pub fn text_areas(&self, camera: &GPUCamera, canvas_size: [f32; 2]) -> impl Iterator<Item = TextArea<'_>> {
+        let camera = *camera;
+        let bounds = TextBounds {
+            left: 0,
+            top: 0,
+            right: canvas_size[0] as i32,
+            bottom: canvas_size[1] as i32,
+        };
+        self.slots.iter().filter(|slot| slot.anim.shown).map(move |slot| {
+            let [x, y] = camera.world_to_canvas([slot.pos[0], slot.pos[1] + slot.anim.rise], canvas_size);
+            let scale = slot.anim.scale;
+            let [r, g, b] = slot.color;
+            TextArea {
+                buffer: &slot.buffer,
+                // Scaling happens from the top left, so move that to keep the middle in place
+                left: x - slot.size[0] * scale / 2.0,
+                top: y - slot.size[1] * scale / 2.0,
+                scale,
+                bounds,
+                default_color: Color::rgba(r, g, b, (slot.anim.alpha.clamp(0.0, 1.0) * 255.0) as u8),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pop in (0.3s), hold (0.4s), float away (0.7s)
+    const LIFETIME: f32 = 1.4;
+
+    fn camera() -> GPUCamera {
+        GPUCamera { screen_pos: [0.0, 0.0], screen_size: [1024.0, 768.0] }
+    }
+
+    #[test]
+    fn the_oldest_popup_is_reused_when_all_are_busy() {
+        let mut font_system = FontSystem::new();
+        let mut popups = WorldPopups::new(&mut font_system, 2, 20.0);
+        popups.spawn(&mut font_system, "+1", [100.0, 100.0], [255, 255, 255]);
+        popups.update(0.1);
+        popups.spawn(&mut font_system, "+2", [200.0, 100.0], [255, 255, 255]);
+        popups.spawn(&mut font_system, "+3", [300.0, 100.0], [255, 255, 255]);
+        assert_eq!(popups.active_count(), 2);
+        let mut positions: Vec<_> = popups.slots.iter().map(|slot| slot.pos[0]).collect();
+        positions.sort_by(f32::total_cmp);
+        assert_eq!(positions, [200.0, 300.0]);
+    }
+
+    #[test]
+    fn popups_go_away_once_they_have_played() {
+        let mut font_system = FontSystem::new();
+        let mut popups = WorldPopups::new(&mut font_system, 4, 20.0);
+        popups.spawn(&mut font_system, "+1", [100.0, 100.0], [255, 255, 255]);
+        popups.spawn(&mut font_system, "+2", [200.0, 100.0], [255, 255, 255]);
+        popups.update(LIFETIME - 0.1);
+        assert_eq!(popups.active_count(), 2);
+        popups.update(0.2);
+        assert_eq!(popups.active_count(), 0);
+        assert_eq!(popups.text_areas(&camera(), [1024.0, 768.0]).count(), 0);
+    }
+
+    #[test]
+    fn clear_hides_everything() {
+        let mut font_system = FontSystem::new();
+        let mut popups = WorldPopups::new(&mut font_system, 4, 20.0);
+        for x in [100.0, 200.0, 300.0] {
+            popups.spawn(&mut font_system, "+1", [x, 100.0], [255, 255, 255]);
+        }
+        popups.update(0.5);
+        assert_eq!(popups.text_areas(&camera(), [1024.0, 768.0]).count(), 3);
+        popups.clear();
+        assert_eq!(popups.active_count(), 0);
+        assert_eq!(popups.text_areas(&camera(), [1024.0, 768.0]).count(), 0);
+        // And nothing comes back on the next update
+        popups.update(0.1);
+        assert_eq!(popups.active_count(), 0);
+    }
+}
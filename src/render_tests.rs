@@ -0,0 +1,554 @@
+// Golden-image tests: render a scene offscreen and compare it against a PNG in goldens/.
+// A missing golden is a failure. Run with UPDATE_GOLDENS=1 to write new ones (or rewrite them all after
+// an intentional change), then check the PNGs in once they look right.
+use std::path::PathBuf;
+
+use image::{Rgba, RgbaImage};
+
+use crate::assets::{AssetManager, SamplerConfig, TextureHandle};
+use crate::shapes::{Brush, ShapeRenderer};
+use crate::camera::GPUCamera;
+use crate::canvas::VirtualCanvas;
+use crate::gpus::{Scene, TextureOptions, WGPU};
+use crate::input::Input;
+use crate::locale::{Strings, DEFAULT_LOCALE, LOCALES_DIR};
+use crate::parallax::{ParallaxBackground, ParallaxConfig};
+use crate::screens::{self, MenuScreen, ScreenModel};
+use crate::sprite_game::{BlendMode, GPUSprite, Layer, SpriteRenderer};
+use crate::transition::{ScreenTransitions, Transition};
+use crate::water::{WaterConfig, WaterEffect};
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 96;
+// Different drivers (and software rasterizers) round a little differently
+const CHANNEL_TOLERANCE: u8 = 3;
+const MAX_DIFFERENT_PIXELS: usize = (WIDTH * HEIGHT / 500) as usize;
+
+// Not every machine has a GPU or a software adapter; skip instead of failing there
+fn headless() -> Option<WGPU> {
+    match pollster::block_on(WGPU::headless(WIDTH, HEIGHT)) {
+        Ok(gpu) => Some(gpu),
+        Err(e) => {
+            eprintln!("Skipping render test: {}", e);
+            None
+        }
+    }
+}
+
+// A 4x4 texture: red, green and blue quarters, and a half see-through white one
+fn test_texture(gpu: &WGPU, assets: &mut AssetManager) -> TextureHandle {
+    let img = RgbaImage::from_fn(4, 4, |x, y| match (x < 2, y < 2) {
+        (true, true) => Rgba([255, 0, 0, 255]),
+        (false, true) => Rgba([0, 255, 0, 255]),
+        (true, false) => Rgba([0, 0, 255, 255]),
+        (false, false) => Rgba([255, 255, 255, 128]),
+    });
+    assets.texture_from_image(gpu, "test texture", &img, TextureOptions::DEFAULT)
+}
+
+fn camera() -> GPUCamera {
+    GPUCamera {
+        screen_pos: [0.0, 0.0],
+        screen_size: [WIDTH as f32, HEIGHT as f32],
+    }
+}
+
+fn check_golden(name: &str, actual: &RgbaImage) {
+    let path = PathBuf::from("goldens").join(format!("{}.png", name));
+    if std::env::var_os("UPDATE_GOLDENS").is_some() {
+        std::fs::create_dir_all("goldens").unwrap();
+        actual.save(&path).unwrap();
+        eprintln!("Wrote golden image {}", path.display());
+        return;
+    }
+    if !path.exists() {
+        let actual_path = PathBuf::from("goldens").join(format!("{}.actual.png", name));
+        std::fs::create_dir_all("goldens").unwrap();
+        actual.save(&actual_path).unwrap();
+        panic!("{} has no golden image; see {} and run with UPDATE_GOLDENS=1 to accept it", name, actual_path.display());
+    }
+    let expected = image::open(&path).unwrap().to_rgba8();
+    assert_eq!(expected.dimensions(), actual.dimensions(), "{} changed size", name);
+    let different = expected
+        .pixels()
+        .zip(actual.pixels())
+        .filter(|(e, a)| e.0.iter().zip(a.0.iter()).any(|(e, a)| e.abs_diff(*a) > CHANNEL_TOLERANCE))
+        .count();
+    if different > MAX_DIFFERENT_PIXELS {
+        let actual_path = PathBuf::from("goldens").join(format!("{}.actual.png", name));
+        actual.save(&actual_path).unwrap();
+        panic!("{} differs from its golden in {} pixels, see {}", name, different, actual_path.display());
+    }
+}
+
+#[test]
+fn layered_sprites() {
+    let Some(gpu) = headless() else { return };
+    let mut assets = AssetManager::new(&gpu);
+    let mut renderer = SpriteRenderer::new(&gpu, &assets);
+    let sprites = vec![
+        // Added front to back on purpose: z decides the order, not the vec
+        GPUSprite::new([40.0, 30.0, 40.0, 40.0], [0.5, 0.0, 0.5, 0.5], Layer::Pier.z()),
+        GPUSprite::new([20.0, 20.0, 40.0, 40.0], [0.0, 0.0, 0.5, 0.5], Layer::Fish.z()),
+        GPUSprite::new([0.0, 0.0, WIDTH as f32, 30.0], [0.0, 0.5, 0.5, 0.5], Layer::Sky.z()),
+    ];
+    let tex = test_texture(&gpu, &mut assets);
+    let group = renderer.add_sprite_group(&gpu, &assets, &tex, sprites, camera());
+    renderer.upload_sprites(&gpu, group);
+    check_golden("layered_sprites", &gpu.render_to_image(&renderer, wgpu::Color::BLACK));
+}
+
+#[test]
+fn tint_and_alpha_blending() {
+    let Some(gpu) = headless() else { return };
+    let mut assets = AssetManager::new(&gpu);
+    let mut renderer = SpriteRenderer::new(&gpu, &assets);
+    let sprites = vec![
+        GPUSprite::new([10.0, 10.0, 50.0, 50.0], [0.0, 0.0, 0.5, 0.5], Layer::Fish.z()),
+        // Half see-through white from the texture on top of the red
+        GPUSprite::new([30.0, 30.0, 50.0, 50.0], [0.5, 0.5, 0.5, 0.5], Layer::Pier.z()),
+        // Green at quarter opacity
+        GPUSprite::new([70.0, 10.0, 50.0, 50.0], [0.5, 0.0, 0.5, 0.5], Layer::Pier.z())
+            .with_tint([1.0, 1.0, 1.0, 0.25]),
+    ];
+    let tex = test_texture(&gpu, &mut assets);
+    let group = renderer.add_sprite_group(&gpu, &assets, &tex, sprites, camera());
+    renderer.upload_sprites(&gpu, group);
+    check_golden("tint_and_alpha_blending", &gpu.render_to_image(&renderer, wgpu::Color::BLACK));
+}
+
+#[test]
+fn cutout_and_rotation() {
+    let Some(gpu) = headless() else { return };
+    let mut assets = AssetManager::new(&gpu);
+    let mut renderer = SpriteRenderer::new(&gpu, &assets);
+    let sprites = vec![
+        // The whole texture: in cutout mode the half see-through quarter is drawn solid
+        GPUSprite::new([10.0, 20.0, 48.0, 48.0], [0.0, 0.0, 1.0, 1.0], Layer::Fish.z()),
+        GPUSprite::new([70.0, 20.0, 48.0, 48.0], [0.0, 0.0, 1.0, 1.0], Layer::Fish.z())
+            .with_rotation(std::f32::consts::FRAC_PI_4, [0.5, 0.5]),
+    ];
+    let tex = test_texture(&gpu, &mut assets);
+    let group = renderer.add_sprite_group(&gpu, &assets, &tex, sprites, camera());
+    renderer.set_blend_mode(group, BlendMode::Cutout);
+    renderer.upload_sprites(&gpu, group);
+    check_golden("cutout_and_rotation", &gpu.render_to_image(&renderer, wgpu::Color::BLACK));
+}
+
+#[test]
+fn atlases_are_only_packed_once() {
+    let Some(gpu) = headless() else { return };
+    let mut assets = AssetManager::new(&gpu);
+    let first = assets.load_atlas(&gpu, "nut_war_content", 2);
+    let second = assets.load_atlas(&gpu, "nut_war_content", 2);
+    // The cached texture comes back with the layout it was packed with, not a fresh one
+    assert!(first.texture == second.texture);
+    assert!(std::rc::Rc::ptr_eq(&first.layout, &second.layout));
+    assert_eq!(first.layout.numbered_frames("sq").len(), 5);
+}
+
+#[test]
+fn textures_are_shared_until_dropped() {
+    let Some(gpu) = headless() else { return };
+    let mut assets = AssetManager::new(&gpu);
+    let path = "fishful_content/background.png";
+    let first = pollster::block_on(assets.load_texture(&gpu, path));
+    // Another spelling of the same file gets the same texture
+    let second = pollster::block_on(assets.load_texture(&gpu, "fishful_content/../fishful_content/background.png"));
+    assert!(first == second);
+    assert_eq!(assets.loaded_count(), 1);
+    // Still cached while any handle is alive
+    drop(first);
+    assets.collect_garbage();
+    assert_eq!(assets.loaded_count(), 1);
+    // Once the last one goes it's forgotten, and loading it again makes a new texture
+    drop(second);
+    assets.collect_garbage();
+    assert_eq!(assets.loaded_count(), 0);
+    let again = pollster::block_on(assets.load_texture(&gpu, path));
+    assert_eq!(assets.loaded_count(), 1);
+    assert_eq!(again.generation(), 0);
+}
+
+#[test]
+fn atlases_are_forgotten_with_their_texture() {
+    let Some(gpu) = headless() else { return };
+    let mut assets = AssetManager::new(&gpu);
+    let first = assets.load_atlas(&gpu, "nut_war_content", 2);
+    let layout = std::rc::Rc::downgrade(&first.layout);
+    drop(first);
+    assets.collect_garbage();
+    // Nothing holds the old layout anymore, so the next load packed a new one
+    assert!(layout.upgrade().is_none());
+    let second = assets.load_atlas(&gpu, "nut_war_content", 2);
+    assert_eq!(second.layout.numbered_frames("sq").len(), 5);
+}
+
+#[test]
+fn empty_sprite_groups_draw_nothing() {
+    let Some(gpu) = headless() else { return };
+    let mut assets = AssetManager::new(&gpu);
+    let mut renderer = SpriteRenderer::new(&gpu, &assets);
+    let tex = test_texture(&gpu, &mut assets);
+    // Made before there's anything to put in it, like the particles before the first splash
+    let empty = renderer.add_sprite_group(&gpu, &assets, &tex, Vec::new(), camera());
+    renderer.upload_sprites(&gpu, empty);
+    let group = renderer.add_sprite_group(&gpu, &assets, &tex, vec![GPUSprite::new([0.0, 0.0, WIDTH as f32, HEIGHT as f32], [0.0, 0.0, 0.5, 0.5], Layer::Fish.z())], camera());
+    renderer.upload_sprites(&gpu, group);
+    let img = gpu.render_to_image(&renderer, wgpu::Color::BLACK);
+    assert!(img.pixels().all(|p| *p == Rgba([255, 0, 0, 255])));
+}
+
+#[test]
+fn unwrapping_a_layer_keeps_its_filtering() {
+    let Some(gpu) = headless() else { return };
+    let mut assets = AssetManager::new(&gpu);
+    let mut renderer = SpriteRenderer::new(&gpu, &assets);
+    let mut config = ParallaxConfig::load(crate::parallax::PARALLAX_PATH);
+    let view_size = [crate::canvas::LOGICAL_WIDTH as f32, crate::canvas::LOGICAL_HEIGHT as f32];
+    let mut background = pollster::block_on(ParallaxBackground::new(&gpu, &mut assets, &mut renderer, &config, view_size));
+    // The sky wraps; give it smooth filtering, as if it weren't pixel art
+    let sky = pollster::block_on(assets.load_texture_with(&gpu, &config.layers[1].texture, TextureOptions::MIPMAPPED));
+    assert!(config.layers[1].wrap);
+    sky.set_sampler(SamplerConfig::SMOOTH.repeating());
+    config.layers[1].wrap = false;
+    background.reconfigure(&config).unwrap();
+    assert_eq!(sky.sampler(), SamplerConfig::SMOOTH);
+    config.layers[1].wrap = true;
+    background.reconfigure(&config).unwrap();
+    assert_eq!(sky.sampler(), SamplerConfig::SMOOTH.repeating());
+}
+
+#[test]
+fn readback_strips_row_padding() {
+    // 128 pixels is 512 bytes a row, already aligned; 100 pixels isn't
+    let Ok(gpu) = pollster::block_on(WGPU::headless(100, 3)) else { return };
+    let renderer = SpriteRenderer::new(&gpu, &AssetManager::new(&gpu));
+    let img = gpu.render_to_image(&renderer, wgpu::Color::RED);
+    assert_eq!(img.dimensions(), (100, 3));
+    assert!(img.pixels().all(|p| *p == Rgba([255, 0, 0, 255])));
+}
+
+#[test]
+fn broken_shader_reload_keeps_old_pipelines() {
+    let Some(gpu) = headless() else { return };
+    let mut assets = AssetManager::new(&gpu);
+    let mut renderer = SpriteRenderer::new(&gpu, &assets);
+    let tex = test_texture(&gpu, &mut assets);
+    let sprites = vec![GPUSprite::new([0.0, 0.0, WIDTH as f32, HEIGHT as f32], [0.0, 0.0, 0.5, 0.5], Layer::Fish.z())];
+    let group = renderer.add_sprite_group(&gpu, &assets, &tex, sprites, camera());
+    renderer.upload_sprites(&gpu, group);
+
+    assert!(renderer.reload_shader(&gpu, "fn vs_main( {").is_err());
+    let img = gpu.render_to_image(&renderer, wgpu::Color::BLACK);
+    assert_eq!(*img.get_pixel(WIDTH / 2, HEIGHT / 2), Rgba([255, 0, 0, 255]));
+
+    assert!(renderer.reload_shader(&gpu, include_str!("shader.wgsl")).is_ok());
+}
+
+#[test]
+fn mipmaps_do_not_darken_see_through_edges() {
+    let Some(gpu) = headless() else { return };
+    // Opaque white next to fully transparent black
+    let img = RgbaImage::from_fn(2, 2, |x, _| if x == 0 { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) });
+    let texture = WGPU::texture_from_image(&img, None, TextureOptions::MIPMAPPED, &gpu.device, &gpu.queue);
+    assert_eq!(texture.mip_level_count(), 2);
+    let smaller = gpu.read_texture_level(&texture, 1);
+    assert_eq!(smaller.dimensions(), (1, 1));
+    let [r, g, b, a] = smaller.get_pixel(0, 0).0;
+    // Still white, just half see-through; a plain average would have come out grey
+    assert!(r > 250 && g > 250 && b > 250, "{:?}", (r, g, b));
+    assert!(a.abs_diff(128) <= 1, "{}", a);
+}
+
+#[test]
+fn premultiplied_textures_draw_like_straight_ones() {
+    let Some(gpu) = headless() else { return };
+    let mut assets = AssetManager::new(&gpu);
+    let mut renderer = SpriteRenderer::new(&gpu, &assets);
+    let img = RgbaImage::from_fn(4, 4, |x, y| Rgba([60 * x as u8, 200, 60 * y as u8, 40 + 60 * x as u8]));
+    let straight = assets.texture_from_image(&gpu, "straight", &img, TextureOptions::DEFAULT);
+    let premultiplied = TextureOptions {
+        premultiply_alpha: true,
+        ..TextureOptions::DEFAULT
+    };
+    let premultiplied = assets.texture_from_image(&gpu, "premultiplied", &img, premultiplied);
+    let sprite = |x: f32| {
+        vec![GPUSprite::new([x, 0.0, WIDTH as f32 / 2.0, HEIGHT as f32], [0.0, 0.0, 1.0, 1.0], Layer::Fish.z())
+            .with_tint([1.0, 0.5, 1.0, 0.75])]
+    };
+    renderer.add_sprite_group(&gpu, &assets, &straight, sprite(0.0), camera());
+    renderer.add_sprite_group(&gpu, &assets, &premultiplied, sprite(WIDTH as f32 / 2.0), camera());
+    let out = gpu.render_to_image(&renderer, wgpu::Color::BLUE);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH / 2 {
+            let (left, right) = (out.get_pixel(x, y).0, out.get_pixel(x + WIDTH / 2, y).0);
+            // Premultiplying in 8 bits loses a little precision
+            assert!(left.iter().zip(right.iter()).all(|(l, r)| l.abs_diff(*r) <= 4), "{:?} vs {:?} at {},{}", left, right, x, y);
+        }
+    }
+}
+
+#[test]
+fn water_only_changes_what_is_under_the_waterline() {
+    let Some(gpu) = headless() else { return };
+    let config = WaterConfig {
+        waterline: HEIGHT as f32 / 2.0,
+        wave_amplitude: 0.0,
+        refraction: 0.0,
+        surface_color: [1.0, 1.0, 1.0, 0.0],
+        deep_tint: [0.0, 0.0, 0.0, 1.0],
+        ..WaterConfig::default()
+    };
+    let water = WaterEffect::new(&gpu, WIDTH, HEIGHT, config);
+    let target = gpu.device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width: WIDTH,
+            height: HEIGHT,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: gpu.config.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    // A plain white scene
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: None,
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: water.scene_view(),
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: None,
+    });
+    water.apply(&gpu, &mut encoder, &target.create_view(&wgpu::TextureViewDescriptor::default()));
+    gpu.queue.submit(Some(encoder.finish()));
+    let img = gpu.read_texture(&target);
+
+    // Above the water it's untouched, and it gets darker all the way to the bottom
+    assert_eq!(*img.get_pixel(10, 5), Rgba([255, 255, 255, 255]));
+    let brightness = |y: u32| img.get_pixel(10, y).0[0];
+    assert!(brightness(HEIGHT / 2 + 4) > brightness(HEIGHT * 3 / 4));
+    // Not quite black: the bottom row is a hair above full depth, and sRGB brightens that hair
+    assert!(brightness(HEIGHT - 1) < 40, "{}", brightness(HEIGHT - 1));
+}
+
+// A canvas-sized texture that can be drawn into, copied from and read back
+fn screen_texture(gpu: &WGPU) -> wgpu::Texture {
+    gpu.device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width: WIDTH,
+            height: HEIGHT,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: gpu.config.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+fn clear(encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, color: wgpu::Color) {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: None,
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(color),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: None,
+    });
+}
+
+#[test]
+fn canvas_averages_detail_when_scaled_down() {
+    let Some(gpu) = headless() else { return };
+    // Four times bigger than the window, so every window pixel covers 4x4 canvas pixels
+    let (width, height) = (WIDTH * 4, HEIGHT * 4);
+    let canvas = VirtualCanvas::new(&gpu, width, height, false);
+    // One-pixel white stripes on black, the worst case for skipping pixels
+    let mut shapes = ShapeRenderer::new(&gpu);
+    for x in (0..width).step_by(2) {
+        shapes.rect(Brush::canvas(Layer::Sky), [x as f32, 0.0, 1.0, height as f32], [1.0, 1.0, 1.0, 1.0]);
+    }
+    shapes.prepare(&gpu, &camera(), [width as f32, height as f32]);
+    let window = screen_texture(&gpu);
+    let window_view = window.create_view(&wgpu::TextureViewDescriptor::default());
+    let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &canvas.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        shapes.draw(&mut rpass);
+    }
+    canvas.present(&mut encoder, &window_view);
+    gpu.queue.submit(Some(encoder.finish()));
+    let img = gpu.read_texture(&window);
+    // Every pixel comes out the same middling grey, instead of some black and some white
+    for x in 0..WIDTH {
+        let [r, g, b, _] = img.get_pixel(x, HEIGHT / 2).0;
+        assert!((150..=220).contains(&r) && r == g && g == b, "{:?} at {}", [r, g, b], x);
+    }
+}
+
+#[test]
+fn transitions_go_from_the_old_screen_to_the_new_one() {
+    let Some(gpu) = headless() else { return };
+    let mut transitions = ScreenTransitions::new(&gpu, WIDTH, HEIGHT);
+    let canvas = screen_texture(&gpu);
+    let canvas_view = canvas.create_view(&wgpu::TextureViewDescriptor::default());
+    // The old screen is red and the new one is blue; returns the canvas after `time` seconds of a
+    // one second transition
+    let mut frame_at = |transition: Transition, time: f32| {
+        let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        clear(&mut encoder, &canvas_view, wgpu::Color::RED);
+        gpu.queue.submit(Some(encoder.finish()));
+        transitions.start(&gpu, transition, &canvas);
+        transitions.update(time);
+        let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        clear(&mut encoder, transitions.scene_view(), wgpu::Color::BLUE);
+        transitions.apply(&gpu, &mut encoder, &canvas_view);
+        gpu.queue.submit(Some(encoder.finish()));
+        gpu.read_texture(&canvas)
+    };
+    let (left, middle, right, corner) = ((4, HEIGHT / 2), (WIDTH / 2, HEIGHT / 2), (WIDTH - 4, HEIGHT / 2), (0, 0));
+    let red = Rgba([255, 0, 0, 255]);
+    let blue = Rgba([0, 0, 255, 255]);
+    let black = Rgba([0, 0, 0, 255]);
+
+    let wipe = frame_at(Transition::wipe(1.0), 0.5);
+    assert_eq!(*wipe.get_pixel(left.0, left.1), blue);
+    assert_eq!(*wipe.get_pixel(right.0, right.1), red);
+
+    let crossfade = frame_at(Transition::crossfade(1.0), 0.5);
+    let [r, g, b, _] = crossfade.get_pixel(middle.0, middle.1).0;
+    assert!(r > 100 && b > 100 && g == 0, "{:?}", [r, g, b]);
+
+    // Halfway through a fade it's all the color
+    let fade = frame_at(Transition::fade(1.0, [0.0, 1.0, 0.0, 1.0]), 0.5);
+    assert_eq!(*fade.get_pixel(middle.0, middle.1), Rgba([0, 255, 0, 255]));
+
+    // The iris closes on the old screen, then opens on the new one
+    let closing = frame_at(Transition::iris(1.0), 0.25);
+    assert_eq!(*closing.get_pixel(middle.0, middle.1), red);
+    assert_eq!(*closing.get_pixel(corner.0, corner.1), black);
+    let opening = frame_at(Transition::iris(1.0), 0.75);
+    assert_eq!(*opening.get_pixel(middle.0, middle.1), blue);
+    assert_eq!(*opening.get_pixel(corner.0, corner.1), black);
+
+    // And once it's over the game goes back to drawing straight into the canvas
+    transitions.update(1.0);
+    assert!(!transitions.is_running());
+}
+
+#[test]
+fn shapes_in_world_and_canvas_space() {
+    let Some(gpu) = headless() else { return };
+    let mut shapes = ShapeRenderer::new(&gpu);
+    let canvas_size = [WIDTH as f32, HEIGHT as f32];
+    // A filled rect in the world (y up) and the same size outline on the canvas (y down) should
+    // cover the same pixels
+    shapes.rect(Brush::world(Layer::Fish), [8.0, 8.0, 24.0, 16.0], [1.0, 0.0, 0.0, 1.0]);
+    shapes.rect_outline(Brush::canvas(Layer::Ui), [8.0, HEIGHT as f32 - 24.0, 24.0, 16.0], 2.0, [0.0, 1.0, 0.0, 1.0]);
+    shapes.circle(Brush::world(Layer::Fish), [80.0, 60.0], 20.0, [0.0, 0.0, 1.0, 0.5]);
+    shapes.polyline(Brush::canvas(Layer::Sky), &[[40.0, 90.0], [64.0, 50.0], [120.0, 70.0]], 4.0, [1.0, 1.0, 1.0, 1.0]);
+    shapes.prepare(&gpu, &camera(), canvas_size);
+    let img = gpu.render_to_image(&shapes, wgpu::Color::BLACK);
+    let green = Rgba([0, 255, 0, 255]);
+    let red = Rgba([255, 0, 0, 255]);
+    assert_eq!(*img.get_pixel(8, HEIGHT - 24), green);
+    assert_eq!(*img.get_pixel(20, HEIGHT - 16), red);
+    check_golden("shapes_in_world_and_canvas_space", &img);
+}
+
+// Each screen put together the way main.rs does it: the parallax background with that screen's UI
+// over it. Text isn't drawn, since fonts differ from machine to machine.
+struct ScreenFixture {
+    gpu: WGPU,
+    assets: AssetManager,
+    renderer: SpriteRenderer,
+    font_system: glyphon::FontSystem,
+    sheet: TextureHandle,
+    model: ScreenModel,
+}
+
+fn screen_fixture() -> Option<ScreenFixture> {
+    let gpu = headless()?;
+    let mut assets = AssetManager::new(&gpu);
+    let mut renderer = SpriteRenderer::new(&gpu, &assets);
+    let sheet = pollster::block_on(assets.load_texture(&gpu, "fishful_content/fishful_spritesheet.png"));
+    let view_size = [crate::canvas::LOGICAL_WIDTH as f32, crate::canvas::LOGICAL_HEIGHT as f32];
+    let config = ParallaxConfig::load(crate::parallax::PARALLAX_PATH);
+    pollster::block_on(ParallaxBackground::new(&gpu, &mut assets, &mut renderer, &config, view_size));
+    let model = ScreenModel {
+        score: 12,
+        secs_left: 20,
+        round_length: 60,
+        keys: Default::default(),
+        strings: std::rc::Rc::new(Strings::load(LOCALES_DIR, DEFAULT_LOCALE)),
+    };
+    Some(ScreenFixture {
+        gpu,
+        assets,
+        renderer,
+        font_system: glyphon::FontSystem::new(),
+        sheet,
+        model,
+    })
+}
+
+fn check_menu_screen(name: &str, build: fn(&mut glyphon::FontSystem) -> MenuScreen) {
+    let Some(mut f) = screen_fixture() else { return };
+    let mut screen = build(&mut f.font_system);
+    screen.add_to_renderer(&f.gpu, &mut f.assets, &mut f.renderer, &f.sheet);
+    // Active, so the first item shows as selected
+    screen.update(&mut f.font_system, &f.model, &Input::default(), true);
+    screen.sync(&f.gpu, &mut f.renderer);
+    check_golden(name, &f.gpu.render_to_image(&f.renderer, wgpu::Color::BLACK));
+}
+
+#[test]
+fn title_screen() {
+    check_menu_screen("title_screen", MenuScreen::title);
+}
+
+#[test]
+fn end_screen() {
+    check_menu_screen("end_screen", MenuScreen::end);
+}
+
+#[test]
+fn play_screen_hud() {
+    let Some(mut f) = screen_fixture() else { return };
+    // The small fish, as main.rs cuts it out of the sheet
+    let (width, height) = f.sheet.size();
+    let fish_icon = [0.0, 1.0 / height as f32, 12.0 / width as f32, 6.0 / height as f32];
+    let (mut hud, _, _) = screens::hud(&mut f.font_system, fish_icon, 10.0);
+    hud.add_to_renderer(&f.gpu, &mut f.assets, &mut f.renderer, &f.sheet);
+    hud.update(&mut f.font_system, &f.model, &Input::default());
+    hud.sync(&f.gpu, &mut f.renderer);
+    check_golden("play_screen_hud", &f.gpu.render_to_image(&f.renderer, wgpu::Color::BLACK));
+}
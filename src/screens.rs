@@ -0,0 +1,272 @@
+// The title, instructions and end screens, laid out with the UI module over the play screen's
+// background instead of being baked into PNGs, so they can show the score and the current key bindings.
+
+use std::rc::Rc;
+
+use glyphon::FontSystem;
+
+use crate::assets::{AssetManager, TextureHandle};
+use crate::gpus::WGPU;
+use crate::input::{Input, Key};
+use crate::locale::Strings;
+use crate::settings::KeyBindings;
+use crate::sprite_game::SpriteRenderer;
+use crate::ui::{bind, Anchor, Binding, Ui, Widget, WidgetId};
+
+// What these screens and the HUD show, copied out of the game every frame
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScreenModel {
+    pub score: usize,
+    pub secs_left: usize,
+    pub round_length: usize,
+    pub keys: KeyBindings,
+    // Shared rather than copied; bindings that include it re-shape when the language changes
+    pub strings: Rc<Strings>,
+}
+
+// What picking a menu item asks main.rs to do
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuAction {
+    Start,
+    Settings,
+    Play,
+    PlayAgain,
+    Quit,
+}
+
+const SIGN_COLOR: [f32; 4] = [0.12, 0.42, 0.2, 0.9];
+const PANEL_COLOR: [f32; 4] = [0.0, 0.05, 0.15, 0.6];
+const BUTTON_SIZE: [f32; 2] = [280.0, 50.0];
+
+// How a key shows up in hints, e.g. "Space"
+pub fn key_name(key: Key) -> String {
+    format!("{:?}", key)
+}
+
+// Text that's just one string from the tables
+pub fn text(key: &'static str) -> Binding<ScreenModel> {
+    bind(|model: &ScreenModel| model.strings.clone(), move |strings| strings.get(key))
+}
+
+// The play screen's HUD: score and time left on a panel, and a button to end the round early. The text
+// follows the game state by itself and only gets laid out again when it changes. Returns the panel and
+// the button too, since the game slides them in and listens for clicks. `fish_icon` is the sheet region
+// of the fish shown next to the score.
+pub fn hud(font_system: &mut FontSystem, fish_icon: [f32; 4], top: f32) -> (Ui<ScreenModel>, WidgetId, WidgetId) {
+    let mut hud: Ui<ScreenModel> = Ui::new([crate::canvas::LOGICAL_WIDTH as f32, crate::canvas::LOGICAL_HEIGHT as f32]);
+    let panel = hud.add(font_system, Widget::panel([330.0, 100.0], [0.0, 0.05, 0.15, 0.45]).offset([10.0, top]));
+    hud.add(
+        font_system,
+        Widget::icon_row(
+            fish_icon,
+            [48.0, 29.0],
+            bind(
+                |model: &ScreenModel| (model.strings.clone(), model.score),
+                |(strings, score)| strings.format("hud.score", &[("score", score)]),
+            ),
+        )
+        .font(34.0, 40.0)
+        .parent(panel)
+        .offset([12.0, 8.0]),
+    );
+    hud.add(
+        font_system,
+        Widget::label(bind(
+            |model: &ScreenModel| (model.strings.clone(), model.secs_left),
+            |(strings, secs)| strings.plural("hud.seconds", *secs as i64, &[]),
+        ))
+            .font(24.0, 28.0)
+            .parent(panel)
+            .anchor(Anchor::BottomLeft)
+            .offset([12.0, -10.0]),
+    );
+    hud.add(
+        font_system,
+        Widget::progress_bar(
+            [240.0, 14.0],
+            |model: &ScreenModel| model.secs_left as f32 / model.round_length.max(1) as f32,
+            [0.0, 0.0, 0.0, 0.5],
+            [1.0, 0.8, 0.3, 1.0],
+        )
+        .parent(panel)
+        .anchor(Anchor::BottomRight)
+        .offset([-12.0, -16.0]),
+    );
+    let end_round_button = hud.add(
+        font_system,
+        Widget::button(text("hud.end_round"), [150.0, 44.0]).font(22.0, 26.0).anchor(Anchor::TopRight).offset([-10.0, top]),
+    );
+    (hud, panel, end_round_button)
+}
+
+// One screen with a list of buttons to pick from. Like the settings screen it's moved through with the
+// arrows, and Return always picks (as well as the Confirm key), so a bad key binding can't leave you
+// stuck; the mouse works too.
+pub struct MenuScreen {
+    ui: Ui<ScreenModel>,
+    items: Vec<(WidgetId, MenuAction)>,
+    selected: usize,
+}
+
+impl MenuScreen {
+    fn new() -> Self {
+        Self {
+            ui: Ui::new([crate::canvas::LOGICAL_WIDTH as f32, crate::canvas::LOGICAL_HEIGHT as f32]),
+            items: Vec::new(),
+            selected: 0,
+        }
+    }
+
+    // Buttons stack down from the middle of the screen
+    fn add_item(&mut self, font_system: &mut FontSystem, text: Binding<ScreenModel>, action: MenuAction, first_y: f32) {
+        let y = first_y + self.items.len() as f32 * (BUTTON_SIZE[1] + 12.0);
+        let id = self.ui.add(
+            font_system,
+            Widget::button(text, BUTTON_SIZE).font(28.0, 32.0).anchor(Anchor::Center).offset([0.0, y]),
+        );
+        self.items.push((id, action));
+    }
+
+    pub fn title(font_system: &mut FontSystem) -> Self {
+        let mut screen = Self::new();
+        let sign = screen.ui.add(font_system, Widget::panel([560.0, 210.0], SIGN_COLOR).anchor(Anchor::Top).offset([0.0, 90.0]));
+        screen.ui.add(font_system, Widget::label("FISHFUL").font(96.0, 110.0).parent(sign).anchor(Anchor::Top).offset([0.0, 14.0]));
+        screen.ui.add(font_system, Widget::label("Thinking").font(40.0, 46.0).parent(sign).anchor(Anchor::Bottom).offset([0.0, -24.0]));
+        screen.add_item(font_system, text("menu.start"), MenuAction::Start, 40.0);
+        screen.add_item(font_system, text("menu.settings"), MenuAction::Settings, 40.0);
+        screen.add_item(font_system, text("menu.quit"), MenuAction::Quit, 40.0);
+        screen.ui.add(
+            font_system,
+            Widget::label(bind(
+                |model: &ScreenModel| (model.strings.clone(), model.keys.settings),
+                |(strings, settings)| strings.format("title.hint", &[("key", &key_name(*settings))]),
+            ))
+            .font(22.0, 26.0)
+            .anchor(Anchor::Bottom)
+            .offset([0.0, -24.0]),
+        );
+        screen
+    }
+
+    pub fn instructions(font_system: &mut FontSystem) -> Self {
+        let mut screen = Self::new();
+        let panel = screen.ui.add(font_system, Widget::panel([720.0, 400.0], PANEL_COLOR).anchor(Anchor::Center).offset([0.0, -60.0]));
+        screen.ui.add(font_system, Widget::label(text("instructions.heading")).font(48.0, 56.0).parent(panel).anchor(Anchor::Top).offset([0.0, 20.0]));
+        // Only laid out again if a binding changes
+        screen.ui.add(
+            font_system,
+            Widget::label(bind(
+                |model: &ScreenModel| (model.strings.clone(), model.keys.clone()),
+                |(strings, keys)| {
+                    strings.format(
+                        "instructions.controls",
+                        &[
+                            ("left", &key_name(keys.left)),
+                            ("right", &key_name(keys.right)),
+                            ("cast", &key_name(keys.cast)),
+                            ("drop", &key_name(keys.drop_line)),
+                            ("reel", &key_name(keys.reel_in)),
+                            ("end", &key_name(keys.end_round)),
+                        ],
+                    )
+                },
+            ))
+            .font(28.0, 40.0)
+            .parent(panel)
+            .offset([40.0, 100.0]),
+        );
+        let play = screen.ui.add(
+            font_system,
+            Widget::button(
+                bind(
+                    |model: &ScreenModel| (model.strings.clone(), model.keys.play),
+                    |(strings, play)| strings.format("menu.play", &[("key", &key_name(*play))]),
+                ),
+                BUTTON_SIZE,
+            )
+                .font(28.0, 32.0)
+                .anchor(Anchor::Bottom)
+                .offset([0.0, -90.0]),
+        );
+        screen.items.push((play, MenuAction::Play));
+        screen
+    }
+
+    pub fn end(font_system: &mut FontSystem) -> Self {
+        let mut screen = Self::new();
+        let sign = screen.ui.add(font_system, Widget::panel([560.0, 240.0], SIGN_COLOR).anchor(Anchor::Top).offset([0.0, 90.0]));
+        screen.ui.add(font_system, Widget::label(text("end.heading")).font(80.0, 92.0).parent(sign).anchor(Anchor::Top).offset([0.0, 24.0]));
+        screen.ui.add(
+            font_system,
+            Widget::label(bind(
+                |model: &ScreenModel| (model.strings.clone(), model.score),
+                |(strings, score)| strings.format("end.final_score", &[("score", score)]),
+            ))
+                .font(44.0, 50.0)
+                .parent(sign)
+                .anchor(Anchor::Bottom)
+                .offset([0.0, -36.0]),
+        );
+        screen.add_item(font_system, text("menu.play_again"), MenuAction::PlayAgain, 100.0);
+        screen.add_item(font_system, text("menu.quit"), MenuAction::Quit, 100.0);
+        screen.ui.add(
+            font_system,
+            Widget::label(bind(
+                |model: &ScreenModel| (model.strings.clone(), model.keys.restart),
+                |(strings, restart)| strings.format("end.hint", &[("key", &key_name(*restart))]),
+            ))
+            .font(22.0, 26.0)
+            .anchor(Anchor::Bottom)
+            .offset([0.0, -24.0]),
+        );
+        screen
+    }
+
+    pub(crate) fn add_to_renderer(&mut self, gpu: &WGPU, assets: &mut AssetManager, renderer: &mut SpriteRenderer, icons: &TextureHandle) {
+        self.ui.add_to_renderer(gpu, assets, renderer, icons);
+    }
+
+    // Refresh the text from `model`, and if this is the screen being shown (`active`), move the
+    // selection and return whatever got picked
+    pub fn update(&mut self, font_system: &mut FontSystem, model: &ScreenModel, input: &Input, active: bool) -> Option<MenuAction> {
+        let confirm = model.keys.confirm;
+        self.ui.update(font_system, model, input);
+        if !active || self.items.is_empty() {
+            return None;
+        }
+        let count = self.items.len();
+        if input.is_key_pressed(Key::Up) {
+            self.selected = (self.selected + count - 1) % count;
+        }
+        if input.is_key_pressed(Key::Down) {
+            self.selected = (self.selected + 1) % count;
+        }
+        // Pointing at an item selects it too
+        if let Some(hovered) = self.items.iter().position(|&(id, _)| self.ui.hovered(id)) {
+            self.selected = hovered;
+        }
+        for (index, &(id, _)) in self.items.iter().enumerate() {
+            self.ui.set_focused(id, index == self.selected);
+        }
+        let clicked = self.items.iter().find(|&&(id, _)| self.ui.clicked(id)).map(|&(_, action)| action);
+        let picked = input.is_key_pressed(Key::Return) || input.is_key_pressed(confirm);
+        clicked.or_else(|| picked.then_some(self.items[self.selected].1))
+    }
+
+    // Back to the first item, for the next time the screen comes up
+    pub fn reset_selection(&mut self) {
+        self.selected = 0;
+    }
+
+    pub(crate) fn sync(&self, gpu: &WGPU, renderer: &mut SpriteRenderer) {
+        self.ui.sync(gpu, renderer);
+    }
+
+    pub fn set_visible(&mut self, renderer: &mut SpriteRenderer, visible: bool) {
+        self.ui.set_visible(renderer, visible);
+    }
+
+    pub fn text_areas(&self) -> impl Iterator<Item = glyphon::TextArea<'_>> {
+        self.ui.text_areas()
+    }
+}
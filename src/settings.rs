@@ -0,0 +1,522 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::input::{Input, Key};
+use crate::locale::{self, Strings};
+use crate::water::WaterConfig;
+
+// Where the settings live, relative to the working directory (same as the content folders).
+pub const SETTINGS_PATH: &str = "settings.toml";
+
+// The resolutions the settings screen lets you cycle through.
+const RESOLUTIONS: [(u32, u32); 5] = [(800, 600), (1024, 768), (1280, 960), (1600, 1200), (1920, 1080)];
+
+// Which key does what. These replace the hardcoded VirtualKeyCodes in main.rs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub left: Key,
+    pub right: Key,
+    pub drop_line: Key,
+    pub reel_in: Key,
+    pub cast: Key,
+    // pick the highlighted menu item (Return always works too)
+    pub confirm: Key,
+    // instructions -> play
+    pub play: Key,
+    // end the round early
+    pub end_round: Key,
+    // end screen -> title
+    pub restart: Key,
+    // title -> settings
+    pub settings: Key,
+    // save the current frame as a PNG
+    pub screenshot: Key,
+    // save the last few seconds as a GIF
+    pub record_clip: Key,
+    // hitboxes, an inspector and a frame-time graph
+    pub debug_overlay: Key,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            left: Key::Left,
+            right: Key::Right,
+            drop_line: Key::Down,
+            reel_in: Key::Up,
+            cast: Key::Space,
+            confirm: Key::Return,
+            play: Key::P,
+            end_round: Key::E,
+            restart: Key::A,
+            settings: Key::S,
+            screenshot: Key::F12,
+            record_clip: Key::F11,
+            debug_overlay: Key::F3,
+        }
+    }
+}
+
+impl KeyBindings {
+    // String keys for the names of, and mutable references to, every binding, in the order the
+    // settings screen lists them.
+    fn entries_mut(&mut self) -> [(&'static str, &mut Key); KEY_ROWS] {
+        [
+            ("settings.keys.walk_left", &mut self.left),
+            ("settings.keys.walk_right", &mut self.right),
+            ("settings.keys.drop_line", &mut self.drop_line),
+            ("settings.keys.reel_in", &mut self.reel_in),
+            ("settings.keys.cast", &mut self.cast),
+            ("settings.keys.confirm", &mut self.confirm),
+            ("settings.keys.play", &mut self.play),
+            ("settings.keys.end_round", &mut self.end_round),
+            ("settings.keys.restart", &mut self.restart),
+            ("settings.keys.settings", &mut self.settings),
+            ("settings.keys.screenshot", &mut self.screenshot),
+            ("settings.keys.record_clip", &mut self.record_clip),
+            ("settings.keys.debug_overlay", &mut self.debug_overlay),
+        ]
+    }
+
+    // Give `key` to the binding in row `index`. Whatever already had that key takes this binding's old
+    // one, so two actions never end up on the same key.
+    fn rebind(&mut self, index: usize, key: Key) {
+        let mut entries = self.entries_mut();
+        let old = *entries[index].1;
+        for (_, bound) in entries.iter_mut() {
+            if **bound == key {
+                **bound = old;
+            }
+        }
+        *entries[index].1 = key;
+    }
+
+    // Whether some key is bound to more than one action
+    fn has_duplicates(&mut self) -> bool {
+        let mut seen = HashSet::new();
+        !self.entries_mut().into_iter().all(|(_, key)| seen.insert(*key))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub window_width: u32,
+    pub window_height: u32,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    // Scale the 1024x768 canvas by whole numbers only (sharper, but with bigger black bars)
+    pub integer_scaling: bool,
+    // The wavy underwater post-process; turn it off on slow machines
+    pub water_effect: bool,
+    // volumes are 0.0 to 1.0
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    // how long a round lasts, in seconds
+    pub round_length: usize,
+    // Which string table to use, like "en" or "pt-BR"; see locale.rs
+    pub locale: String,
+    pub keys: KeyBindings,
+    // Where the waterline is and how the waves and tint look, for when the effect is on
+    pub water: WaterConfig,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            window_width: 1024,
+            window_height: 768,
+            fullscreen: false,
+            vsync: true,
+            integer_scaling: false,
+            water_effect: true,
+            master_volume: 1.0,
+            music_volume: 0.8,
+            sfx_volume: 0.8,
+            round_length: 30,
+            locale: locale::DEFAULT_LOCALE.to_string(),
+            keys: KeyBindings::default(),
+            water: WaterConfig::default(),
+        }
+    }
+}
+
+impl Settings {
+    // Load settings from a TOML file. A missing or broken file isn't fatal, we just fall back to defaults.
+    pub fn load(path: impl AsRef<Path>) -> Settings {
+        let path = path.as_ref();
+        let mut settings = match std::fs::read_to_string(path) {
+            Ok(text) => match toml::from_str::<Settings>(&text) {
+                Ok(settings) => settings,
+                Err(e) => {
+                    log::warn!("Couldn't parse {}, using default settings: {}", path.display(), e);
+                    Settings::default()
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Settings::default(),
+            Err(e) => {
+                log::warn!("Couldn't read {}, using default settings: {}", path.display(), e);
+                Settings::default()
+            }
+        };
+        settings.validate();
+        settings
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let text = toml::to_string_pretty(self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, text)
+    }
+
+    // Clamp everything into a sensible range so a hand-edited file can't break the game.
+    pub fn validate(&mut self) {
+        self.window_width = self.window_width.clamp(320, 7680);
+        self.window_height = self.window_height.clamp(240, 4320);
+        self.master_volume = clamp_volume(self.master_volume);
+        self.music_volume = clamp_volume(self.music_volume);
+        self.sfx_volume = clamp_volume(self.sfx_volume);
+        self.round_length = self.round_length.clamp(5, 600);
+        self.water.validate();
+        // One of the two actions would never get its key, so a file like that gets the default keys back
+        if self.keys.has_duplicates() {
+            log::warn!("Some keys are bound to more than one action, using the default key bindings");
+            self.keys = KeyBindings::default();
+        }
+        if self.locale.trim().is_empty() {
+            self.locale = locale::DEFAULT_LOCALE.to_string();
+        }
+    }
+
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        if self.vsync {
+            wgpu::PresentMode::AutoVsync
+        } else {
+            wgpu::PresentMode::AutoNoVsync
+        }
+    }
+
+    pub fn window_size(&self) -> winit::dpi::PhysicalSize<u32> {
+        winit::dpi::PhysicalSize::new(self.window_width, self.window_height)
+    }
+
+    pub fn fullscreen_mode(&self) -> Option<winit::window::Fullscreen> {
+        if self.fullscreen {
+            Some(winit::window::Fullscreen::Borderless(None))
+        } else {
+            None
+        }
+    }
+}
+
+fn clamp_volume(v: f32) -> f32 {
+    if v.is_nan() {
+        1.0
+    } else {
+        v.clamp(0.0, 1.0)
+    }
+}
+
+// What the settings screen wants main.rs to do after handling input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingsChange {
+    None,
+    // Something that only needs the text redrawn
+    Value,
+    Resolution,
+    Fullscreen,
+    PresentMode,
+    Scaling,
+    // The strings need loading again
+    Locale,
+    // Leave the settings screen
+    Close,
+}
+
+// The state of the settings screen: which row is highlighted and whether we're waiting on a key to rebind.
+#[derive(Default)]
+pub struct SettingsMenu {
+    pub selected: usize,
+    pub rebinding: bool,
+}
+
+// Rows before the key bindings start
+const FIXED_ROWS: usize = 10;
+const KEY_ROWS: usize = 13;
+const BACK_ROW: usize = FIXED_ROWS + KEY_ROWS;
+
+impl SettingsMenu {
+    // Menu navigation always uses the arrows, Return and Escape so a bad binding can't lock you out.
+    pub fn update(&mut self, input: &Input, settings: &mut Settings) -> SettingsChange {
+        if self.rebinding {
+            if let Some(key) = input.last_key_pressed() {
+                if key != Key::Escape {
+                    settings.keys.rebind(self.selected - FIXED_ROWS, key);
+                }
+                self.rebinding = false;
+                return SettingsChange::Value;
+            }
+            return SettingsChange::None;
+        }
+        if input.is_key_pressed(Key::Escape) {
+            return SettingsChange::Close;
+        }
+        if input.is_key_pressed(Key::Up) {
+            self.selected = (self.selected + BACK_ROW) % (BACK_ROW + 1);
+            return SettingsChange::Value;
+        }
+        if input.is_key_pressed(Key::Down) {
+            self.selected = (self.selected + 1) % (BACK_ROW + 1);
+            return SettingsChange::Value;
+        }
+        let step = if input.is_key_pressed(Key::Right) {
+            1
+        } else if input.is_key_pressed(Key::Left) {
+            -1
+        } else {
+            0
+        };
+        let enter = input.is_key_pressed(Key::Return);
+        if step == 0 && !enter {
+            return SettingsChange::None;
+        }
+        match self.selected {
+            0 => {
+                let current = RESOLUTIONS
+                    .iter()
+                    .position(|&r| r == (settings.window_width, settings.window_height))
+                    .unwrap_or(1);
+                let next = (current as i32 + if step == 0 { 1 } else { step })
+                    .rem_euclid(RESOLUTIONS.len() as i32) as usize;
+                (settings.window_width, settings.window_height) = RESOLUTIONS[next];
+                SettingsChange::Resolution
+            }
+            1 => {
+                settings.fullscreen = !settings.fullscreen;
+                SettingsChange::Fullscreen
+            }
+            2 => {
+                settings.vsync = !settings.vsync;
+                SettingsChange::PresentMode
+            }
+            3 => {
+                settings.integer_scaling = !settings.integer_scaling;
+                SettingsChange::Scaling
+            }
+            // Read every frame, so there's nothing else to do
+            4 => {
+                settings.water_effect = !settings.water_effect;
+                SettingsChange::Value
+            }
+            5 => {
+                settings.master_volume = clamp_volume(settings.master_volume + step as f32 * 0.1);
+                SettingsChange::Value
+            }
+            6 => {
+                settings.music_volume = clamp_volume(settings.music_volume + step as f32 * 0.1);
+                SettingsChange::Value
+            }
+            7 => {
+                settings.sfx_volume = clamp_volume(settings.sfx_volume + step as f32 * 0.1);
+                SettingsChange::Value
+            }
+            8 => {
+                settings.round_length = (settings.round_length as i32 + step * 5).clamp(5, 600) as usize;
+                SettingsChange::Value
+            }
+            // Goes through whatever tables are in the locales folder
+            9 => {
+                let locales = locale::available(locale::LOCALES_DIR);
+                let current = locales.iter().position(|l| *l == settings.locale).unwrap_or(0);
+                let next = (current as i32 + if step == 0 { 1 } else { step }).rem_euclid(locales.len() as i32) as usize;
+                settings.locale = locales[next].clone();
+                SettingsChange::Locale
+            }
+            BACK_ROW => {
+                if enter {
+                    SettingsChange::Close
+                } else {
+                    SettingsChange::None
+                }
+            }
+            _ => {
+                if enter {
+                    self.rebinding = true;
+                    SettingsChange::Value
+                } else {
+                    SettingsChange::None
+                }
+            }
+        }
+    }
+
+    // The whole screen as one block of text, with a marker on the selected row.
+    pub fn text(&self, settings: &Settings, strings: &Strings) -> String {
+        let on_off = |b: bool| strings.get(if b { "settings.on" } else { "settings.off" });
+        let value = |key: &str, value: &dyn std::fmt::Display| strings.format(key, &[("value", value)]);
+        let percent = |v: f32| (v * 100.0).round() as i32;
+        let mut rows = vec![
+            strings.format("settings.resolution", &[("width", &settings.window_width), ("height", &settings.window_height)]),
+            value("settings.fullscreen", &on_off(settings.fullscreen)),
+            value("settings.vsync", &on_off(settings.vsync)),
+            value(
+                "settings.scaling",
+                &strings.get(if settings.integer_scaling { "settings.scaling_integer" } else { "settings.scaling_fit" }),
+            ),
+            value("settings.water", &on_off(settings.water_effect)),
+            value("settings.master_volume", &percent(settings.master_volume)),
+            value("settings.music_volume", &percent(settings.music_volume)),
+            value("settings.effects_volume", &percent(settings.sfx_volume)),
+            strings.plural("settings.round_length", settings.round_length as i64, &[]),
+            value("settings.language", &strings.get("language.name")),
+        ];
+        let mut keys = settings.keys.clone();
+        for (i, (name, key)) in keys.entries_mut().into_iter().enumerate() {
+            let name = strings.get(name);
+            if self.rebinding && self.selected == FIXED_ROWS + i {
+                rows.push(strings.format("settings.press_key", &[("name", &name)]));
+            } else {
+                rows.push(strings.format("settings.key", &[("name", &name), ("key", &format!("{:?}", key))]));
+            }
+        }
+        rows.push(strings.get("settings.back"));
+
+        let mut text = strings.get("settings.heading");
+        text.push_str("\n\n");
+        for (i, row) in rows.iter().enumerate() {
+            text.push_str(if i == self.selected { "> " } else { "   " });
+            text.push_str(row);
+            text.push('\n');
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::event::ElementState;
+
+    // Run one frame of the menu with `key` pressed
+    fn press(menu: &mut SettingsMenu, settings: &mut Settings, input: &mut Input, key: Key) -> SettingsChange {
+        input.handle_key(key, ElementState::Pressed);
+        let change = menu.update(input, settings);
+        input.next_frame();
+        input.handle_key(key, ElementState::Released);
+        input.next_frame();
+        change
+    }
+
+    // The settings menu, on the row for `action`
+    fn menu_on(action: &str) -> SettingsMenu {
+        let row = KeyBindings::default().entries_mut().iter().position(|(name, _)| *name == action).unwrap();
+        SettingsMenu { selected: FIXED_ROWS + row, rebinding: false }
+    }
+
+    #[test]
+    fn validate_clamps_hand_edited_values() {
+        let mut settings = Settings {
+            window_width: 10,
+            window_height: 100_000,
+            master_volume: f32::NAN,
+            music_volume: 3.0,
+            sfx_volume: -1.0,
+            round_length: 0,
+            locale: "  ".to_string(),
+            ..Settings::default()
+        };
+        settings.validate();
+        assert_eq!((settings.window_width, settings.window_height), (320, 4320));
+        assert_eq!((settings.master_volume, settings.music_volume, settings.sfx_volume), (1.0, 1.0, 0.0));
+        assert_eq!(settings.round_length, 5);
+        assert_eq!(settings.locale, locale::DEFAULT_LOCALE);
+    }
+
+    #[test]
+    fn validate_rejects_duplicate_keys() {
+        let mut settings = Settings::default();
+        settings.keys.cast = Key::C;
+        settings.keys.screenshot = Key::F1;
+        settings.validate();
+        // Changed but all different is fine
+        assert_eq!(settings.keys.cast, Key::C);
+        settings.keys.left = Key::C;
+        settings.validate();
+        assert_eq!(settings.keys, KeyBindings::default());
+    }
+
+    #[test]
+    fn rebinding_takes_the_next_key() {
+        let mut settings = Settings::default();
+        let mut input = Input::default();
+        let mut menu = menu_on("settings.keys.cast");
+        assert_eq!(press(&mut menu, &mut settings, &mut input, Key::Return), SettingsChange::Value);
+        assert!(menu.rebinding);
+        // Nothing pressed, still waiting
+        assert_eq!(menu.update(&input, &mut settings), SettingsChange::None);
+        press(&mut menu, &mut settings, &mut input, Key::C);
+        assert!(!menu.rebinding);
+        assert_eq!(settings.keys.cast, Key::C);
+    }
+
+    #[test]
+    fn escape_cancels_rebinding() {
+        let mut settings = Settings::default();
+        let mut input = Input::default();
+        let mut menu = menu_on("settings.keys.cast");
+        press(&mut menu, &mut settings, &mut input, Key::Return);
+        // Leaves the binding alone, and doesn't close the menu either
+        assert_eq!(press(&mut menu, &mut settings, &mut input, Key::Escape), SettingsChange::Value);
+        assert!(!menu.rebinding);
+        assert_eq!(settings.keys, KeyBindings::default());
+    }
+
+    #[test]
+    fn rebinding_to_a_taken_key_swaps_them() {
+        let mut settings = Settings::default();
+        let mut input = Input::default();
+        let mut menu = menu_on("settings.keys.cast");
+        press(&mut menu, &mut settings, &mut input, Key::Return);
+        // Left already walks left: it gets Space instead
+        press(&mut menu, &mut settings, &mut input, Key::Left);
+        assert_eq!(settings.keys.cast, Key::Left);
+        assert_eq!(settings.keys.left, Key::Space);
+        assert!(!settings.keys.has_duplicates());
+        // Picking the key it already has changes nothing
+        press(&mut menu, &mut settings, &mut input, Key::Return);
+        press(&mut menu, &mut settings, &mut input, Key::Left);
+        assert_eq!(settings.keys.cast, Key::Left);
+        assert_eq!(settings.keys.left, Key::Space);
+    }
+
+    #[test]
+    fn water_is_read_from_the_settings_file() {
+        let text = "[water]\nwaterline = 500.0\nwave_amplitude = 6.0\ndeep_tint = [0.1, 0.2, 0.3, 0.4]\n";
+        let settings: Settings = toml::from_str(text).unwrap();
+        assert_eq!(settings.water.waterline, 500.0);
+        assert_eq!(settings.water.wave_amplitude, 6.0);
+        assert_eq!(settings.water.deep_tint, [0.1, 0.2, 0.3, 0.4]);
+        // Whatever's left out keeps its default
+        assert_eq!(settings.water.wave_length, WaterConfig::default().wave_length);
+        // And it all survives a save
+        let saved: Settings = toml::from_str(&toml::to_string_pretty(&settings).unwrap()).unwrap();
+        assert_eq!(saved, settings);
+    }
+
+    #[test]
+    fn validate_keeps_the_water_sensible() {
+        let mut settings = Settings::default();
+        settings.water.waterline = 5000.0;
+        settings.water.wave_amplitude = f32::NAN;
+        settings.water.wave_length = 0.0;
+        settings.water.surface_color = [2.0, -1.0, 0.5, 1.0];
+        settings.validate();
+        assert_eq!(settings.water.waterline, crate::canvas::LOGICAL_HEIGHT as f32);
+        assert_eq!(settings.water.wave_amplitude, WaterConfig::default().wave_amplitude);
+        assert!(settings.water.wave_length > 0.0);
+        assert_eq!(settings.water.surface_color, [1.0, 0.0, 0.5, 1.0]);
+    }
+}
@@ -31,7 +31,7 @@ struct VertexOutput_bg {
 
 // vs_main now produces an instance of that struct...
 @vertex
-fn vs_main_bg(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+fn vs_main_bg(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput_bg {
     // We'll just look up the vertex data in those constant arrays
     return VertexOutput_bg(
         VERTICES_bg[in_vertex_index],
@@ -60,7 +60,15 @@ struct Camera {
 // GPUSprite, from before
 struct GPUSprite {
     to_rect:vec4<f32>,
-    from_rect:vec4<f32>
+    from_rect:vec4<f32>,
+    // Multiplied into the texture color; alpha is the sprite's opacity
+    tint:vec4<f32>,
+    // Draw order; the sprites arrive already sorted so the shader doesn't need it
+    z:f32,
+    // Counterclockwise, in radians
+    rotation:f32,
+    // What to rotate around, as a fraction of the sprite's size
+    pivot:vec2<f32>
 }
 
 // One binding for the camera...
@@ -74,6 +82,8 @@ var<storage, read> sprites: array<GPUSprite>;
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    // The sprite's tint, passed along unchanged to the fragment shader
+    @location(1) tint: vec4<f32>,
 }
 
 @vertex
@@ -82,7 +92,7 @@ fn vs_main(@builtin(vertex_index) in_vertex_index: u32,
            @builtin(instance_index) sprite_index:u32) -> VertexOutput {
     // The corner and size of the sprite in world space.
     // Which sprite? sprites[sprite_index]
-    let corner:vec4<f32> = vec4(sprites[sprite_index].to_rect.xy,0.,1.);
+    let corner:vec2<f32> = sprites[sprite_index].to_rect.xy;
     let size:vec2<f32> = sprites[sprite_index].to_rect.zw;
     // The corner and size of the texture area in UVs
     let tex_corner:vec2<f32> = sprites[sprite_index].from_rect.xy;
@@ -91,11 +101,18 @@ fn vs_main(@builtin(vertex_index) in_vertex_index: u32,
     let which_vtx:vec2<f32> = VERTICES[in_vertex_index];
     // Which corner of the UV square we need to draw (UV coordinates are flipped in Y)
     let which_uv: vec2<f32> = vec2(VERTICES[in_vertex_index].x, 1.0 - VERTICES[in_vertex_index].y);
+    // Rotate this corner around the pivot. GPUSprite::world_corner does the same thing on the CPU.
+    let pivot:vec2<f32> = size * sprites[sprite_index].pivot;
+    let local:vec2<f32> = which_vtx*size - pivot;
+    let c:f32 = cos(sprites[sprite_index].rotation);
+    let s:f32 = sin(sprites[sprite_index].rotation);
+    let world:vec2<f32> = corner + pivot + vec2(local.x*c - local.y*s, local.x*s + local.y*c);
     return VertexOutput(
-        // Offset corner by size * which_vtx to get the right corner, then do camera stuff. Dividing screen size by 2 and the last subtraction are to deal with the NDC coordinate space, which goes from -1 to 1 in WGPU.
-        ((corner + vec4(which_vtx*size,0.,0.) - vec4(camera.screen_pos,0.,0.)) / vec4(camera.screen_size/2., 1.0, 1.0)) - vec4(1.0, 1.0, 0.0, 0.0),
+        // Then do camera stuff. Dividing screen size by 2 and the last subtraction are to deal with the NDC coordinate space, which goes from -1 to 1 in WGPU.
+        (vec4(world - camera.screen_pos, 0., 1.) / vec4(camera.screen_size/2., 1.0, 1.0)) - vec4(1.0, 1.0, 0.0, 0.0),
         // Offset texture corner by tex_size * which_uv to get the right corner
-        tex_corner + which_uv*tex_size
+        tex_corner + which_uv*tex_size,
+        sprites[sprite_index].tint
     );
 }
 
@@ -112,9 +129,25 @@ var s_diffuse: sampler;
 @fragment
 fn fs_main(in:VertexOutput) -> @location(0) vec4<f32> {
     // And we use the tex coords from the vertex output to sample from the texture.
-    let color:vec4<f32> = textureSample(t_diffuse, s_diffuse, in.tex_coords);
-    // This is new: if the alpha value of the color is very low, don't draw any fragment here.
-    // This is like "cutout" transparency.
+    let color:vec4<f32> = textureSample(t_diffuse, s_diffuse, in.tex_coords) * in.tint;
+    // The pipeline blends with premultiplied alpha, so multiply the color by its alpha on the way out.
+    // That way half see-through pixels fade smoothly instead of getting a dark fringe.
+    return vec4(color.rgb * color.a, color.a);
+}
+
+// For textures that were premultiplied when they were loaded (TextureOptions::premultiply_alpha):
+// only the tint still needs multiplying by its own alpha.
+@fragment
+fn fs_main_premultiplied(in:VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(t_diffuse, s_diffuse, in.tex_coords) * vec4(in.tint.rgb * in.tint.a, in.tint.a);
+}
+
+// The old way, for sprite groups that don't want blending:
+// if the alpha value of the color is very low, don't draw any fragment here.
+// This is like "cutout" transparency.
+@fragment
+fn fs_main_cutout(in:VertexOutput) -> @location(0) vec4<f32> {
+    let color:vec4<f32> = textureSample(t_diffuse, s_diffuse, in.tex_coords) * in.tint;
     if color.w < 0.2 { discard; }
     return color;
 }
@@ -130,7 +163,7 @@ var s_diffuse_bg: sampler;
 
 // Our fragment shader takes an interpolated `VertexOutput` as input now
 @fragment
-fn fs_main_bg(in:VertexOutput) -> @location(0) vec4<f32> {
+fn fs_main_bg(in:VertexOutput_bg) -> @location(0) vec4<f32> {
     // And we use the tex coords from the vertex output to sample from the texture.
     return textureSample(t_diffuse_bg, s_diffuse_bg, in.tex_coords);
 }
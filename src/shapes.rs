@@ -0,0 +1,372 @@
+use std::borrow::Cow;
+use std::ops::{Range, RangeInclusive};
+
+use crate::camera::GPUCamera;
+use crate::gpus::{Scene, WGPU};
+use crate::sprite_game::Layer;
+
+// Which coordinates a shape is given in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Space {
+    // World pixels with y going up, seen through the game camera like the sprites
+    World,
+    // Canvas pixels from the top left, like the UI and the mouse
+    Canvas,
+}
+
+// Where a shape goes: which coordinates it's in, and which layer it's drawn with so it can go between
+// sprites (the fishing line goes behind the foreground water, the debug overlay over everything)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Brush {
+    pub space: Space,
+    pub layer: Layer,
+}
+
+impl Brush {
+    pub const fn world(layer: Layer) -> Self {
+        Self {
+            space: Space::World,
+            layer,
+        }
+    }
+
+    pub const fn canvas(layer: Layer) -> Self {
+        Self {
+            space: Space::Canvas,
+            layer,
+        }
+    }
+}
+
+// What the shader sees, see VertexInput in shapes.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct ShapeVertex {
+    position: [f32; 2],
+    color: [f32; 4],
+}
+
+// One corner of a triangle, before `prepare` turns it into clip space
+#[derive(Clone, Copy, Debug)]
+struct Corner {
+    brush: Brush,
+    pos: [f32; 2],
+    color: [f32; 4],
+}
+
+// How sharp a polyline corner can get before its miter is cut off, as a multiple of half the thickness
+const MITER_LIMIT: f32 = 4.0;
+
+// Flat colored lines, rectangles, circles and polylines, drawn fresh every frame. Queue shapes up from
+// anywhere during the frame, then `prepare` turns them all into triangles in one vertex buffer and
+// `render` draws whichever layers are asked for, in as few calls as there are layers.
+pub struct ShapeRenderer {
+    corners: Vec<Corner>,
+    vertex_buffer: wgpu::Buffer,
+    // In vertices
+    capacity: usize,
+    // Where each layer's triangles are in the vertex buffer, back to front
+    layer_ranges: Vec<(Layer, Range<u32>)>,
+    pipeline: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+}
+
+#[allow(dead_code)]
+impl ShapeRenderer {
+    pub(crate) fn new(gpu: &WGPU) -> Self {
+        let pipeline_layout = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("shapes"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let pipeline = Self::create_pipeline(gpu, &pipeline_layout, include_str!("shapes.wgsl"));
+        let capacity = 4096;
+        Self {
+            corners: Vec::new(),
+            vertex_buffer: Self::create_vertex_buffer(gpu, capacity),
+            capacity,
+            layer_ranges: Vec::new(),
+            pipeline,
+            pipeline_layout,
+        }
+    }
+
+    fn create_vertex_buffer(gpu: &WGPU, capacity: usize) -> wgpu::Buffer {
+        gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("shapes"),
+            size: (capacity * std::mem::size_of::<ShapeVertex>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_pipeline(gpu: &WGPU, pipeline_layout: &wgpu::PipelineLayout, source: &str) -> wgpu::RenderPipeline {
+        let shader = gpu.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shapes"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
+        });
+        gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("shapes"),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<ShapeVertex>() as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: gpu.config.format,
+                    // Colors are given straight, not premultiplied
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            // Shapes can come out wound either way, so nothing gets culled
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    // Like SpriteRenderer::reload_shader: keeps the old pipeline if the new source doesn't compile
+    pub(crate) fn reload_shader(&mut self, gpu: &WGPU, source: &str) -> Result<(), String> {
+        self.pipeline = gpu.validated(|| Self::create_pipeline(gpu, &self.pipeline_layout, source))?;
+        Ok(())
+    }
+
+    fn triangles(&mut self, brush: Brush, corners: impl IntoIterator<Item = [f32; 2]>, color: [f32; 4]) {
+        self.corners.extend(corners.into_iter().map(|pos| Corner { brush, pos, color }));
+    }
+
+    // A straight line `thickness` wide, centered on the points, with square ends
+    pub fn line(&mut self, brush: Brush, from: [f32; 2], to: [f32; 2], thickness: f32, color: [f32; 4]) {
+        self.polyline(brush, &[from, to], thickness, color);
+    }
+
+    // Lines through each of `points` in turn, with mitered corners so they join up without gaps or
+    // overlaps (which would show as darker spots on see-through lines)
+    pub fn polyline(&mut self, brush: Brush, points: &[[f32; 2]], thickness: f32, color: [f32; 4]) {
+        self.triangles(brush, polyline_triangles(points, thickness, false), color);
+    }
+
+    // Like polyline, but the last point joins back up with the first
+    pub fn polygon_outline(&mut self, brush: Brush, points: &[[f32; 2]], thickness: f32, color: [f32; 4]) {
+        self.triangles(brush, polyline_triangles(points, thickness, true), color);
+    }
+
+    // `rect` is [x, y, width, height] from its corner nearest the origin, like a sprite's screen region
+    pub fn rect(&mut self, brush: Brush, rect: [f32; 4], color: [f32; 4]) {
+        let [a, b, c, d] = rect_corners(rect);
+        self.triangles(brush, [a, b, c, a, c, d], color);
+    }
+
+    // Just the edge of `rect`, drawn inside it so outlines line up with filled rects of the same size
+    pub fn rect_outline(&mut self, brush: Brush, rect: [f32; 4], thickness: f32, color: [f32; 4]) {
+        let [x, y, w, h] = rect;
+        let half = (thickness / 2.0).min(w.abs() / 2.0).min(h.abs() / 2.0);
+        let inset = [x + half * w.signum(), y + half * h.signum(), w - 2.0 * half * w.signum(), h - 2.0 * half * h.signum()];
+        self.polygon_outline(brush, &rect_corners(inset), thickness, color);
+    }
+
+    pub fn circle(&mut self, brush: Brush, center: [f32; 2], radius: f32, color: [f32; 4]) {
+        let points = circle_points(center, radius);
+        let fan: Vec<[f32; 2]> = (0..points.len())
+            .flat_map(|i| [center, points[i], points[(i + 1) % points.len()]])
+            .collect();
+        self.triangles(brush, fan, color);
+    }
+
+    pub fn circle_outline(&mut self, brush: Brush, center: [f32; 2], radius: f32, thickness: f32, color: [f32; 4]) {
+        self.polygon_outline(brush, &circle_points(center, radius), thickness, color);
+    }
+
+    // Send this frame's shapes to the GPU and start queueing up the next frame's
+    pub(crate) fn prepare(&mut self, gpu: &WGPU, camera: &GPUCamera, canvas_size: [f32; 2]) {
+        // Stable, so shapes in the same layer keep the order they were queued in
+        self.corners.sort_by_key(|corner| corner.brush.layer);
+        self.layer_ranges.clear();
+        for (index, corner) in self.corners.iter().enumerate() {
+            let index = index as u32;
+            match self.layer_ranges.last_mut() {
+                Some((layer, range)) if *layer == corner.brush.layer => range.end = index + 1,
+                _ => self.layer_ranges.push((corner.brush.layer, index..index + 1)),
+            }
+        }
+        let vertices: Vec<ShapeVertex> = self
+            .corners
+            .drain(..)
+            .map(|corner| ShapeVertex {
+                position: to_clip(corner.brush.space, corner.pos, camera, canvas_size),
+                color: corner.color,
+            })
+            .collect();
+        if vertices.len() > self.capacity {
+            self.capacity = vertices.len().next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(gpu, self.capacity);
+        }
+        gpu.queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+
+    // Draw the prepared shapes in `layers`, to go between SpriteRenderer::render calls for the layers
+    // around them
+    pub(crate) fn render<'s, 'pass>(&'s self, rpass: &mut wgpu::RenderPass<'pass>, layers: RangeInclusive<Layer>)
+    where
+        's: 'pass,
+    {
+        // The layers are sorted, so everything in `layers` is one run of vertices
+        let mut wanted = self.layer_ranges.iter().filter(|(layer, _)| layers.contains(layer));
+        let Some((_, first)) = wanted.next() else { return };
+        let end = wanted.last().map_or(first.end, |(_, range)| range.end);
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.draw(first.start..end, 0..1);
+    }
+}
+
+// Offscreen, a shape renderer on its own draws every layer
+impl Scene for ShapeRenderer {
+    fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        self.render(rpass, Layer::Sky..=Layer::Ui);
+    }
+}
+
+fn to_clip(space: Space, pos: [f32; 2], camera: &GPUCamera, canvas_size: [f32; 2]) -> [f32; 2] {
+    let [x, y] = match space {
+        Space::World => camera.world_to_canvas(pos, canvas_size),
+        Space::Canvas => pos,
+    };
+    [x / canvas_size[0] * 2.0 - 1.0, 1.0 - y / canvas_size[1] * 2.0]
+}
+
+fn rect_corners([x, y, w, h]: [f32; 4]) -> [[f32; 2]; 4] {
+    [[x, y], [x + w, y], [x + w, y + h], [x, y + h]]
+}
+
+// Enough points around the edge that it looks round at that size
+fn circle_points(center: [f32; 2], radius: f32) -> Vec<[f32; 2]> {
+    let count = (radius.abs().sqrt() * 4.0).clamp(12.0, 64.0) as usize;
+    (0..count)
+        .map(|i| {
+            let angle = i as f32 / count as f32 * std::f32::consts::TAU;
+            [center[0] + radius * angle.cos(), center[1] + radius * angle.sin()]
+        })
+        .collect()
+}
+
+fn sub(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+// A quarter turn counterclockwise, scaled to length 1 (or zero for a zero-length segment)
+fn unit_normal(from: [f32; 2], to: [f32; 2]) -> [f32; 2] {
+    let [dx, dy] = sub(to, from);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length == 0.0 {
+        [0.0, 0.0]
+    } else {
+        [-dy / length, dx / length]
+    }
+}
+
+// Triangles for a line `thickness` wide through `points`. Each point gets a pair of vertices either side
+// of it, along the average of the normals of the segments on each side (stretched so the edges stay
+// parallel to the segments); very sharp corners are capped at MITER_LIMIT so they don't spike out.
+fn polyline_triangles(points: &[[f32; 2]], thickness: f32, closed: bool) -> Vec<[f32; 2]> {
+    // Repeated points would make zero-length segments with no direction
+    let mut clean: Vec<[f32; 2]> = Vec::with_capacity(points.len());
+    for &point in points {
+        if clean.last() != Some(&point) {
+            clean.push(point);
+        }
+    }
+    if closed && clean.len() > 2 && clean.first() == clean.last() {
+        clean.pop();
+    }
+    let count = clean.len();
+    if count < 2 {
+        return Vec::new();
+    }
+    let half = thickness / 2.0;
+    let segment_normal = |i: usize| unit_normal(clean[i % count], clean[(i + 1) % count]);
+    let edges: Vec<([f32; 2], [f32; 2])> = (0..count)
+        .map(|i| {
+            let before = if i > 0 || closed { Some(segment_normal((i + count - 1) % count)) } else { None };
+            let after = if i < count - 1 || closed { Some(segment_normal(i)) } else { None };
+            let offset = match (before, after) {
+                (Some(a), Some(b)) => {
+                    let sum = [a[0] + b[0], a[1] + b[1]];
+                    let length = (sum[0] * sum[0] + sum[1] * sum[1]).sqrt();
+                    if length < 1e-6 {
+                        // Doubling straight back: there's no sensible miter, just go square
+                        [a[0] * half, a[1] * half]
+                    } else {
+                        let miter = [sum[0] / length, sum[1] / length];
+                        // How much longer the miter is than half the thickness
+                        let stretch = (1.0 / (miter[0] * b[0] + miter[1] * b[1]).max(1e-6)).min(MITER_LIMIT);
+                        [miter[0] * half * stretch, miter[1] * half * stretch]
+                    }
+                }
+                (Some(n), None) | (None, Some(n)) => [n[0] * half, n[1] * half],
+                (None, None) => [0.0, 0.0],
+            };
+            let p = clean[i];
+            ([p[0] + offset[0], p[1] + offset[1]], [p[0] - offset[0], p[1] - offset[1]])
+        })
+        .collect();
+    let segments = if closed { count } else { count - 1 };
+    (0..segments)
+        .flat_map(|i| {
+            let (left_a, right_a) = edges[i];
+            let (left_b, right_b) = edges[(i + 1) % count];
+            [left_a, right_a, right_b, left_a, right_b, left_b]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f32; 2], b: [f32; 2]) {
+        assert!((a[0] - b[0]).abs() < 1e-4 && (a[1] - b[1]).abs() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn lines_are_quads_around_the_points() {
+        let triangles = polyline_triangles(&[[0.0, 0.0], [10.0, 0.0]], 4.0, false);
+        assert_eq!(triangles.len(), 6);
+        assert_close(triangles[0], [0.0, 2.0]);
+        assert_close(triangles[1], [0.0, -2.0]);
+        assert_close(triangles[2], [10.0, -2.0]);
+        assert_close(triangles[5], [10.0, 2.0]);
+    }
+
+    #[test]
+    fn corners_are_mitered() {
+        // A right angle: the outside corner sits out at (half, half) from the point, not just half
+        let triangles = polyline_triangles(&[[0.0, 0.0], [10.0, 0.0], [10.0, 10.0]], 2.0, false);
+        assert_eq!(triangles.len(), 12);
+        assert_close(triangles[5], [9.0, 1.0]);
+        assert_close(triangles[2], [11.0, -1.0]);
+        // Repeated points are skipped instead of making a zero-length segment
+        assert_eq!(polyline_triangles(&[[0.0, 0.0], [0.0, 0.0], [5.0, 0.0]], 2.0, false).len(), 6);
+        assert!(polyline_triangles(&[[3.0, 3.0]], 2.0, false).is_empty());
+    }
+
+    #[test]
+    fn closed_outlines_join_up() {
+        let square = rect_corners([0.0, 0.0, 10.0, 10.0]);
+        let triangles = polyline_triangles(&square, 2.0, true);
+        // Four sides, and the first corner is mitered like the others
+        assert_eq!(triangles.len(), 24);
+        assert_close(triangles[0], [1.0, 1.0]);
+        assert_close(triangles[1], [-1.0, -1.0]);
+    }
+}
@@ -0,0 +1,22 @@
+// Flat colored triangles for lines, rectangles and circles. Positions are worked out on the CPU, so
+// they arrive already in clip space.
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    return VertexOutput(vec4(in.position, 0.0, 1.0), in.color);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
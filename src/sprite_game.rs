@@ -1,102 +1,188 @@
-use std::{borrow::Cow, ops::Range};
+use std::{borrow::Cow, cell::{Ref, RefCell}, ops::{Range, RangeInclusive}, rc::Rc};
 
-use crate::gpus::WGPU;
+use crate::assets::{AssetManager, TextureHandle};
+use crate::camera::GPUCamera;
+use crate::gpus::{Scene, WGPU};
 // use crate::{USE_STORAGE, WGPU};
 use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+
+// Named draw layers, back to front. A sprite's `z` picks its layer (the whole number part)
+// and its order within that layer (the fraction), so nobody has to shuffle the sprite vec around.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Layer {
+    Sky = 0,
+    FarWater = 1,
+    Fish = 2,
+    ForegroundWater = 3,
+    Pier = 4,
+    Ui = 5,
+}
+
+impl Layer {
+    pub fn z(self) -> f32 {
+        self as u8 as f32
+    }
+
+    // A z value inside this layer, nudged towards the front by `offset` (which should be in 0.0..1.0)
+    pub fn z_offset(self, offset: f32) -> f32 {
+        self.z() + offset.clamp(0.0, 0.999)
+    }
+
+    pub fn from_z(z: f32) -> Layer {
+        match z.floor() as i32 {
+            i32::MIN..=0 => Layer::Sky,
+            1 => Layer::FarWater,
+            2 => Layer::Fish,
+            3 => Layer::ForegroundWater,
+            4 => Layer::Pier,
+            _ => Layer::Ui,
+        }
+    }
+}
 
 #[repr(C)]
-#[derive(Clone, Copy, Zeroable, Pod)]
+#[derive(Clone, Copy, Debug, Zeroable, Pod)]
 pub struct GPUSprite {
     pub screen_region: [f32; 4],
+    // Textures with a bunch of sprites are often called "sprite sheets"
     pub sheet_region: [f32; 4],
+    // RGBA multiplied into the texture color. Alpha is the sprite's opacity, and RGB above 1.0
+    // brightens the sprite, which is handy for hit flashes.
+    pub tint: [f32; 4],
+    // Draw order, see Layer. Bigger is closer to the front.
+    pub z: f32,
+    // Counterclockwise rotation in radians, around the pivot
+    pub rotation: f32,
+    // The point the sprite rotates around, as a fraction of its size: [0,0] is the bottom left corner,
+    // [0.5,0.5] the middle. These two also fill out the struct to a multiple of 16 bytes, like the shader expects.
+    pub pivot: [f32; 2],
 }
 
-#[repr(C)]
-#[derive(Clone, Copy, Zeroable, Pod)]
-pub struct GPUCamera {
-    pub screen_pos: [f32; 2],
-    pub screen_size: [f32; 2],
+#[allow(dead_code)]
+impl GPUSprite {
+    pub fn new(screen_region: [f32; 4], sheet_region: [f32; 4], z: f32) -> Self {
+        Self {
+            screen_region,
+            sheet_region,
+            tint: [1.0, 1.0, 1.0, 1.0],
+            z,
+            rotation: 0.0,
+            pivot: [0.5, 0.5],
+        }
+    }
+
+    pub fn with_tint(mut self, tint: [f32; 4]) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32, pivot: [f32; 2]) -> Self {
+        self.rotation = rotation;
+        self.pivot = pivot;
+        self
+    }
+
+    // Scale the sprite's size while keeping its pivot in the same place in the world.
+    // A negative scale mirrors the sprite.
+    pub fn scale_about_pivot(&mut self, sx: f32, sy: f32) {
+        let [x, y, w, h] = self.screen_region;
+        let pivot_x = x + w * self.pivot[0];
+        let pivot_y = y + h * self.pivot[1];
+        self.screen_region = [pivot_x - w * sx * self.pivot[0], pivot_y - h * sy * self.pivot[1], w * sx, h * sy];
+    }
+
+    // Where corner `which` (in the same 0..1 units as VERTICES in shader.wgsl) of this sprite ends up
+    // in world space. This is the same math vs_main does, so it's handy for tests and for picking.
+    pub fn world_corner(&self, which: [f32; 2]) -> [f32; 2] {
+        let [x, y, w, h] = self.screen_region;
+        let pivot = [w * self.pivot[0], h * self.pivot[1]];
+        let local = [which[0] * w - pivot[0], which[1] * h - pivot[1]];
+        let (sin, cos) = self.rotation.sin_cos();
+        [
+            x + pivot[0] + local[0] * cos - local[1] * sin,
+            y + pivot[1] + local[0] * sin + local[1] * cos,
+        ]
+    }
+
+    // All four corners: bottom left, bottom right, top left, top right
+    pub fn world_corners(&self) -> [[f32; 2]; 4] {
+        [
+            self.world_corner([0.0, 0.0]),
+            self.world_corner([1.0, 0.0]),
+            self.world_corner([0.0, 1.0]),
+            self.world_corner([1.0, 1.0]),
+        ]
+    }
+}
+
+// Shrink a sheet region (in UVs) by half a texel on every side. A frame's edge then samples the middle of
+// its own outermost texels instead of the boundary with the next frame, where rounding at fractional
+// screen positions could pick the neighbour. Works for flipped regions (negative width or height) too.
+pub fn inset_half_texel(region: [f32; 4], (tex_width, tex_height): (u32, u32)) -> [f32; 4] {
+    let half_u = 0.5 / tex_width as f32;
+    let half_v = 0.5 / tex_height as f32;
+    let [x, y, w, h] = region;
+    // Don't turn tiny (or empty) regions inside out
+    let inset_u = half_u.min(w.abs() / 2.0) * w.signum();
+    let inset_v = half_v.min(h.abs() / 2.0) * h.signum();
+    [x + inset_u, y + inset_v, w - 2.0 * inset_u, h - 2.0 * inset_v]
+}
+
+// How a sprite group's pixels combine with what's already been drawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum BlendMode {
+    // Premultiplied alpha blending: smooth edges, fades and see-through tints.
+    // Works with both straight and premultiplied textures; the renderer picks the right shader.
+    Alpha,
+    // Pixels are either drawn fully or not at all (alpha below 0.2 is thrown away)
+    Cutout,
 }
 
 #[allow(dead_code)]
 struct SpriteGroup {
-    tex: wgpu::Texture,
+    // Keeps the texture alive for as long as the group is around
+    tex: TextureHandle,
+    // Which version of the texture tex_bind_group was made for, see TextureHandle::generation
+    tex_generation: u32,
     sprite_buffer: wgpu::Buffer,
     sprites: Vec<GPUSprite>,
+    // The same sprites sorted back to front; this is what actually goes to the GPU
+    sorted: Vec<GPUSprite>,
     camera: GPUCamera,
     camera_buffer: wgpu::Buffer,
-    tex_bind_group: wgpu::BindGroup,
+    tex_bind_group: Rc<wgpu::BindGroup>,
     sprite_bind_group: wgpu::BindGroup,
+    visible: bool,
+    blend_mode: BlendMode,
+    // Pull sheet regions in by half a texel so neighbouring frames don't bleed in, see inset_half_texel
+    half_texel_inset: bool,
+}
+
+// One pipeline per way of drawing a group, all from the same shader
+struct SpritePipelines {
+    alpha: wgpu::RenderPipeline,
+    // Alpha blending for textures that are already premultiplied
+    alpha_premultiplied: wgpu::RenderPipeline,
+    cutout: wgpu::RenderPipeline,
 }
 
 pub struct SpriteRenderer {
-    pipeline: wgpu::RenderPipeline,
+    pipelines: SpritePipelines,
+    // Kept so the pipelines can be rebuilt when the shader changes
+    pipeline_layout: wgpu::PipelineLayout,
     sprite_bind_group_layout: wgpu::BindGroupLayout,
-    texture_bind_group_layout: wgpu::BindGroupLayout,
     groups: Vec<SpriteGroup>,
+    // Scratch space for draw_runs, kept between frames so working out the draw calls doesn't allocate
+    draw_entries: RefCell<Vec<(f32, usize, u32)>>,
+    draw_calls: RefCell<Vec<(usize, Range<u32>)>>,
 }
 
+#[allow(dead_code)]
 impl SpriteRenderer {
-    pub(crate) fn new(gpu: &WGPU) -> Self {
-        let shader = gpu
-            .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: None,
-                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
-            });
-
-        let texture_bind_group_layout =
-            gpu.device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    label: None,
-                    // It needs the first entry for the texture and the second for the sampler.
-                    // This is like defining a type signature.
-                    entries: &[
-                        // The texture binding
-                        wgpu::BindGroupLayoutEntry {
-                            // This matches the binding in the shader
-                            binding: 0,
-                            // Only available in the fragment shader
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            // It's a texture binding
-                            ty: wgpu::BindingType::Texture {
-                                // We can use it with float samplers
-                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                                // It's being used as a 2D texture
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                                // This is not a multisampled texture
-                                multisampled: false,
-                            },
-                            count: None,
-                        },
-                        // The sampler binding
-                        wgpu::BindGroupLayoutEntry {
-                            // This matches the binding in the shader
-                            binding: 1,
-                            // Only available in the fragment shader
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            // It's a sampler
-                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                            // No count
-                            count: None,
-                        },
-                    ],
-                });
-        // The camera binding
-        let camera_layout_entry = wgpu::BindGroupLayoutEntry {
-            // This matches the binding in the shader
-            binding: 0,
-            // Available in vertex shader
-            visibility: wgpu::ShaderStages::VERTEX,
-            // It's a buffer
-            ty: wgpu::BindingType::Buffer {
-                ty: wgpu::BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: None,
-            },
-            // No count, not a buffer array binding
-            count: None,
-        };
+    // The texture bind group layout comes from the asset manager, which makes the bind groups too
+    pub(crate) fn new(gpu: &WGPU, assets: &AssetManager) -> Self {
         let sprite_bind_group_layout =
             gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: None,
@@ -168,28 +254,53 @@ impl SpriteRenderer {
             .device
             .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: None,
-                bind_group_layouts: &[&sprite_bind_group_layout, &texture_bind_group_layout],
+                bind_group_layouts: &[&sprite_bind_group_layout, assets.texture_bind_group_layout()],
                 push_constant_ranges: &[],
             });
-        
-        let pipeline = gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: None,
-        layout: Some(&pipeline_layout),
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: "vs_main",
-            buffers: &[],
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: "fs_main",
-            targets: &[Some(gpu.config.format.into())],
-        }),
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState::default(),
-        multiview: None,});
 
+        let pipelines = Self::create_pipelines(gpu, &pipeline_layout, include_str!("shader.wgsl"));
+
+        Self {
+            pipelines,
+            pipeline_layout,
+            sprite_bind_group_layout,
+            groups: Vec::default(),
+            draw_entries: RefCell::default(),
+            draw_calls: RefCell::default(),
+        }
+    }
+
+    fn create_pipelines(gpu: &WGPU, pipeline_layout: &wgpu::PipelineLayout, source: &str) -> SpritePipelines {
+        let shader = gpu
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
+            });
+        let create = |entry_point: &str, blend: Option<wgpu::BlendState>| {
+            gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: gpu.config.format,
+                    blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // No depth buffer: sprites are sorted back to front instead, which also works for see-through ones
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,})
+        };
         // let pipeline = gpu
         //     .device
         //     .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
@@ -233,44 +344,44 @@ impl SpriteRenderer {
         //         multisample: wgpu::MultisampleState::default(),
         //         multiview: None,
         //     });
+        SpritePipelines {
+            // Both of these output premultiplied colors, so blend them that way
+            alpha: create("fs_main", Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING)),
+            alpha_premultiplied: create("fs_main_premultiplied", Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING)),
+            cutout: create("fs_main_cutout", None),
+        }
+    }
 
-        Self {
-            pipeline,
-            sprite_bind_group_layout,
-            texture_bind_group_layout,
-            groups: Vec::default(),
+    // Swap in a new version of shader.wgsl (for hot reloading). If it doesn't compile, the old
+    // pipelines stay and the error comes back instead.
+    pub(crate) fn reload_shader(&mut self, gpu: &WGPU, source: &str) -> Result<(), String> {
+        self.pipelines = gpu.validated(|| Self::create_pipelines(gpu, &self.pipeline_layout, source))?;
+        Ok(())
+    }
+
+    // Pick up textures that were hot reloaded since the groups' bind groups were made
+    pub(crate) fn refresh_textures(&mut self, gpu: &WGPU, assets: &AssetManager) {
+        for group in self.groups.iter_mut() {
+            if group.tex_generation != group.tex.generation() {
+                group.tex_bind_group = assets.bind_group(gpu, &group.tex);
+                group.tex_generation = group.tex.generation();
+            }
         }
     }
     pub fn add_sprite_group(
         &mut self,
         gpu: &WGPU,
-        tex: wgpu::Texture,
+        assets: &AssetManager,
+        tex: &TextureHandle,
         sprites: Vec<GPUSprite>,
         camera: GPUCamera,
     ) -> usize {
-        let view_sprite = tex.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler_sprite = gpu
-            .device
-            .create_sampler(&wgpu::SamplerDescriptor::default());
-        
-        let tex_bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &self.texture_bind_group_layout,
-            entries: &[
-                // One for the texture, one for the sampler
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&view_sprite),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler_sprite),
-                },
-            ],
-        });
+        let tex_bind_group = assets.bind_group(gpu, tex);
+        // Room for at least one sprite: a storage buffer can't be bound with nothing in it, and an empty
+        // group just draws zero instances
         let sprite_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor{
             label: None,
-            size: bytemuck::cast_slice::<_,u8>(&sprites).len() as u64,
+            size: (sprites.len().max(1) * std::mem::size_of::<GPUSprite>()) as u64,
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false
         });
@@ -329,21 +440,26 @@ impl SpriteRenderer {
         //         }],
         //     })
         // };
-        gpu.queue
-            .write_buffer(&sprite_buffer, 0, bytemuck::cast_slice(&sprites));
         gpu.queue
             .write_buffer(&camera_buffer, 0, bytemuck::bytes_of(&camera));
 
         self.groups.push(SpriteGroup {
-            tex,
-            sprite_buffer: sprite_buffer,
+            tex: tex.clone(),
+            tex_generation: tex.generation(),
+            sprite_buffer,
+            sorted: Vec::with_capacity(sprites.len()),
             sprites,
             tex_bind_group,
             sprite_bind_group,
             camera,
             camera_buffer,
+            visible: true,
+            blend_mode: BlendMode::Alpha,
+            half_texel_inset: true,
         });
-        self.groups.len() - 1
+        let which = self.groups.len() - 1;
+        self.upload_sprites(gpu, which);
+        which
     }
 
     pub fn remove_sprite_group(&mut self, which: usize) {
@@ -403,12 +519,31 @@ impl SpriteRenderer {
         gpu.queue
             .write_buffer(&sg.camera_buffer, 0, bytemuck::bytes_of(&sg.camera));
     }
-    pub fn upload_sprites(&mut self, gpu: &WGPU, which: usize, range: Range<usize>) {
-        gpu.queue.write_buffer(
-            &self.groups[which].sprite_buffer,
-            range.start as u64,
-            bytemuck::cast_slice(&self.groups[which].sprites[range]),
-        );
+    pub fn set_visible(&mut self, which: usize, visible: bool) {
+        self.groups[which].visible = visible;
+    }
+    pub fn set_blend_mode(&mut self, which: usize, blend_mode: BlendMode) {
+        self.groups[which].blend_mode = blend_mode;
+    }
+    // On by default; turn it off for textures meant to be sampled right up to their edges, like tiling ones
+    pub fn set_half_texel_inset(&mut self, which: usize, inset: bool) {
+        self.groups[which].half_texel_inset = inset;
+    }
+    // Sort a group's sprites back to front and send them to the GPU.
+    // The sort is stable, so sprites with equal z keep the order they have in the vec.
+    pub fn upload_sprites(&mut self, gpu: &WGPU, which: usize) {
+        let sg = &mut self.groups[which];
+        sg.sorted.clear();
+        sg.sorted.extend_from_slice(&sg.sprites);
+        sg.sorted.sort_by(|a, b| a.z.total_cmp(&b.z));
+        if sg.half_texel_inset {
+            let tex_size = sg.tex.size();
+            for sprite in sg.sorted.iter_mut() {
+                sprite.sheet_region = inset_half_texel(sprite.sheet_region, tex_size);
+            }
+        }
+        gpu.queue
+            .write_buffer(&sg.sprite_buffer, 0, bytemuck::cast_slice(&sg.sorted));
     }
     pub fn get_sprites(&self, which: usize) -> &[GPUSprite] {
         &self.groups[which].sprites
@@ -416,6 +551,68 @@ impl SpriteRenderer {
     pub fn get_sprites_mut(&mut self, which: usize) -> &mut [GPUSprite] {
         &mut self.groups[which].sprites
     }
+
+    // Work out which draw calls to make for the sprites in `layers`: a list of (group, instance range),
+    // back to front across all the groups. Sprites from different groups can interleave, so one group may
+    // get drawn in several pieces; ties go to the group that was added first.
+    fn draw_runs(&self, layers: &RangeInclusive<Layer>) -> Ref<'_, [(usize, Range<u32>)]> {
+        let mut entries = self.draw_entries.borrow_mut();
+        entries.clear();
+        for (group_index, group) in self.groups.iter().enumerate() {
+            if !group.visible {
+                continue;
+            }
+            for (sprite_index, sprite) in group.sorted.iter().enumerate() {
+                if layers.contains(&Layer::from_z(sprite.z)) {
+                    entries.push((sprite.z, group_index, sprite_index as u32));
+                }
+            }
+        }
+        entries.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+        let mut runs = self.draw_calls.borrow_mut();
+        runs.clear();
+        for &(_, group_index, sprite_index) in entries.iter() {
+            match runs.last_mut() {
+                Some((last_group, range)) if *last_group == group_index && range.end == sprite_index => {
+                    range.end += 1;
+                }
+                _ => runs.push((group_index, sprite_index..sprite_index + 1)),
+            }
+        }
+        drop(runs);
+        Ref::map(self.draw_calls.borrow(), Vec::as_slice)
+    }
+
+    // Draw every visible sprite whose layer is in `layers`, e.g. everything under the HUD text with
+    // `Layer::Sky..=Layer::Pier` and then the rest with `Layer::Ui..=Layer::Ui`.
+    pub(crate) fn render<'s, 'pass>(&'s self, rpass: &mut wgpu::RenderPass<'pass>, layers: RangeInclusive<Layer>)
+    where
+        's: 'pass,
+    {
+        let mut current_mode = None;
+        for (group_index, instances) in self.draw_runs(&layers).iter().cloned() {
+            let group = &self.groups[group_index];
+            // Only switch pipelines when the blend mode actually changes
+            let mode = (group.blend_mode, group.tex.options().premultiply_alpha);
+            if current_mode != Some(mode) {
+                rpass.set_pipeline(match mode {
+                    (BlendMode::Alpha, false) => &self.pipelines.alpha,
+                    (BlendMode::Alpha, true) => &self.pipelines.alpha_premultiplied,
+                    // The alpha test works the same on premultiplied colors
+                    (BlendMode::Cutout, _) => &self.pipelines.cutout,
+                });
+                current_mode = Some(mode);
+            }
+            rpass.set_bind_group(0, &group.sprite_bind_group, &[]);
+            rpass.set_bind_group(1, &group.tex_bind_group, &[]);
+            // draw two triangles per sprite, and sprites-many sprites.
+            // this uses instanced drawing, but it would also be okay
+            // to draw 6 * sprites.len() vertices and use modular arithmetic
+            // to figure out which sprite we're drawing.
+            rpass.draw(0..6, instances);
+        }
+    }
     // pub(crate) fn render<'s, 'pass>(&'s self, rpass: &mut wgpu::RenderPass<'pass>)
     // where
     //     's: 'pass,
@@ -435,3 +632,80 @@ impl SpriteRenderer {
     //     }
     // }
 }
+
+// Offscreen, a sprite renderer on its own draws every layer
+impl Scene for SpriteRenderer {
+    fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        self.render(rpass, Layer::Sky..=Layer::Ui);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f32; 2], b: [f32; 2]) {
+        assert!((a[0] - b[0]).abs() < 1e-4 && (a[1] - b[1]).abs() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn unrotated_corners_match_screen_region() {
+        let sprite = GPUSprite::new([10.0, 20.0, 30.0, 40.0], [0.0; 4], 0.0);
+        let corners = sprite.world_corners();
+        assert_close(corners[0], [10.0, 20.0]);
+        assert_close(corners[1], [40.0, 20.0]);
+        assert_close(corners[2], [10.0, 60.0]);
+        assert_close(corners[3], [40.0, 60.0]);
+    }
+
+    #[test]
+    fn quarter_turn_around_center() {
+        let sprite = GPUSprite::new([0.0, 0.0, 20.0, 10.0], [0.0; 4], 0.0)
+            .with_rotation(std::f32::consts::FRAC_PI_2, [0.5, 0.5]);
+        // The center stays put at (10, 5), the 20x10 box turns into a 10x20 one
+        assert_close(sprite.world_corner([0.5, 0.5]), [10.0, 5.0]);
+        assert_close(sprite.world_corner([0.0, 0.0]), [15.0, -5.0]);
+        assert_close(sprite.world_corner([1.0, 1.0]), [5.0, 15.0]);
+    }
+
+    #[test]
+    fn rotation_keeps_pivot_fixed() {
+        let sprite = GPUSprite::new([100.0, 200.0, 50.0, 80.0], [0.0; 4], 0.0)
+            .with_rotation(1.234, [0.5, 1.0]);
+        assert_close(sprite.world_corner([0.5, 1.0]), [125.0, 280.0]);
+        // Half a turn around the bottom left corner flips the sprite down and to the left
+        let flipped = GPUSprite::new([0.0, 0.0, 4.0, 2.0], [0.0; 4], 0.0)
+            .with_rotation(std::f32::consts::PI, [0.0, 0.0]);
+        assert_close(flipped.world_corner([1.0, 1.0]), [-4.0, -2.0]);
+    }
+
+    #[test]
+    fn scale_about_pivot_keeps_pivot_fixed() {
+        let mut sprite = GPUSprite::new([10.0, 10.0, 20.0, 20.0], [0.0; 4], 0.0);
+        sprite.scale_about_pivot(2.0, 0.5);
+        assert_eq!(sprite.screen_region, [0.0, 15.0, 40.0, 10.0]);
+        sprite.pivot = [0.0, 0.0];
+        sprite.scale_about_pivot(-1.0, 1.0);
+        assert_eq!(sprite.screen_region, [0.0, 15.0, -40.0, 10.0]);
+    }
+
+    #[test]
+    fn half_texel_inset_handles_flipped_regions() {
+        // A 10x20 texel frame in a 100x100 sheet
+        let region = [0.1, 0.2, 0.1, 0.2];
+        let inset = inset_half_texel(region, (100, 100));
+        assert_close([inset[0], inset[1]], [0.105, 0.205]);
+        assert_close([inset[2], inset[3]], [0.09, 0.19]);
+        // Flipped horizontally, the way Animation faces left: x starts at the right edge, width is negative
+        let flipped = inset_half_texel([0.2, 0.2, -0.1, 0.2], (100, 100));
+        assert_close([flipped[0], flipped[2]], [0.195, -0.09]);
+        // Empty regions (the hidden hook) stay empty
+        assert_eq!(inset_half_texel([0.3, 0.3, 0.0, 0.0], (100, 100)), [0.3, 0.3, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn layout_matches_shader() {
+        // to_rect, from_rect, tint, then z/rotation/pivot packed into one 16 byte row
+        assert_eq!(std::mem::size_of::<GPUSprite>(), 64);
+    }
+}
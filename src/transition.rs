@@ -0,0 +1,326 @@
+use std::borrow::Cow;
+
+use crate::gpus::WGPU;
+use crate::tween::Ease;
+
+// What a screen change looks like
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum TransitionKind {
+    // Fade the old screen out to a color, then the new one in from it
+    FadeToColor,
+    // Blend straight from one screen to the other
+    Crossfade,
+    // The new screen slides in over the old one from the left
+    Wipe,
+    // A circle closes on the old screen, then opens on the new one
+    Iris,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    // In seconds
+    pub duration: f32,
+    // Only used by fades and irises
+    pub color: [f32; 4],
+}
+
+#[allow(dead_code)]
+impl Transition {
+    pub const fn fade(duration: f32, color: [f32; 4]) -> Self {
+        Self {
+            kind: TransitionKind::FadeToColor,
+            duration,
+            color,
+        }
+    }
+
+    pub const fn crossfade(duration: f32) -> Self {
+        Self {
+            kind: TransitionKind::Crossfade,
+            duration,
+            color: [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+
+    pub const fn wipe(duration: f32) -> Self {
+        Self {
+            kind: TransitionKind::Wipe,
+            duration,
+            color: [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+
+    pub const fn iris(duration: f32) -> Self {
+        Self {
+            kind: TransitionKind::Iris,
+            duration,
+            color: [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+}
+
+// What the shader sees, see the Transition struct in transition.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct TransitionUniform {
+    progress: f32,
+    kind: u32,
+    canvas_size: [f32; 2],
+    color: [f32; 4],
+}
+
+// Changes between screens. Starting one freezes whatever is on the canvas as the old screen; while it
+// runs the game draws the new screen into `scene_view` instead of the canvas, and `apply` blends the
+// two into the canvas. Like the water, it costs nothing when nothing is changing.
+pub struct ScreenTransitions {
+    width: u32,
+    height: u32,
+    outgoing: wgpu::Texture,
+    // Kept alive for scene_view
+    #[allow(dead_code)]
+    incoming: wgpu::Texture,
+    incoming_view: wgpu::TextureView,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    // Kept so the pipeline can be rebuilt when the shader changes
+    pipeline_layout: wgpu::PipelineLayout,
+    current: Option<Transition>,
+    elapsed: f32,
+}
+
+#[allow(dead_code)]
+impl ScreenTransitions {
+    pub(crate) fn new(gpu: &WGPU, width: u32, height: u32) -> Self {
+        let screen_texture = |label| {
+            gpu.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                // Same format as the canvas, so it can be copied from and drawn into the same way
+                format: gpu.config.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            })
+        };
+        let outgoing = screen_texture("outgoing screen");
+        let incoming = screen_texture("incoming screen");
+        let outgoing_view = outgoing.create_view(&wgpu::TextureViewDescriptor::default());
+        let incoming_view = incoming.create_view(&wgpu::TextureViewDescriptor::default());
+        // Both screens are canvas-sized, so every pixel lines up and nearest is exact
+        let sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("screen transition"),
+            ..Default::default()
+        });
+        let uniform_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("screen transition"),
+            size: std::mem::size_of::<TransitionUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let screen_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("screen transition"),
+            entries: &[
+                // How far along it is and what kind it is, see TransitionUniform
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // The old screen and the new one...
+                screen_entry(1),
+                screen_entry(2),
+                // ...and how to sample them
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("screen transition"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&outgoing_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&incoming_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+        let pipeline_layout = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("screen transition"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = Self::create_pipeline(gpu, &pipeline_layout, include_str!("transition.wgsl"));
+
+        Self {
+            width,
+            height,
+            outgoing,
+            incoming,
+            incoming_view,
+            uniform_buffer,
+            bind_group,
+            pipeline,
+            pipeline_layout,
+            current: None,
+            elapsed: 0.0,
+        }
+    }
+
+    fn create_pipeline(gpu: &WGPU, pipeline_layout: &wgpu::PipelineLayout, source: &str) -> wgpu::RenderPipeline {
+        let shader = gpu.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("screen transition"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
+        });
+        gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("screen transition"),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                // Every pixel gets replaced, so no blending
+                targets: &[Some(gpu.config.format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    // Like SpriteRenderer::reload_shader: keeps the old pipeline if the new source doesn't compile
+    pub(crate) fn reload_shader(&mut self, gpu: &WGPU, source: &str) -> Result<(), String> {
+        self.pipeline = gpu.validated(|| Self::create_pipeline(gpu, &self.pipeline_layout, source))?;
+        Ok(())
+    }
+
+    // Start changing screens. `outgoing` should still hold the old screen, so call this after the
+    // last frame of it was drawn and before the first frame of the new one. Starting another
+    // transition halfway through one just starts from whatever the canvas shows.
+    pub(crate) fn start(&mut self, gpu: &WGPU, transition: Transition, outgoing: &wgpu::Texture) {
+        let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("screen transition"),
+        });
+        encoder.copy_texture_to_texture(
+            outgoing.as_image_copy(),
+            self.outgoing.as_image_copy(),
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        gpu.queue.submit(Some(encoder.finish()));
+        self.current = Some(transition);
+        self.elapsed = 0.0;
+    }
+
+    // While this is true, draw into `scene_view` and then call `apply`; the game should also ignore
+    // input until it's over
+    pub fn is_running(&self) -> bool {
+        self.current.is_some()
+    }
+
+    // Where the new screen goes while a transition is running
+    pub(crate) fn scene_view(&self) -> &wgpu::TextureView {
+        &self.incoming_view
+    }
+
+    // 0 at the start, 1 at the end, eased so it doesn't start or stop abruptly
+    pub fn progress(&self) -> f32 {
+        match self.current {
+            Some(transition) if transition.duration > 0.0 => {
+                Ease::QuadInOut.apply(self.elapsed / transition.duration)
+            }
+            _ => 1.0,
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if let Some(transition) = self.current {
+            self.elapsed += dt;
+            if self.elapsed >= transition.duration {
+                self.current = None;
+            }
+        }
+    }
+
+    // Blend the old screen and the new one into `target`, which should be the canvas
+    pub(crate) fn apply(&self, gpu: &WGPU, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView) {
+        let Some(transition) = self.current else { return };
+        let uniform = TransitionUniform {
+            progress: self.progress(),
+            kind: match transition.kind {
+                TransitionKind::FadeToColor => 0,
+                TransitionKind::Crossfade => 1,
+                TransitionKind::Wipe => 2,
+                TransitionKind::Iris => 3,
+            },
+            canvas_size: [self.width as f32, self.height as f32],
+            color: transition.color,
+        };
+        gpu.queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("screen transition"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
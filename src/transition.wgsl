@@ -0,0 +1,76 @@
+// Screen transitions: blends the last frame of the old screen into the new one as `progress` goes
+// from 0 to 1.
+
+// Matches TransitionUniform in transition.rs
+struct Transition {
+    progress: f32,
+    // 0 = fade through a color, 1 = crossfade, 2 = wipe, 3 = iris
+    kind: u32,
+    canvas_size: vec2<f32>,
+    // What fades and irises go through
+    color: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> transition: Transition;
+// The old screen, frozen as it was...
+@group(0) @binding(1)
+var t_from: texture_2d<f32>;
+// ...and the new one, drawn fresh every frame
+@group(0) @binding(2)
+var t_to: texture_2d<f32>;
+@group(0) @binding(3)
+var s_screen: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // 0,0 at the top left, like texture coordinates
+    @location(0) uv: vec2<f32>,
+}
+
+// One triangle that covers the whole canvas
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    let corner:vec2<f32> = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    return VertexOutput(
+        vec4(corner * 2.0 - 1.0, 0.0, 1.0),
+        vec2(corner.x, 1.0 - corner.y)
+    );
+}
+
+// How many pixels wide the soft edge of a wipe or iris is
+const EDGE:f32 = 3.0;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let outgoing:vec4<f32> = textureSample(t_from, s_screen, in.uv);
+    let incoming:vec4<f32> = textureSample(t_to, s_screen, in.uv);
+    let p:f32 = clamp(transition.progress, 0.0, 1.0);
+    let pixel:vec2<f32> = in.uv * transition.canvas_size;
+    switch transition.kind {
+        // Fade out to the color, then in from it
+        case 0u: {
+            if p < 0.5 {
+                return mix(outgoing, transition.color, p * 2.0);
+            }
+            return mix(transition.color, incoming, p * 2.0 - 1.0);
+        }
+        case 1u: {
+            return mix(outgoing, incoming, p);
+        }
+        // The new screen comes in from the left behind a soft edge
+        case 2u: {
+            let edge:f32 = p * (transition.canvas_size.x + EDGE * 2.0) - EDGE;
+            return mix(outgoing, incoming, 1.0 - smoothstep(edge - EDGE, edge + EDGE, pixel.x));
+        }
+        // A circle closes in on the old screen, then opens up on the new one
+        default: {
+            let distance:f32 = length(pixel - transition.canvas_size / 2.0);
+            let widest:f32 = length(transition.canvas_size / 2.0) + EDGE;
+            let radius:f32 = abs(p * 2.0 - 1.0) * widest;
+            let inside:f32 = 1.0 - smoothstep(radius - EDGE, radius + EDGE, distance);
+            let screen:vec4<f32> = select(incoming, outgoing, p < 0.5);
+            return mix(transition.color, screen, inside);
+        }
+    }
+}
@@ -0,0 +1,472 @@
+// Smooth changes over time, instead of moving things a fixed step every frame.
+//
+// A tween animates a value (an f32 or an [f32; N]) from one number to another over some seconds,
+// following an easing curve. It writes into a target through a setter, so one tween can drive any
+// field of any struct: `Tween::field(|hook: &mut Char_action, y| hook.screen_region[1] = y, ...)`.
+// Tweens can be put one after another in a `Sequence` or run side by side in a `Parallel`, and
+// `Tweens` plays any number of them on one target.
+
+use std::f32::consts::PI;
+
+// Anything that can be blended between two values
+pub trait Lerp: Copy {
+    fn lerp(self, to: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(self, to: Self, t: f32) -> Self {
+        self + (to - self) * t
+    }
+}
+
+impl<const N: usize> Lerp for [f32; N] {
+    fn lerp(self, to: Self, t: f32) -> Self {
+        std::array::from_fn(|i| self[i].lerp(to[i], t))
+    }
+}
+
+// The shape of the motion. "In" curves start slow, "Out" curves end slow, "InOut" do both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ease {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicOut,
+    SineIn,
+    // Overshoot a little before settling
+    BackOut,
+    // Hit the end and bounce, like something dropped
+    BounceOut,
+}
+
+impl Ease {
+    // Map how far through the tween we are (0 to 1) to how far along the value is. Always 0 at the
+    // start and 1 at the end, but BackOut goes past 1 in between.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        // How far BackOut overshoots; this is the usual value
+        const BACK: f32 = 1.70158;
+        match self {
+            Ease::Linear => t,
+            Ease::QuadIn => t * t,
+            Ease::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Ease::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+            Ease::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Ease::SineIn => 1.0 - (t * PI / 2.0).cos(),
+            Ease::BackOut => 1.0 + (BACK + 1.0) * (t - 1.0).powi(3) + BACK * (t - 1.0).powi(2),
+            Ease::BounceOut => {
+                // Four parabolas, each bounce smaller than the last
+                const N: f32 = 7.5625;
+                const D: f32 = 2.75;
+                if t < 1.0 / D {
+                    N * t * t
+                } else if t < 2.0 / D {
+                    let t = t - 1.5 / D;
+                    N * t * t + 0.75
+                } else if t < 2.5 / D {
+                    let t = t - 2.25 / D;
+                    N * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / D;
+                    N * t * t + 0.984375
+                }
+            }
+        }
+    }
+}
+
+// Anything that plays out over time and writes into a `T`
+pub trait Animator<T> {
+    // Move on by `dt` seconds and write the new values into `target`. Returns how much of `dt` was
+    // left over after finishing (0 if it's still going), so whatever comes next in a sequence starts
+    // exactly on time instead of a frame late.
+    fn advance(&mut self, target: &mut T, dt: f32) -> f32;
+    fn is_done(&self) -> bool;
+}
+
+type Setter<T, V> = Box<dyn Fn(&mut T, V)>;
+type Callback<T> = Box<dyn FnMut(&mut T)>;
+
+// One value going from `from` to `to`
+pub struct Tween<T, V> {
+    from: V,
+    to: V,
+    duration: f32,
+    ease: Ease,
+    delay: f32,
+    // Play back to `from` after reaching `to`; the way back counts as part of the same play
+    yoyo: bool,
+    elapsed: f32,
+    done: bool,
+    set: Setter<T, V>,
+}
+
+impl<V: Lerp + 'static> Tween<V, V> {
+    // A tween that animates the target value itself, e.g. a `Tweens<f32>` for one number
+    pub fn new(from: V, to: V, duration: f32) -> Self {
+        Self::field(|target: &mut V, value| *target = value, from, to, duration)
+    }
+}
+
+impl<T, V: Lerp> Tween<T, V> {
+    // A tween that writes into one part of the target through `set`
+    pub fn field(set: impl Fn(&mut T, V) + 'static, from: V, to: V, duration: f32) -> Self {
+        Self {
+            from,
+            to,
+            duration: duration.max(0.0),
+            ease: Ease::Linear,
+            delay: 0.0,
+            yoyo: false,
+            elapsed: 0.0,
+            done: false,
+            set: Box::new(set),
+        }
+    }
+
+    pub fn ease(mut self, ease: Ease) -> Self {
+        self.ease = ease;
+        self
+    }
+
+    // Wait this long before starting. The target holds `from` while it waits.
+    pub fn delay(mut self, seconds: f32) -> Self {
+        self.delay = seconds.max(0.0);
+        self
+    }
+
+    pub fn yoyo(mut self) -> Self {
+        self.yoyo = true;
+        self
+    }
+
+    // The whole thing from the start of the delay, there and (for yoyo) back
+    fn total_length(&self) -> f32 {
+        if self.yoyo {
+            self.delay + self.duration * 2.0
+        } else {
+            self.delay + self.duration
+        }
+    }
+
+    fn value_at(&self, elapsed: f32) -> V {
+        let time = elapsed - self.delay;
+        if time <= 0.0 {
+            return self.from;
+        }
+        if self.duration <= 0.0 {
+            return if self.yoyo { self.from } else { self.to };
+        }
+        let t = if time > self.duration {
+            2.0 - time / self.duration
+        } else {
+            time / self.duration
+        };
+        if t >= 1.0 {
+            self.to
+        } else if t <= 0.0 {
+            self.from
+        } else {
+            self.from.lerp(self.to, self.ease.apply(t))
+        }
+    }
+}
+
+impl<T, V: Lerp> Animator<T> for Tween<T, V> {
+    fn advance(&mut self, target: &mut T, dt: f32) -> f32 {
+        if self.done {
+            return dt;
+        }
+        self.elapsed += dt;
+        let mut leftover = 0.0;
+        let total = self.total_length();
+        if self.elapsed >= total {
+            leftover = self.elapsed - total;
+            self.elapsed = total;
+            self.done = true;
+        }
+        (self.set)(target, self.value_at(self.elapsed));
+        leftover
+    }
+
+    fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+// Do nothing for a while; handy between the steps of a sequence
+pub struct Wait {
+    duration: f32,
+    elapsed: f32,
+}
+
+impl Wait {
+    pub fn new(seconds: f32) -> Self {
+        Self {
+            duration: seconds.max(0.0),
+            elapsed: 0.0,
+        }
+    }
+}
+
+impl<T> Animator<T> for Wait {
+    fn advance(&mut self, _target: &mut T, dt: f32) -> f32 {
+        self.elapsed += dt;
+        let leftover = (self.elapsed - self.duration).max(0.0);
+        self.elapsed = self.elapsed.min(self.duration);
+        leftover
+    }
+
+    fn is_done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+// Steps played one after another
+pub struct Sequence<T> {
+    steps: Vec<Box<dyn Animator<T>>>,
+    current: usize,
+    done: bool,
+    on_complete: Option<Callback<T>>,
+}
+
+impl<T> Default for Sequence<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Sequence<T> {
+    pub fn new() -> Self {
+        Self {
+            steps: Vec::new(),
+            current: 0,
+            done: false,
+            on_complete: None,
+        }
+    }
+
+    pub fn then(mut self, step: impl Animator<T> + 'static) -> Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+
+    pub fn on_complete(mut self, callback: impl FnMut(&mut T) + 'static) -> Self {
+        self.on_complete = Some(Box::new(callback));
+        self
+    }
+}
+
+impl<T> Animator<T> for Sequence<T> {
+    fn advance(&mut self, target: &mut T, mut dt: f32) -> f32 {
+        if self.done {
+            return dt;
+        }
+        // Time left over from one step goes straight into the next
+        while let Some(step) = self.steps.get_mut(self.current) {
+            dt = step.advance(target, dt);
+            if !step.is_done() {
+                return 0.0;
+            }
+            self.current += 1;
+        }
+        self.done = true;
+        if let Some(callback) = &mut self.on_complete {
+            callback(target);
+        }
+        dt
+    }
+
+    fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+// Steps played at the same time; done when the longest one is
+pub struct Parallel<T> {
+    steps: Vec<Box<dyn Animator<T>>>,
+    done: bool,
+}
+
+impl<T> Default for Parallel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Parallel<T> {
+    pub fn new() -> Self {
+        Self {
+            steps: Vec::new(),
+            done: false,
+        }
+    }
+
+    pub fn with(mut self, step: impl Animator<T> + 'static) -> Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+}
+
+impl<T> Animator<T> for Parallel<T> {
+    fn advance(&mut self, target: &mut T, dt: f32) -> f32 {
+        if self.done {
+            return dt;
+        }
+        // What's left over is however long ago the last step to finish finished
+        let mut leftover = dt;
+        for step in self.steps.iter_mut().filter(|step| !step.is_done()) {
+            leftover = leftover.min(step.advance(target, dt));
+        }
+        if !self.steps.iter().all(|step| step.is_done()) {
+            return 0.0;
+        }
+        self.done = true;
+        leftover
+    }
+
+    fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+// Everything currently playing on one target. Finished animations are dropped as they end.
+pub struct Tweens<T> {
+    playing: Vec<Box<dyn Animator<T>>>,
+}
+
+impl<T> Default for Tweens<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Tweens<T> {
+    pub fn new() -> Self {
+        Self { playing: Vec::new() }
+    }
+
+    // Start an animation alongside whatever's already playing
+    pub fn play(&mut self, animation: impl Animator<T> + 'static) {
+        self.playing.push(Box::new(animation));
+    }
+
+    // Stop everything and start this instead
+    pub fn replace(&mut self, animation: impl Animator<T> + 'static) {
+        self.stop();
+        self.play(animation);
+    }
+
+    // Stop everything where it is; completion callbacks don't get called
+    pub fn stop(&mut self) {
+        self.playing.clear();
+    }
+
+    pub fn is_playing(&self) -> bool {
+        !self.playing.is_empty()
+    }
+
+    pub fn update(&mut self, target: &mut T, dt: f32) {
+        for animation in self.playing.iter_mut() {
+            animation.advance(target, dt);
+        }
+        self.playing.retain(|animation| !animation.is_done());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn every_ease_starts_at_0_and_ends_at_1() {
+        use Ease::*;
+        for ease in [Linear, QuadIn, QuadOut, QuadInOut, CubicOut, SineIn, BackOut, BounceOut] {
+            assert_close(ease.apply(0.0), 0.0);
+            assert_close(ease.apply(1.0), 1.0);
+        }
+        // BackOut overshoots on the way
+        assert!(Ease::BackOut.apply(0.7) > 1.0);
+    }
+
+    #[test]
+    fn tweens_write_arrays_and_stop_at_the_end() {
+        let mut value = [0.0; 2];
+        let mut tween = Tween::new([0.0, 10.0], [10.0, 30.0], 2.0);
+        assert_eq!(tween.advance(&mut value, 1.0), 0.0);
+        assert_eq!(value, [5.0, 20.0]);
+        // Half a second too long
+        assert_close(tween.advance(&mut value, 1.5), 0.5);
+        assert_eq!(value, [10.0, 30.0]);
+        assert!(tween.is_done());
+    }
+
+    #[test]
+    fn yoyo_comes_back_after_a_delay() {
+        let mut value = 1.0;
+        let mut tween = Tween::new(0.0, 10.0, 1.0).delay(0.5).yoyo();
+        tween.advance(&mut value, 0.25);
+        assert_eq!(value, 0.0);
+        tween.advance(&mut value, 1.0);
+        assert_close(value, 7.5);
+        tween.advance(&mut value, 1.25);
+        assert_close(value, 0.0);
+        assert!(tween.is_done());
+    }
+
+    #[test]
+    fn sequences_carry_leftover_time_into_the_next_step() {
+        let mut value = [0.0; 2];
+        let mut sequence = Sequence::new()
+            .then(Tween::field(|v: &mut [f32; 2], x| v[0] = x, 0.0, 1.0, 1.0))
+            .then(Wait::new(0.5))
+            .then(Tween::field(|v: &mut [f32; 2], y| v[1] = y, 0.0, 4.0, 1.0));
+        sequence.advance(&mut value, 1.75);
+        assert_close(value[0], 1.0);
+        assert_close(value[1], 1.0);
+        assert!(!sequence.is_done());
+        assert_close(sequence.advance(&mut value, 1.0), 0.25);
+        assert!(sequence.is_done());
+    }
+
+    #[test]
+    fn parallel_finishes_with_its_longest_step_and_calls_back_once() {
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let mut value = [0.0; 2];
+        let mut tweens = Tweens::new();
+        tweens.play(
+            Sequence::new()
+                .then(
+                    Parallel::new()
+                        .with(Tween::field(|v: &mut [f32; 2], x| v[0] = x, 0.0, 1.0, 1.0))
+                        .with(Tween::field(|v: &mut [f32; 2], y| v[1] = y, 0.0, 1.0, 2.0)),
+                )
+                .on_complete(move |v| {
+                    counter.set(counter.get() + 1);
+                    v[0] = -1.0;
+                }),
+        );
+        tweens.update(&mut value, 1.5);
+        assert_eq!(value[0], 1.0);
+        assert!(tweens.is_playing());
+        tweens.update(&mut value, 1.0);
+        tweens.update(&mut value, 1.0);
+        assert_eq!(value, [-1.0, 1.0]);
+        assert_eq!(calls.get(), 1);
+        assert!(!tweens.is_playing());
+    }
+}
@@ -0,0 +1,550 @@
+// A small retained-mode UI: build the widgets once, then each frame `update` pulls fresh values out of
+// a model (the GameState, say) and `sync` puts the result on screen. Text only gets re-shaped when
+// the value it's bound to actually changes, so a HUD that shows the score doesn't lay out "Score: 3"
+// sixty times a second.
+//
+// Positions are in canvas pixels from the top left, like glyphon and the mouse; the sprites for panels,
+// bars and icons get flipped into the sprite renderer's y-up coordinates in `sync`.
+
+use glyphon::{Attrs, Buffer, Color, Family, FontSystem, Metrics, Shaping, TextArea, TextBounds};
+use winit::event::MouseButton;
+
+use crate::assets::{AssetManager, TextureHandle};
+use crate::camera::GPUCamera;
+use crate::gpus::{TextureOptions, WGPU};
+use crate::input::Input;
+use crate::sprite_game::{GPUSprite, Layer, SpriteRenderer};
+
+// Which point of its parent (the canvas, unless it has one) a widget is pinned to. The same point of
+// the widget goes there, so TopRight puts the widget's top right corner in the parent's top right corner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    // How far across and down the anchor point is, as fractions
+    fn fraction(self) -> [f32; 2] {
+        match self {
+            Anchor::TopLeft => [0.0, 0.0],
+            Anchor::Top => [0.5, 0.0],
+            Anchor::TopRight => [1.0, 0.0],
+            Anchor::Left => [0.0, 0.5],
+            Anchor::Center => [0.5, 0.5],
+            Anchor::Right => [1.0, 0.5],
+            Anchor::BottomLeft => [0.0, 1.0],
+            Anchor::Bottom => [0.5, 1.0],
+            Anchor::BottomRight => [1.0, 1.0],
+        }
+    }
+
+    // Where something `size` big goes inside `parent` ([x, y, w, h]), then moved by `offset`.
+    // Offsets are always right and down, so a margin from a right or bottom edge is negative.
+    pub fn place(self, size: [f32; 2], offset: [f32; 2], parent: [f32; 4]) -> [f32; 4] {
+        let [fx, fy] = self.fraction();
+        [
+            parent[0] + (parent[2] - size[0]) * fx + offset[0],
+            parent[1] + (parent[3] - size[1]) * fy + offset[1],
+            size[0],
+            size[1],
+        ]
+    }
+}
+
+// Text that follows a value in the model. Returns the new text when the value changed since last
+// time, and None when it didn't.
+pub struct Binding<M> {
+    refresh: Box<dyn FnMut(&M) -> Option<String>>,
+}
+
+// Bind text to part of the model: `get` picks the value out and `format` turns it into text, but only
+// when it's different from last frame's
+pub fn bind<M, V: PartialEq + 'static>(get: impl Fn(&M) -> V + 'static, format: impl Fn(&V) -> String + 'static) -> Binding<M> {
+    let mut last: Option<V> = None;
+    Binding {
+        refresh: Box::new(move |model| {
+            let value = get(model);
+            if last.as_ref() == Some(&value) {
+                return None;
+            }
+            let text = format(&value);
+            last = Some(value);
+            Some(text)
+        }),
+    }
+}
+
+pub enum TextSource<M> {
+    Fixed(String),
+    Bound(Binding<M>),
+}
+
+impl<M> From<&str> for TextSource<M> {
+    fn from(text: &str) -> Self {
+        TextSource::Fixed(text.to_string())
+    }
+}
+
+impl<M> From<String> for TextSource<M> {
+    fn from(text: String) -> Self {
+        TextSource::Fixed(text)
+    }
+}
+
+impl<M> From<Binding<M>> for TextSource<M> {
+    fn from(binding: Binding<M>) -> Self {
+        TextSource::Bound(binding)
+    }
+}
+
+enum Kind<M> {
+    Label,
+    Panel {
+        color: [f32; 4],
+    },
+    ProgressBar {
+        value: Box<dyn Fn(&M) -> f32>,
+        back: [f32; 4],
+        fill: [f32; 4],
+    },
+    Button {
+        color: [f32; 4],
+        hover_color: [f32; 4],
+    },
+    IconRow {
+        // Where the icon is on the icon texture, like GPUSprite::sheet_region
+        icon: [f32; 4],
+        icon_size: [f32; 2],
+    },
+}
+
+// Space between an icon and its text, and around a button's text
+const GAP: f32 = 8.0;
+
+// One widget, before it's added to a Ui
+pub struct Widget<M> {
+    kind: Kind<M>,
+    text: Option<TextSource<M>>,
+    anchor: Anchor,
+    offset: [f32; 2],
+    // None fits the widget to its text
+    size: Option<[f32; 2]>,
+    parent: Option<WidgetId>,
+    font_size: f32,
+    line_height: f32,
+    text_color: [f32; 4],
+}
+
+#[allow(dead_code)]
+impl<M> Widget<M> {
+    fn new(kind: Kind<M>, text: Option<TextSource<M>>, size: Option<[f32; 2]>) -> Self {
+        Self {
+            kind,
+            text,
+            anchor: Anchor::TopLeft,
+            offset: [0.0, 0.0],
+            size,
+            parent: None,
+            font_size: 28.0,
+            line_height: 32.0,
+            text_color: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+
+    pub fn label(text: impl Into<TextSource<M>>) -> Self {
+        Self::new(Kind::Label, Some(text.into()), None)
+    }
+
+    // A plain colored rectangle, mostly to put other widgets on
+    pub fn panel(size: [f32; 2], color: [f32; 4]) -> Self {
+        Self::new(Kind::Panel { color }, None, Some(size))
+    }
+
+    // A bar filled `value(model)` of the way across; the value is clamped to 0..1
+    pub fn progress_bar(size: [f32; 2], value: impl Fn(&M) -> f32 + 'static, back: [f32; 4], fill: [f32; 4]) -> Self {
+        Self::new(
+            Kind::ProgressBar {
+                value: Box::new(value),
+                back,
+                fill,
+            },
+            None,
+            Some(size),
+        )
+    }
+
+    // Clickable; check Ui::clicked after Ui::update
+    pub fn button(text: impl Into<TextSource<M>>, size: [f32; 2]) -> Self {
+        Self::new(
+            Kind::Button {
+                color: [0.1, 0.2, 0.35, 0.8],
+                hover_color: [0.2, 0.4, 0.65, 0.9],
+            },
+            Some(text.into()),
+            Some(size),
+        )
+    }
+
+    // An icon from the Ui's icon texture with text next to it
+    pub fn icon_row(icon: [f32; 4], icon_size: [f32; 2], text: impl Into<TextSource<M>>) -> Self {
+        Self::new(Kind::IconRow { icon, icon_size }, Some(text.into()), None)
+    }
+
+    pub fn anchor(mut self, anchor: Anchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    pub fn offset(mut self, offset: [f32; 2]) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    // Lay this out inside another widget instead of the whole canvas
+    pub fn parent(mut self, parent: WidgetId) -> Self {
+        self.parent = Some(parent);
+        self
+    }
+
+    pub fn font(mut self, size: f32, line_height: f32) -> Self {
+        self.font_size = size;
+        self.line_height = line_height;
+        self
+    }
+
+    pub fn text_color(mut self, color: [f32; 4]) -> Self {
+        self.text_color = color;
+        self
+    }
+
+    pub fn colors(mut self, color: [f32; 4], hover_color: [f32; 4]) -> Self {
+        if let Kind::Button {
+            color: c,
+            hover_color: h,
+        } = &mut self.kind
+        {
+            *c = color;
+            *h = hover_color;
+        }
+        self
+    }
+
+    // How many plain rectangles it draws
+    fn shape_count(&self) -> usize {
+        match self.kind {
+            Kind::Panel { .. } | Kind::Button { .. } => 1,
+            Kind::ProgressBar { .. } => 2,
+            Kind::Label | Kind::IconRow { .. } => 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WidgetId(usize);
+
+struct Node<M> {
+    widget: Widget<M>,
+    buffer: Option<Buffer>,
+    text_size: [f32; 2],
+    // Where it ended up, from the last layout
+    rect: [f32; 4],
+    // Hidden by the game; `visible` also counts hidden parents
+    shown: bool,
+    visible: bool,
+    fraction: f32,
+    hovered: bool,
+    // Picked with the keyboard; draws like hovered
+    focused: bool,
+    clicked: bool,
+}
+
+impl<M> Node<M> {
+    fn set_text(&mut self, font_system: &mut FontSystem, text: &str) {
+        let Some(buffer) = &mut self.buffer else { return };
+        buffer.set_text(font_system, text, Attrs::new().family(Family::SansSerif), Shaping::Advanced);
+        buffer.shape_until_scroll(font_system);
+        let width = buffer.layout_runs().map(|run| run.line_w).fold(0.0, f32::max);
+        let lines = buffer.layout_runs().count();
+        self.text_size = [width, lines as f32 * buffer.metrics().line_height];
+    }
+
+    fn size(&self) -> [f32; 2] {
+        if let Some(size) = self.widget.size {
+            return size;
+        }
+        let [text_w, text_h] = self.text_size;
+        match self.widget.kind {
+            Kind::IconRow { icon_size, .. } => [icon_size[0] + GAP + text_w, icon_size[1].max(text_h)],
+            Kind::Button { .. } => [text_w + GAP * 2.0, text_h + GAP * 2.0],
+            _ => self.text_size,
+        }
+    }
+
+    // Top left of the text
+    fn text_pos(&self) -> [f32; 2] {
+        let [x, y, w, h] = self.rect;
+        let [text_w, text_h] = self.text_size;
+        match self.widget.kind {
+            Kind::Button { .. } => [x + (w - text_w) / 2.0, y + (h - text_h) / 2.0],
+            Kind::IconRow { icon_size, .. } => [x + icon_size[0] + GAP, y + (h - text_h) / 2.0],
+            _ => [x, y],
+        }
+    }
+}
+
+pub struct Ui<M> {
+    size: [f32; 2],
+    nodes: Vec<Node<M>>,
+    visible: bool,
+    // Panels, bars and buttons are tinted white squares; icons come from their own texture
+    shapes_group: Option<usize>,
+    icons_group: Option<usize>,
+}
+
+#[allow(dead_code)]
+impl<M> Ui<M> {
+    // `size` is the canvas size
+    pub fn new(size: [f32; 2]) -> Self {
+        Self {
+            size,
+            nodes: Vec::new(),
+            visible: true,
+            shapes_group: None,
+            icons_group: None,
+        }
+    }
+
+    // Add every widget before add_to_renderer, since that's when the sprites get made.
+    // Parents have to be added before their children.
+    pub fn add(&mut self, font_system: &mut FontSystem, widget: Widget<M>) -> WidgetId {
+        let buffer = widget.text.as_ref().map(|_| {
+            let mut buffer = Buffer::new(font_system, Metrics::new(widget.font_size, widget.line_height));
+            buffer.set_size(font_system, self.size[0], self.size[1]);
+            buffer
+        });
+        let mut node = Node {
+            widget,
+            buffer,
+            text_size: [0.0, 0.0],
+            rect: [0.0, 0.0, 0.0, 0.0],
+            shown: true,
+            visible: true,
+            fraction: 0.0,
+            hovered: false,
+            focused: false,
+            clicked: false,
+        };
+        // Fixed text only ever needs shaping once
+        if let Some(TextSource::Fixed(text)) = &node.widget.text {
+            let text = text.clone();
+            node.set_text(font_system, &text);
+        }
+        self.nodes.push(node);
+        self.layout();
+        WidgetId(self.nodes.len() - 1)
+    }
+
+    pub(crate) fn add_to_renderer(&mut self, gpu: &WGPU, assets: &mut AssetManager, renderer: &mut SpriteRenderer, icons: &TextureHandle) {
+        let camera = GPUCamera {
+            screen_pos: [0.0, 0.0],
+            screen_size: self.size,
+        };
+        let hidden = GPUSprite::new([0.0, 0.0, 0.0, 0.0], [0.0, 0.0, 1.0, 1.0], Layer::Ui.z());
+        let shapes: usize = self.nodes.iter().map(|node| node.widget.shape_count()).sum();
+        if shapes > 0 {
+            let white = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+            let tex = assets.texture_from_image(gpu, "ui white", &white, TextureOptions::DEFAULT);
+            self.shapes_group = Some(renderer.add_sprite_group(gpu, assets, &tex, vec![hidden; shapes], camera));
+        }
+        let icon_rows = self.nodes.iter().filter(|node| matches!(node.widget.kind, Kind::IconRow { .. })).count();
+        if icon_rows > 0 {
+            self.icons_group = Some(renderer.add_sprite_group(gpu, assets, icons, vec![hidden; icon_rows], camera));
+        }
+    }
+
+    // Pull new values out of the model, re-shape any text whose value changed, lay everything out
+    // and see which buttons got clicked
+    pub fn update(&mut self, font_system: &mut FontSystem, model: &M, input: &Input) {
+        for node in self.nodes.iter_mut() {
+            if let Some(TextSource::Bound(binding)) = &mut node.widget.text {
+                if let Some(text) = (binding.refresh)(model) {
+                    node.set_text(font_system, &text);
+                }
+            }
+            if let Kind::ProgressBar { value, .. } = &node.widget.kind {
+                node.fraction = value(model).clamp(0.0, 1.0);
+            }
+        }
+        self.layout();
+        let mouse = input.mouse_pos();
+        let (mouse_x, mouse_y) = (mouse.x as f32, mouse.y as f32);
+        let pressed = input.is_mouse_pressed(MouseButton::Left);
+        for node in self.nodes.iter_mut() {
+            let [x, y, w, h] = node.rect;
+            node.hovered = self.visible
+                && node.visible
+                && matches!(node.widget.kind, Kind::Button { .. })
+                && (x..x + w).contains(&mouse_x)
+                && (y..y + h).contains(&mouse_y);
+            node.clicked = node.hovered && pressed;
+        }
+    }
+
+    // Parents always come before their children, so one pass in order is enough
+    fn layout(&mut self) {
+        let canvas = [0.0, 0.0, self.size[0], self.size[1]];
+        for index in 0..self.nodes.len() {
+            let (parent_rect, parent_visible) = match self.nodes[index].widget.parent {
+                Some(WidgetId(parent)) => (self.nodes[parent].rect, self.nodes[parent].visible),
+                None => (canvas, true),
+            };
+            let node = &mut self.nodes[index];
+            node.rect = node.widget.anchor.place(node.size(), node.widget.offset, parent_rect);
+            node.visible = node.shown && parent_visible;
+        }
+    }
+
+    // Put the panels, bars, buttons and icons where the last update left them
+    pub(crate) fn sync(&self, gpu: &WGPU, renderer: &mut SpriteRenderer) {
+        let height = self.size[1];
+        // Flip a top-left rect into the sprite renderer's bottom-left coordinates
+        let region = |rect: [f32; 4]| [rect[0], height - rect[1] - rect[3], rect[2], rect[3]];
+        if let Some(group) = self.shapes_group {
+            let sprites = renderer.get_sprites_mut(group);
+            let mut next = 0;
+            for (index, node) in self.nodes.iter().enumerate() {
+                // Children draw over their parents
+                let z = Layer::Ui.z_offset(index as f32 * 0.001);
+                let mut rect = node.rect;
+                if !node.visible {
+                    rect[2] = 0.0;
+                    rect[3] = 0.0;
+                }
+                let mut put = |rect: [f32; 4], color: [f32; 4], z: f32| {
+                    sprites[next] = GPUSprite::new(region(rect), [0.0, 0.0, 1.0, 1.0], z).with_tint(color);
+                    next += 1;
+                };
+                match node.widget.kind {
+                    Kind::Panel { color } => put(rect, color, z),
+                    Kind::Button { color, hover_color } => {
+                        put(rect, if node.hovered || node.focused { hover_color } else { color }, z)
+                    }
+                    Kind::ProgressBar { back, fill, .. } => {
+                        put(rect, back, z);
+                        put([rect[0], rect[1], rect[2] * node.fraction, rect[3]], fill, z + 0.0005);
+                    }
+                    Kind::Label | Kind::IconRow { .. } => {}
+                }
+            }
+            renderer.upload_sprites(gpu, group);
+        }
+        if let Some(group) = self.icons_group {
+            let sprites = renderer.get_sprites_mut(group);
+            let icon_rows = self.nodes.iter().enumerate().filter_map(|(index, node)| match node.widget.kind {
+                Kind::IconRow { icon, icon_size } => Some((index, node, icon, icon_size)),
+                _ => None,
+            });
+            for (sprite, (index, node, icon, [icon_w, icon_h])) in sprites.iter_mut().zip(icon_rows) {
+                let [x, y, _, h] = node.rect;
+                let size = if node.visible { [icon_w, icon_h] } else { [0.0, 0.0] };
+                let rect = [x, y + (h - icon_h) / 2.0, size[0], size[1]];
+                *sprite = GPUSprite::new(region(rect), icon, Layer::Ui.z_offset(index as f32 * 0.001));
+            }
+            renderer.upload_sprites(gpu, group);
+        }
+    }
+
+    // Hand these to the text renderer along with any other text
+    pub fn text_areas(&self) -> impl Iterator<Item = TextArea<'_>> {
+        let bounds = TextBounds {
+            left: 0,
+            top: 0,
+            right: self.size[0] as i32,
+            bottom: self.size[1] as i32,
+        };
+        self.nodes
+            .iter()
+            .filter(move |node| self.visible && node.visible)
+            .filter_map(move |node| {
+                let [left, top] = node.text_pos();
+                let [r, g, b, a] = node.widget.text_color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0) as u8);
+                Some(TextArea {
+                    buffer: node.buffer.as_ref()?,
+                    left,
+                    top,
+                    scale: 1.0,
+                    bounds,
+                    default_color: Color::rgba(r, g, b, a),
+                })
+            })
+    }
+
+    pub fn set_visible(&mut self, renderer: &mut SpriteRenderer, visible: bool) {
+        self.visible = visible;
+        for group in [self.shapes_group, self.icons_group].into_iter().flatten() {
+            renderer.set_visible(group, visible);
+        }
+    }
+
+    // Move a widget, e.g. from a tween
+    pub fn set_offset(&mut self, id: WidgetId, offset: [f32; 2]) {
+        self.nodes[id.0].widget.offset = offset;
+    }
+
+    // Hide or show one widget (and everything inside it)
+    pub fn set_shown(&mut self, id: WidgetId, shown: bool) {
+        self.nodes[id.0].shown = shown;
+    }
+
+    // Where a widget is, [x, y, w, h] from the top left of the canvas
+    pub fn rect(&self, id: WidgetId) -> [f32; 4] {
+        self.nodes[id.0].rect
+    }
+
+    // Whether a button was clicked in the last update
+    pub fn clicked(&self, id: WidgetId) -> bool {
+        self.nodes[id.0].clicked
+    }
+
+    // Whether the mouse was over a button in the last update
+    pub fn hovered(&self, id: WidgetId) -> bool {
+        self.nodes[id.0].hovered
+    }
+
+    // Highlight a button as if the mouse were over it, for keyboard menus
+    pub fn set_focused(&mut self, id: WidgetId, focused: bool) {
+        self.nodes[id.0].focused = focused;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anchors_pin_the_matching_corner() {
+        let parent = [100.0, 50.0, 400.0, 300.0];
+        assert_eq!(Anchor::TopLeft.place([40.0, 20.0], [5.0, 5.0], parent), [105.0, 55.0, 40.0, 20.0]);
+        assert_eq!(Anchor::Center.place([40.0, 20.0], [0.0, 0.0], parent), [280.0, 190.0, 40.0, 20.0]);
+        // A margin from the bottom right edges is a negative offset
+        assert_eq!(Anchor::BottomRight.place([40.0, 20.0], [-10.0, -10.0], parent), [450.0, 320.0, 40.0, 20.0]);
+    }
+
+    #[test]
+    fn bindings_only_give_text_when_the_value_changes() {
+        let mut binding = bind(|score: &usize| *score, |score| format!("Score: {}", score));
+        assert_eq!((binding.refresh)(&0), Some("Score: 0".to_string()));
+        assert_eq!((binding.refresh)(&0), None);
+        assert_eq!((binding.refresh)(&3), Some("Score: 3".to_string()));
+        assert_eq!((binding.refresh)(&3), None);
+    }
+}
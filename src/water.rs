@@ -0,0 +1,277 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::gpus::WGPU;
+
+// How the water looks. Distances are in canvas pixels, with y going up from the bottom. Read from the
+// [water] table in settings.toml; anything left out keeps its default.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WaterConfig {
+    // Where the surface is when it's still
+    pub waterline: f32,
+    // How far the surface bobs up and down
+    pub wave_amplitude: f32,
+    pub wave_length: f32,
+    // Radians per second
+    pub wave_speed: f32,
+    // How far things under water get pushed around, 0 for none
+    pub refraction: f32,
+    // The bright edge along the surface; alpha is how strong it is
+    pub surface_color: [f32; 4],
+    // What the bottom of the canvas fades towards; alpha is how strong it gets
+    pub deep_tint: [f32; 4],
+}
+
+impl Default for WaterConfig {
+    // Tuned to background.png: the water starts just under the sand
+    fn default() -> Self {
+        Self {
+            waterline: 580.0,
+            wave_amplitude: 3.0,
+            wave_length: 180.0,
+            wave_speed: 1.5,
+            refraction: 2.5,
+            surface_color: [0.85, 0.95, 1.0, 0.6],
+            deep_tint: [0.02, 0.08, 0.25, 0.55],
+        }
+    }
+}
+
+impl WaterConfig {
+    // Keep a hand-edited config on the canvas and out of NaN territory; anything unusable goes back to
+    // its default
+    pub fn validate(&mut self) {
+        let default = WaterConfig::default();
+        let canvas_height = crate::canvas::LOGICAL_HEIGHT as f32;
+        let fix = |value: f32, min: f32, max: f32, default: f32| if value.is_nan() { default } else { value.clamp(min, max) };
+        self.waterline = fix(self.waterline, 0.0, canvas_height, default.waterline);
+        self.wave_amplitude = fix(self.wave_amplitude, 0.0, 100.0, default.wave_amplitude);
+        // Zero would divide by zero in the shader
+        self.wave_length = fix(self.wave_length, 1.0, 10_000.0, default.wave_length);
+        self.wave_speed = fix(self.wave_speed, -100.0, 100.0, default.wave_speed);
+        self.refraction = fix(self.refraction, 0.0, 100.0, default.refraction);
+        for (channel, default) in self.surface_color.iter_mut().zip(default.surface_color) {
+            *channel = fix(*channel, 0.0, 1.0, default);
+        }
+        for (channel, default) in self.deep_tint.iter_mut().zip(default.deep_tint) {
+            *channel = fix(*channel, 0.0, 1.0, default);
+        }
+    }
+}
+
+// What the shader sees, see the Water struct in water.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct WaterUniform {
+    waterline: f32,
+    wave_amplitude: f32,
+    wave_length: f32,
+    wave_speed: f32,
+    refraction: f32,
+    time: f32,
+    canvas_size: [f32; 2],
+    surface_color: [f32; 4],
+    deep_tint: [f32; 4],
+}
+
+// A post-process for the play screen. The scene is drawn into this effect's own texture instead of
+// the canvas, then `apply` draws it into the canvas with waves, refraction and a depth tint. The HUD
+// goes on afterwards so text stays readable. Turning the effect off just means drawing straight into
+// the canvas and not calling `apply`, so it costs nothing on machines that can't afford it.
+pub struct WaterEffect {
+    pub config: WaterConfig,
+    time: f32,
+    width: u32,
+    height: u32,
+    // Kept alive for scene_view
+    #[allow(dead_code)]
+    scene_texture: wgpu::Texture,
+    scene_view: wgpu::TextureView,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    // Kept so the pipeline can be rebuilt when the shader changes
+    pipeline_layout: wgpu::PipelineLayout,
+}
+
+#[allow(dead_code)]
+impl WaterEffect {
+    pub(crate) fn new(gpu: &WGPU, width: u32, height: u32, config: WaterConfig) -> Self {
+        let scene_texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("water scene"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            // Same format as the canvas, so the sprite pipelines can draw into either
+            format: gpu.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let scene_view = scene_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // Nearest, so the wobble moves whole pixels and the pixel art stays crisp
+        let sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("water scene"),
+            ..Default::default()
+        });
+        let uniform_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("water"),
+            size: std::mem::size_of::<WaterUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let layout = gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("water"),
+            entries: &[
+                // The settings, see WaterUniform
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // The scene...
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // ...and how to sample it
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("water"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&scene_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+        let pipeline_layout = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("water"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = Self::create_pipeline(gpu, &pipeline_layout, include_str!("water.wgsl"));
+
+        Self {
+            config,
+            time: 0.0,
+            width,
+            height,
+            scene_texture,
+            scene_view,
+            uniform_buffer,
+            bind_group,
+            pipeline,
+            pipeline_layout,
+        }
+    }
+
+    fn create_pipeline(gpu: &WGPU, pipeline_layout: &wgpu::PipelineLayout, source: &str) -> wgpu::RenderPipeline {
+        let shader = gpu.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("water"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
+        });
+        gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("water"),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                // Every pixel gets replaced, so no blending
+                targets: &[Some(gpu.config.format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    // Like SpriteRenderer::reload_shader: keeps the old pipeline if the new source doesn't compile
+    pub(crate) fn reload_shader(&mut self, gpu: &WGPU, source: &str) -> Result<(), String> {
+        self.pipeline = gpu.validated(|| Self::create_pipeline(gpu, &self.pipeline_layout, source))?;
+        Ok(())
+    }
+
+    // Draw the scene into this instead of the canvas when the effect is on
+    pub(crate) fn scene_view(&self) -> &wgpu::TextureView {
+        &self.scene_view
+    }
+
+    // Keep the waves moving
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+    }
+
+    // Draw the scene into `target` with the water applied. `target` should be the canvas (or
+    // something else the same size and format).
+    pub(crate) fn apply(&self, gpu: &WGPU, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView) {
+        let config = self.config;
+        let uniform = WaterUniform {
+            waterline: config.waterline,
+            wave_amplitude: config.wave_amplitude,
+            wave_length: config.wave_length.max(1.0),
+            wave_speed: config.wave_speed,
+            refraction: config.refraction,
+            time: self.time,
+            canvas_size: [self.width as f32, self.height as f32],
+            surface_color: config.surface_color,
+            deep_tint: config.deep_tint,
+        };
+        gpu.queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("water"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,80 @@
+// The water post-process: everything below the (wavy) waterline gets wobbled and tinted darker
+// the deeper it is, and the waterline itself gets a bright edge.
+
+// Matches WaterUniform in water.rs
+struct Water {
+    // All in canvas pixels, y going up from the bottom
+    waterline: f32,
+    wave_amplitude: f32,
+    wave_length: f32,
+    // Radians per second
+    wave_speed: f32,
+    // How far (in pixels) things under water get pushed around
+    refraction: f32,
+    time: f32,
+    canvas_size: vec2<f32>,
+    surface_color: vec4<f32>,
+    deep_tint: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> water: Water;
+// The scene as it was drawn, before any water
+@group(0) @binding(1)
+var t_scene: texture_2d<f32>;
+@group(0) @binding(2)
+var s_scene: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // 0,0 at the top left, like texture coordinates
+    @location(0) uv: vec2<f32>,
+}
+
+// One triangle that covers the whole canvas
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    let corner:vec2<f32> = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    return VertexOutput(
+        vec4(corner * 2.0 - 1.0, 0.0, 1.0),
+        vec2(corner.x, 1.0 - corner.y)
+    );
+}
+
+const TAU:f32 = 6.28318530718;
+
+// Two sine waves at different lengths and speeds, so the surface doesn't look like one neat sine
+fn surface_height(x: f32) -> f32 {
+    let phase:f32 = x / water.wave_length * TAU;
+    return water.waterline
+        + water.wave_amplitude * sin(phase + water.time * water.wave_speed)
+        + water.wave_amplitude * 0.4 * sin(phase * 2.7 - water.time * water.wave_speed * 1.3);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel:vec2<f32> = vec2(in.uv.x, 1.0 - in.uv.y) * water.canvas_size;
+    // How far under the surface this pixel is; negative above the water
+    let depth:f32 = surface_height(pixel.x) - pixel.y;
+    if depth < 0.0 {
+        return textureSampleLevel(t_scene, s_scene, in.uv, 0.0);
+    }
+    // Wobble sideways in bands, and a little up and down. The wobble fades in over the first few
+    // pixels so nothing above the surface gets dragged under it.
+    let fade_in:f32 = clamp(depth / 12.0, 0.0, 1.0);
+    let wobble:vec2<f32> = vec2(
+        sin(pixel.y * 0.06 + water.time * 2.0),
+        cos(pixel.x * 0.045 + water.time * 1.6) * 0.5
+    ) * water.refraction * fade_in;
+    // Texture y is flipped compared to pixel y
+    let uv:vec2<f32> = in.uv + vec2(wobble.x, -wobble.y) / water.canvas_size;
+    // (SampleLevel because plain textureSample isn't allowed after the early return above)
+    var color:vec4<f32> = textureSampleLevel(t_scene, s_scene, uv, 0.0);
+    // Darker (or whatever deep_tint is) towards the bottom of the canvas
+    let deepness:f32 = clamp(depth / max(water.waterline, 1.0), 0.0, 1.0);
+    color = vec4(mix(color.rgb, water.deep_tint.rgb, water.deep_tint.a * deepness), color.a);
+    // A soft bright edge along the surface
+    let edge:f32 = 1.0 - smoothstep(0.0, 3.0, depth);
+    color = vec4(mix(color.rgb, water.surface_color.rgb, water.surface_color.a * edge), color.a);
+    return color;
+}